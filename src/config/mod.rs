@@ -1,23 +1,100 @@
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::OnceLock;
 
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub api_keys: ApiKeys,
+    #[serde(default)]
+    pub sycli: Sycli,
+    #[serde(default)]
+    pub defaults: Defaults,
+    #[serde(default)]
+    pub http: Http,
+    /// Friendly names for trackers, keyed by announce host, used by `import --layout
+    /// tracker-name`.
+    #[serde(default)]
+    pub tracker_names: HashMap<String, String>,
+    /// Directories that `move --auto-symlink-dirs` scans for symlinks to update.
+    #[serde(default)]
+    pub symlink_roots: Vec<PathBuf>,
+    /// Subdirectory of `import --symlink-dir` for torrents without a usable announce URL, e.g.
+    /// trackerless torrents that rely on DHT. Defaults to "_dht".
+    pub dht_dir: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 pub struct ApiKeys {
     pub omdb: Option<String>,
 }
 
+/// Defaults for subcommand flags that were not passed explicitly.
+#[derive(Default, Deserialize)]
+pub struct Defaults {
+    /// Strategy for `move`, e.g. "rename".
+    pub move_strategy: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Sycli {
+    /// Path to the sycli binary. If unset, sycli is looked up on `PATH`.
+    pub binary: Option<PathBuf>,
+    /// How many times to retry a sycli command that failed to connect to the daemon.
+    pub retries: u32,
+}
+
+impl Default for Sycli {
+    fn default() -> Self {
+        Sycli {
+            binary: None,
+            retries: 3,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Http {
+    /// Maximum time for an entire HTTP request, including reading the response.
+    pub timeout_secs: u64,
+    /// Maximum time to establish a connection.
+    pub connect_timeout_secs: u64,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Http {
+            timeout_secs: 30,
+            connect_timeout_secs: 10,
+        }
+    }
+}
+
+/// Path of the config file, relative to the working directory.
+pub const CONFIG_PATH: &str = "config.toml";
+
 pub fn config() -> &'static Config {
     static CONFIG: OnceLock<Config> = OnceLock::new();
 
-    CONFIG.get_or_init(|| {
-        toml::from_str::<Config>(
-            &std::fs::read_to_string("config.toml").expect("failed to read config.toml"),
-        )
-        .expect("config.toml contains invalid toml")
+    CONFIG.get_or_init(|| match load() {
+        Ok(config) => config.unwrap_or_default(),
+        Err(err) => panic!("{err:#}"),
     })
 }
+
+/// Reads and parses the config file, returning `None` if it does not exist.
+pub fn load() -> anyhow::Result<Option<Config>> {
+    let contents = match std::fs::read_to_string(CONFIG_PATH) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(anyhow::Error::new(err).context(format!("failed to read {CONFIG_PATH}")));
+        }
+    };
+    let config = toml::from_str(&contents)
+        .map_err(|err| anyhow::anyhow!("{CONFIG_PATH} contains invalid toml: {err}"))?;
+    Ok(Some(config))
+}
@@ -2,8 +2,11 @@ use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use console::Style;
+use indicatif::ProgressBar;
 use thiserror::Error;
 
+use crate::util;
+
 // TODO: Migrate this to a plan-based system.
 pub trait Filesystem {
     fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
@@ -11,6 +14,10 @@ pub trait Filesystem {
     }
 
     fn symlink(&self, original: &Path, link: &Path) -> std::io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        std::fs::rename(from, to)
+    }
 }
 
 struct PosixFilesystem;
@@ -44,6 +51,17 @@ impl Filesystem for DryRunFilesystem {
         );
         Ok(())
     }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let cyan = Style::new().cyan();
+        let magenta = Style::new().magenta();
+        println!(
+            "renaming {} to {}",
+            cyan.apply_to(from.display()),
+            magenta.apply_to(to.display())
+        );
+        Ok(())
+    }
 }
 
 fn get_dry_run_instance() -> Box<dyn Filesystem> {
@@ -70,17 +88,49 @@ pub enum CollectFilesError {
     NoFiles,
 }
 
+/// Controls how `collect_files` treats entries that are not regular files. The default rejects
+/// them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CollectOptions {
+    /// Skip entries that are neither files nor directories with a warning, instead of failing.
+    pub skip_non_files: bool,
+    /// Follow symlinks and collect the entries they point to under the symlink's path.
+    pub follow_symlinks: bool,
+    /// Only descend this many directories below the starting path, where 1 only collects the
+    /// entries directly in it. Unlimited if unset.
+    pub max_depth: Option<usize>,
+    /// Skip files and directories that cannot be read due to missing permissions with a warning,
+    /// leaving out everything under them, instead of failing.
+    pub skip_unreadable: bool,
+}
+
 /// Walks `path` and returns a `HashMap` of file paths to file sizes in that directory tree. Any
 /// subdirectories are not included in the returned map.
 ///
 /// If `path` is a file, returns a map with a single entry of `path` and its size.
-/// If `path` contains any non-directory and non-file entries, returns an error.
-pub fn collect_files(path: &Path) -> Result<HashMap<PathBuf, u64>, CollectFilesError> {
+/// If `path` contains any non-directory and non-file entries, returns an error, unless `options`
+/// says to skip them.
+///
+/// If `bar` is provided, its position is advanced as each file is found.
+pub fn collect_files(
+    path: &Path,
+    options: &CollectOptions,
+    bar: Option<&ProgressBar>,
+) -> Result<HashMap<PathBuf, u64>, CollectFilesError> {
     type Error = CollectFilesError;
 
     let mut files = HashMap::new();
-    for entry in walkdir::WalkDir::new(path) {
-        let entry = entry?;
+    let walk = walkdir::WalkDir::new(path)
+        .follow_links(options.follow_symlinks)
+        .max_depth(options.max_depth.unwrap_or(usize::MAX));
+    for entry in walk {
+        let entry = match entry {
+            Err(err) if options.skip_unreadable && is_permission_denied(&err) => {
+                util::warning!("skipping unreadable path: {err}");
+                continue;
+            }
+            result => result?,
+        };
 
         if entry.file_type().is_dir() {
             // Do not include directories in the result, as torrents only contain files.
@@ -91,16 +141,30 @@ pub fn collect_files(path: &Path) -> Result<HashMap<PathBuf, u64>, CollectFilesE
         // normal and expected (though completely ignored by the torrent format), while
         // anything else is unexpected and probably needs the user to decide what to do.
         if !entry.file_type().is_file() {
+            if options.skip_non_files {
+                util::warning!("skipping non-file path {}", entry.path().display());
+                continue;
+            }
             return Err(Error::NonFilePath(
                 path.to_path_buf(),
                 entry.path().to_path_buf(),
             ));
         }
 
+        let metadata = match entry.metadata() {
+            Err(err) if options.skip_unreadable && is_permission_denied(&err) => {
+                util::warning!("skipping unreadable path: {err}");
+                continue;
+            }
+            result => result?,
+        };
         // TODO: Perhaps this should just panic?
-        if let Some(_old_value) = files.insert(entry.path().into(), entry.metadata()?.len()) {
+        if let Some(_old_value) = files.insert(entry.path().into(), metadata.len()) {
             return Err(Error::DuplicateEntry(entry.path().to_path_buf()));
         }
+        if let Some(bar) = bar {
+            bar.inc(1);
+        }
     }
     if files.is_empty() {
         Err(Error::NoFiles)
@@ -109,6 +173,23 @@ pub fn collect_files(path: &Path) -> Result<HashMap<PathBuf, u64>, CollectFilesE
     }
 }
 
+/// Like `collect_files`, but shows a spinner with the number of files found so far.
+pub fn collect_files_with_spinner(
+    path: &Path,
+    options: &CollectOptions,
+) -> Result<HashMap<PathBuf, u64>, CollectFilesError> {
+    let bar = util::new_progress_spinner();
+    bar.set_message(format!("collecting files in {}", path.display()));
+    let files = collect_files(path, options, Some(&bar))?;
+    bar.finish_with_message(format!("collected {} files", files.len()));
+    Ok(files)
+}
+
+fn is_permission_denied(err: &walkdir::Error) -> bool {
+    err.io_error()
+        .is_some_and(|err| err.kind() == std::io::ErrorKind::PermissionDenied)
+}
+
 #[derive(Debug, Error)]
 pub enum CollectSymlinksError {
     #[error("WalkDir failed")]
@@ -118,7 +199,14 @@ pub enum CollectSymlinksError {
 }
 
 /// Returns a map of symlinks found in `path` to their corresponding target path.
-pub fn collect_symlinks(path: &Path) -> Result<HashMap<PathBuf, PathBuf>, CollectSymlinksError> {
+///
+/// If `resolve_targets` is false, targets are returned exactly as stored in the symlink, which
+/// may be relative to the symlink's directory. Otherwise, targets are converted to normalized
+/// absolute paths without following any further symlinks.
+pub fn collect_symlinks(
+    path: &Path,
+    resolve_targets: bool,
+) -> Result<HashMap<PathBuf, PathBuf>, CollectSymlinksError> {
     walkdir::WalkDir::new(path)
         .into_iter()
         .filter_map(|entry| -> Option<Result<(_, _), CollectSymlinksError>> {
@@ -127,6 +215,12 @@ pub fn collect_symlinks(path: &Path) -> Result<HashMap<PathBuf, PathBuf>, Collec
                     if entry.path_is_symlink() {
                         let link_path = entry.into_path();
                         let target_path = match std::fs::read_link(&link_path) {
+                            Ok(target_path) if resolve_targets => {
+                                match resolve_symlink_target(&link_path, &target_path) {
+                                    Ok(target_path) => target_path,
+                                    Err(e) => return Some(Err(e.into())),
+                                }
+                            }
                             Ok(target_path) => target_path,
                             Err(e) => return Some(Err(e.into())),
                         };
@@ -141,15 +235,68 @@ pub fn collect_symlinks(path: &Path) -> Result<HashMap<PathBuf, PathBuf>, Collec
         .collect()
 }
 
-/// Unlike `ln -sfn`, this does not try to be clever and preserve state on failure. The underlying
-/// implementation deletes the original and creates a new symlink if `link` already exists.
+/// Resolves `target`, as read from the symlink at `link`, to an absolute path. `..` components are
+/// removed lexically, so this works even if the target does not exist.
+fn resolve_symlink_target(link: &Path, target: &Path) -> std::io::Result<PathBuf> {
+    let joined = std::path::absolute(link.parent().unwrap_or(Path::new("")).join(target))?;
+    let mut resolved = PathBuf::new();
+    for component in joined.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    Ok(resolved)
+}
+
+/// Returns a relative path that leads from the directory `base` to `target`, e.g.
+/// `../../data/file` from `/links/tracker` to `/data/file`. Both paths must be absolute and are
+/// compared lexically, so neither needs to exist.
+pub fn relative_path(base: &Path, target: &Path) -> PathBuf {
+    let mut base_components = base.components().peekable();
+    let mut target_components = target.components().peekable();
+    while base_components.peek().is_some() && base_components.peek() == target_components.peek() {
+        base_components.next();
+        target_components.next();
+    }
+    base_components
+        .map(|_| std::path::Component::ParentDir)
+        .chain(target_components)
+        .collect()
+}
+
+/// Returns true if `link` is a symlink whose target, after following any chain of symlinks, does
+/// not exist.
+pub fn is_dangling(link: &Path) -> bool {
+    link.is_symlink() && !link.exists()
+}
+
+/// Creates `link` pointing to `target`, replacing `link` if it already exists. The new symlink is
+/// created under a temporary name in the same directory and then renamed over `link`, so `link`
+/// never disappears and is left untouched on failure.
 pub fn create_or_update_symlink(link: &Path, target: &Path) -> std::io::Result<()> {
-    match std::os::unix::fs::symlink(target, link) {
-        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
-            std::fs::remove_file(link)?;
-            std::os::unix::fs::symlink(target, link)
+    let file_name = link.file_name().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} has no file name", link.display()),
+        )
+    })?;
+    loop {
+        let mut temp_name = std::ffi::OsString::from(".");
+        temp_name.push(file_name);
+        temp_name.push(format!(".toru-{:016x}", rand::random::<u64>()));
+        let temp_link = link.with_file_name(temp_name);
+        match std::os::unix::fs::symlink(target, &temp_link) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
         }
-        result => result,
+        return std::fs::rename(&temp_link, link).inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp_link);
+        });
     }
 }
 
@@ -161,7 +308,7 @@ mod tests {
     fn collect_files_empty_dir() {
         let tmp_dir = tempfile::tempdir().unwrap();
         assert!(matches!(
-            collect_files(&tmp_dir.path()),
+            collect_files(tmp_dir.path(), &CollectOptions::default(), None),
             Err(CollectFilesError::NoFiles)
         ));
     }
@@ -172,7 +319,10 @@ mod tests {
         let test_file = tmp_dir.path().join("test_file");
         std::fs::write(&test_file, "").expect("failed to create test file");
 
-        assert!(collect_files(&test_file).is_ok_and(|files| { files.contains_key(&test_file) }));
+        assert!(
+            collect_files(&test_file, &CollectOptions::default(), None)
+                .is_ok_and(|files| { files.contains_key(&test_file) })
+        );
     }
 
     #[test]
@@ -181,11 +331,22 @@ mod tests {
         let test_file = tmp_dir.path().join("test_file");
         std::fs::write(&test_file, "").expect("failed to create test file");
 
-        let files = collect_files(tmp_dir.path()).unwrap();
+        let files = collect_files(tmp_dir.path(), &CollectOptions::default(), None).unwrap();
         assert_eq!(files.len(), 1);
         assert!(files.contains_key(&test_file));
     }
 
+    #[test]
+    fn collect_files_advances_progress_bar() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        std::fs::write(tmp_dir.path().join("a"), "").expect("failed to create test file");
+        std::fs::write(tmp_dir.path().join("b"), "").expect("failed to create test file");
+
+        let bar = ProgressBar::hidden();
+        collect_files(tmp_dir.path(), &CollectOptions::default(), Some(&bar)).unwrap();
+        assert_eq!(bar.position(), 2);
+    }
+
     #[test]
     fn collect_files_with_symlink_fails() {
         let tmp_dir = tempfile::tempdir().unwrap();
@@ -195,20 +356,112 @@ mod tests {
         std::os::unix::fs::symlink(test_file, test_symlink).expect("failed to create test symlink");
 
         assert!(matches!(
-            collect_files(tmp_dir.path()),
+            collect_files(tmp_dir.path(), &CollectOptions::default(), None),
             Err(CollectFilesError::NonFilePath(_, _))
         ));
     }
 
+    #[test]
+    fn collect_files_with_symlink_skipped() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let test_file = tmp_dir.path().join("test_file");
+        std::fs::write(&test_file, "").expect("failed to create test file");
+        let test_symlink = tmp_dir.path().join("test_symlink");
+        std::os::unix::fs::symlink(&test_file, test_symlink)
+            .expect("failed to create test symlink");
+
+        let options = CollectOptions {
+            skip_non_files: true,
+            ..Default::default()
+        };
+        let files = collect_files(tmp_dir.path(), &options, None).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files.contains_key(&test_file));
+    }
+
+    #[test]
+    fn collect_files_with_symlink_followed() {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let test_file = tmp_dir.path().join("test_file");
+        std::fs::write(&test_file, "contents").expect("failed to create test file");
+        let test_symlink = tmp_dir.path().join("test_symlink");
+        std::os::unix::fs::symlink(&test_file, &test_symlink)
+            .expect("failed to create test symlink");
+
+        let options = CollectOptions {
+            follow_symlinks: true,
+            ..Default::default()
+        };
+        let files = collect_files(tmp_dir.path(), &options, None).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files.get(&test_symlink), Some(&8));
+    }
+
+    #[test]
+    fn collect_files_respects_max_depth() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let deep_dir = tmp_dir.path().join("a/b");
+        std::fs::create_dir_all(&deep_dir).expect("failed to create test dir");
+        std::fs::write(tmp_dir.path().join("top"), "1").expect("failed to create test file");
+        std::fs::write(tmp_dir.path().join("a/middle"), "22").expect("failed to create test file");
+        std::fs::write(deep_dir.join("deep"), "333").expect("failed to create test file");
+
+        let options = CollectOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        let files = collect_files(tmp_dir.path(), &options, None).unwrap();
+        assert_eq!(
+            files,
+            HashMap::from([
+                (tmp_dir.path().join("top"), 1),
+                (tmp_dir.path().join("a/middle"), 2),
+            ])
+        );
+        let files = collect_files(tmp_dir.path(), &CollectOptions::default(), None).unwrap();
+        assert_eq!(files.len(), 3);
+    }
+
+    #[test]
+    fn collect_files_skips_unreadable_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let locked_dir = tmp_dir.path().join("locked");
+        std::fs::create_dir(&locked_dir).expect("failed to create test dir");
+        std::fs::write(locked_dir.join("hidden"), "1").expect("failed to create test file");
+        std::fs::write(tmp_dir.path().join("visible"), "22").expect("failed to create test file");
+        std::fs::set_permissions(&locked_dir, std::fs::Permissions::from_mode(0o000))
+            .expect("failed to set permissions");
+        // Permissions are not enforced for root, so there is nothing to test.
+        if std::fs::read_dir(&locked_dir).is_ok() {
+            return;
+        }
+
+        let result = collect_files(tmp_dir.path(), &CollectOptions::default(), None);
+        let options = CollectOptions {
+            skip_unreadable: true,
+            ..Default::default()
+        };
+        let files = collect_files(tmp_dir.path(), &options, None);
+        std::fs::set_permissions(&locked_dir, std::fs::Permissions::from_mode(0o755))
+            .expect("failed to set permissions");
+        assert!(matches!(result, Err(CollectFilesError::WalkDir(_))));
+        assert_eq!(
+            files.unwrap(),
+            HashMap::from([(tmp_dir.path().join("visible"), 2)])
+        );
+    }
+
     #[test]
     fn collect_symlinks_no_symlinks() {
         let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
-        assert!(collect_symlinks(tmp_dir.path()).unwrap().is_empty());
+        assert!(collect_symlinks(tmp_dir.path(), false).unwrap().is_empty());
 
         std::fs::write(tmp_dir.path().join("file"), "contents")
             .expect("failed to create normal file");
 
-        assert!(collect_symlinks(tmp_dir.path()).unwrap().is_empty());
+        assert!(collect_symlinks(tmp_dir.path(), false).unwrap().is_empty());
     }
 
     #[test]
@@ -220,11 +473,103 @@ mod tests {
         std::os::unix::fs::symlink(&file_path, &symlink_path).expect("failed to create symlink");
 
         assert_eq!(
-            collect_symlinks(tmp_dir.path()).unwrap(),
+            collect_symlinks(tmp_dir.path(), false).unwrap(),
             HashMap::from([(symlink_path.clone(), file_path.clone())])
         );
     }
 
+    #[test]
+    fn collect_symlinks_relative() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let link_dir = tmp_dir.path().join("links");
+        std::fs::create_dir(&link_dir).expect("failed to create link dir");
+        let symlink_path = link_dir.join("symlink");
+
+        std::os::unix::fs::symlink("../data/./file", &symlink_path)
+            .expect("failed to create symlink");
+
+        assert_eq!(
+            collect_symlinks(tmp_dir.path(), false).unwrap(),
+            HashMap::from([(symlink_path.clone(), PathBuf::from("../data/./file"))])
+        );
+        assert_eq!(
+            collect_symlinks(tmp_dir.path(), true).unwrap(),
+            HashMap::from([(symlink_path.clone(), tmp_dir.path().join("data/file"))])
+        );
+    }
+
+    #[test]
+    fn relative_path_siblings() {
+        assert_eq!(
+            relative_path(
+                Path::new("/links/tracker/show"),
+                Path::new("/data/show/a.mkv")
+            ),
+            Path::new("../../../data/show/a.mkv")
+        );
+        assert_eq!(
+            relative_path(Path::new("/data"), Path::new("/data/show/a.mkv")),
+            Path::new("show/a.mkv")
+        );
+        assert_eq!(
+            relative_path(
+                Path::new("/data/show/extras"),
+                Path::new("/data/show/a.mkv")
+            ),
+            Path::new("../a.mkv")
+        );
+    }
+
+    #[test]
+    fn relative_path_resolves_from_link_dir() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let target = tmp_dir.path().join("data/a");
+        let link = tmp_dir.path().join("links/tracker/a");
+        std::fs::create_dir_all(target.parent().unwrap()).expect("failed to create test dir");
+        std::fs::create_dir_all(link.parent().unwrap()).expect("failed to create test dir");
+        std::fs::write(&target, "a").expect("failed to create test file");
+        std::os::unix::fs::symlink(relative_path(link.parent().unwrap(), &target), &link)
+            .expect("failed to create symlink");
+        assert_eq!(std::fs::read_to_string(&link).unwrap(), "a");
+    }
+
+    #[test]
+    fn is_dangling_valid_link() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let file_path = tmp_dir.path().join("file");
+        let symlink_path = tmp_dir.path().join("symlink");
+        std::fs::write(&file_path, "").expect("failed to create test file");
+        std::os::unix::fs::symlink(&file_path, &symlink_path).expect("failed to create symlink");
+
+        assert!(!is_dangling(&symlink_path));
+        assert!(!is_dangling(&file_path));
+    }
+
+    #[test]
+    fn is_dangling_missing_target() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let symlink_path = tmp_dir.path().join("symlink");
+        std::os::unix::fs::symlink(tmp_dir.path().join("missing"), &symlink_path)
+            .expect("failed to create symlink");
+
+        assert!(is_dangling(&symlink_path));
+    }
+
+    #[test]
+    fn is_dangling_link_to_link() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let file_path = tmp_dir.path().join("file");
+        let first_link = tmp_dir.path().join("first");
+        let second_link = tmp_dir.path().join("second");
+        std::fs::write(&file_path, "").expect("failed to create test file");
+        std::os::unix::fs::symlink(&file_path, &first_link).expect("failed to create symlink");
+        std::os::unix::fs::symlink(&first_link, &second_link).expect("failed to create symlink");
+        assert!(!is_dangling(&second_link));
+
+        std::fs::remove_file(&file_path).expect("failed to remove test file");
+        assert!(is_dangling(&second_link));
+    }
+
     #[test]
     fn create_or_update_symlink_basic() {
         let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
@@ -238,4 +583,37 @@ mod tests {
         create_or_update_symlink(&symlink_path, &new_file_path).expect("failed to update symlink");
         assert_eq!(std::fs::read_link(&symlink_path).unwrap(), new_file_path);
     }
+
+    #[test]
+    fn create_or_update_symlink_always_resolves() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let first_path = tmp_dir.path().join("first");
+        let second_path = tmp_dir.path().join("second");
+        std::fs::write(&first_path, "first").expect("failed to create test file");
+        std::fs::write(&second_path, "second").expect("failed to create test file");
+        let symlink_path = tmp_dir.path().join("symlink");
+        create_or_update_symlink(&symlink_path, &first_path).expect("failed to create symlink");
+
+        let done = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..1000 {
+                    let target = if i % 2 == 0 {
+                        &second_path
+                    } else {
+                        &first_path
+                    };
+                    create_or_update_symlink(&symlink_path, target)
+                        .expect("failed to update symlink");
+                }
+                done.store(true, std::sync::atomic::Ordering::Relaxed);
+            });
+            while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                assert!(std::fs::metadata(&symlink_path).is_ok());
+            }
+        });
+
+        // No temporary links should be left behind.
+        assert_eq!(std::fs::read_dir(tmp_dir.path()).unwrap().count(), 3);
+    }
 }
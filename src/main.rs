@@ -5,10 +5,23 @@ mod sycli;
 mod torrent;
 mod util;
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use std::process::ExitCode;
 
 #[derive(Parser)]
 struct Cli {
+    /// Suppress informational output and progress bars; errors and warnings are still printed.
+    #[arg(long, short, global = true)]
+    quiet: bool,
+
+    /// Log internal diagnostics to stderr. Pass twice (-vv) for more detail.
+    #[arg(short = 'v', global = true, action = clap::ArgAction::Count)]
+    verbosity: u8,
+
+    /// When to use colors in output.
+    #[arg(default_value = "auto", long, global = true, value_enum)]
+    color: util::ColorChoice,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -17,29 +30,102 @@ struct Cli {
 enum Commands {
     /// Imports torrent files for cross-seeding, creating symlinks if necessary.
     Import(subcommands::ImportArgs),
+    /// Undoes the imports recorded by `import --manifest`, removing the symlinks they created.
+    Unimport(subcommands::UnimportArgs),
+    /// Verifies existing files against a torrent file without modifying anything.
+    Verify(subcommands::VerifyArgs),
+    /// Prints the metadata in a torrent file.
+    Inspect(subcommands::InspectArgs),
+    /// Creates a torrent file for a file or directory.
+    Create(subcommands::CreateArgs),
 
+    /// Lists all torrents known to the client.
+    List(subcommands::ListArgs),
+    /// Summarizes the torrents known to the client.
+    Stats(subcommands::StatsArgs),
+    /// Exports the torrents known to the client as JSON or CSV.
+    Export(subcommands::ExportArgs),
     /// Finds the torrents that correspond to a given path.
+    ///
+    /// Exits with status 1 if no torrents match, and 2 on any other error.
     Find(subcommands::FindArgs),
+    /// Prints where the files for a torrent are on disk.
+    Where(subcommands::WhereArgs),
     /// Moves a file or directory to a new location.
     Move(subcommands::MoveArgs),
     /// Update paths after files or directories are externally moved.
     UpdatePaths(subcommands::UpdatePathsArgs),
+    /// Removes torrents from the client, optionally deleting their data.
+    Remove(subcommands::RemoveArgs),
+    /// Replaces identical files with hardlinks to a single copy.
+    Dedupe(subcommands::DedupeArgs),
+    /// Repairs dangling symlinks by finding replacement targets with the same file name, and the
+    /// same size if a torrent in the client knows it.
+    Relink(subcommands::RelinkArgs),
+    /// Reports inconsistencies between the client's torrents and the files on disk.
+    Doctor(subcommands::DoctorArgs),
 
     /// Organizes files for an episode into directories.
     BatchEpisodes(subcommands::BatchEpisodesArgs),
     /// Creates symlinks for a TV scanner to recognize files as episodes.
     MakeEpisodeLinks(subcommands::MakeEpisodeLinksArgs),
+
+    /// Inspects and validates config.toml.
+    Config(subcommands::ConfigArgs),
+
+    /// Generates shell completions and writes them to stdout.
+    #[command(hide = true)]
+    Completions(subcommands::CompletionsArgs),
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        // Not a failure as such; the subcommand has already reported that nothing matched.
+        Err(err) if err.is::<subcommands::NoMatchesError>() => ExitCode::from(1),
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            ExitCode::from(2)
+        }
+    }
 }
 
-fn main() -> anyhow::Result<()> {
+fn run() -> anyhow::Result<()> {
     let cli = Cli::parse();
+    util::set_quiet(cli.quiet);
+    util::set_color_choice(cli.color);
+    util::init_logging(cli.verbosity);
 
     match cli.command {
         Commands::Import(args) => args.exec(),
+        Commands::Unimport(args) => args.exec(),
+        Commands::Verify(args) => args.exec(),
+        Commands::Inspect(args) => args.exec(),
+        Commands::Create(args) => args.exec(),
+        Commands::List(args) => args.exec(),
+        Commands::Stats(args) => args.exec(),
+        Commands::Export(args) => args.exec(),
         Commands::Find(args) => args.exec(),
+        Commands::Where(args) => args.exec(),
         Commands::Move(args) => args.exec(),
         Commands::UpdatePaths(args) => args.exec(),
+        Commands::Remove(args) => args.exec(),
+        Commands::Dedupe(args) => args.exec(),
+        Commands::Relink(args) => args.exec(),
+        Commands::Doctor(args) => args.exec(),
         Commands::BatchEpisodes(args) => args.exec(),
         Commands::MakeEpisodeLinks(args) => args.exec(),
+        Commands::Config(args) => args.exec(),
+        Commands::Completions(args) => args.exec(Cli::command()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cli_is_valid() {
+        Cli::command().debug_assert();
     }
 }
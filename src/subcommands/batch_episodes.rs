@@ -1,10 +1,11 @@
-use clap::Args;
+use clap::{Args, ValueEnum};
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::ffi::OsStr;
 use std::path::PathBuf;
 
 use crate::sycli;
+use crate::util;
 
 #[derive(Args)]
 pub struct BatchEpisodesArgs {
@@ -16,11 +17,27 @@ pub struct BatchEpisodesArgs {
     #[arg(long)]
     dry_run: bool,
 
+    /// How to put each file into its new directory.
+    #[arg(default_value = "hardlink", long, value_enum)]
+    link_mode: LinkMode,
+
     /// The files to process.
     #[arg(num_args(1..))]
     files: Vec<PathBuf>,
 }
 
+#[derive(Copy, Clone, Default, ValueEnum)]
+enum LinkMode {
+    /// Hardlink the file into the new directory, then remove the original once the torrent has
+    /// been updated. Requires the new directory to be on the same filesystem.
+    #[default]
+    Hardlink,
+    /// Symlink the file into the new directory, keeping the original in place.
+    Symlink,
+    /// Rename the file into the new directory before updating the torrent.
+    Rename,
+}
+
 impl BatchEpisodesArgs {
     pub fn exec(self) -> anyhow::Result<()> {
         let to_process = self.files.into_iter().collect::<HashSet<_>>();
@@ -49,21 +66,21 @@ impl BatchEpisodesArgs {
         Regex::new(r"^(?<header>.+?\.S[0-9][0-9])E[0-9][0-9]\..+?\.(?<trailer>(?:720|1080|2160)p\..+?\.WEB-DL.+)\.mkv").unwrap();
 
         for (torrent_id, (path, _size)) in files {
-            eprintln!(
+            util::info!(
                 "processing file {} for torrent {}...",
                 path.display(),
                 torrent_id
             );
             let Some(file_name) = path.file_name().and_then(OsStr::to_str) else {
-                eprintln!(
-                    "  warning: missing or invalid filename for {}; skipping",
+                util::warning!(
+                    "missing or invalid filename for {}; skipping",
                     path.display()
                 );
                 continue;
             };
 
             let Some(captures) = re.captures(file_name) else {
-                eprintln!("  warning: unable to extract metadata from {file_name}; skipping",);
+                util::warning!("unable to extract metadata from {file_name}; skipping");
                 continue;
             };
 
@@ -75,7 +92,7 @@ impl BatchEpisodesArgs {
 
             let dir_path = self.base_dir.join(dir_name);
 
-            eprintln!("  making directory {}", dir_path.display());
+            util::info!("  making directory {}", dir_path.display());
             if !self.dry_run {
                 std::fs::create_dir(&dir_path).or_else(|e| {
                     if e.kind() == std::io::ErrorKind::AlreadyExists {
@@ -85,15 +102,25 @@ impl BatchEpisodesArgs {
                     }
                 })?;
             }
-            eprintln!(
-                "  creating link from {} to original {}",
-                dir_path.join(file_name).display(),
-                path.display(),
-            );
+            let new_path = dir_path.join(file_name);
+            match self.link_mode {
+                LinkMode::Hardlink | LinkMode::Symlink => util::info!(
+                    "  creating link from {} to original {}",
+                    new_path.display(),
+                    path.display(),
+                ),
+                LinkMode::Rename => {
+                    util::info!("  renaming {} to {}", path.display(), new_path.display())
+                }
+            }
             if !self.dry_run {
-                std::fs::hard_link(&path, dir_path.join(file_name))?;
+                match self.link_mode {
+                    LinkMode::Hardlink => std::fs::hard_link(&path, &new_path)?,
+                    LinkMode::Symlink => std::os::unix::fs::symlink(&path, &new_path)?,
+                    LinkMode::Rename => std::fs::rename(&path, &new_path)?,
+                }
             }
-            eprintln!(
+            util::info!(
                 "  updating torrent {} directory to {}",
                 torrent_id,
                 dir_path.display()
@@ -101,9 +128,12 @@ impl BatchEpisodesArgs {
             if !self.dry_run {
                 sycli::move_torrent(&torrent_id, &dir_path)?;
             }
-            eprintln!("  unlinking original path {}", path.display());
-            if !self.dry_run {
-                std::fs::remove_file(&path)?;
+            // A symlink still needs the original, and a rename already removed it.
+            if matches!(self.link_mode, LinkMode::Hardlink) {
+                util::info!("  unlinking original path {}", path.display());
+                if !self.dry_run {
+                    std::fs::remove_file(&path)?;
+                }
             }
         }
         Ok(())
@@ -0,0 +1,16 @@
+use clap::Args;
+
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate completions for.
+    shell: clap_complete::Shell,
+}
+
+impl CompletionsArgs {
+    /// Writes completions for `command` to stdout.
+    pub fn exec(self, mut command: clap::Command) -> anyhow::Result<()> {
+        let name = command.get_name().to_string();
+        clap_complete::generate(self.shell, &mut command, name, &mut std::io::stdout());
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+use anyhow::bail;
+use clap::{Args, Subcommand};
+use console::style;
+
+use crate::config::{CONFIG_PATH, Config};
+use crate::sycli;
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    command: ConfigCommands,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Loads config.toml, reports which settings are set, and checks that the torrent client and
+    /// the OMDb API key work. Nothing is modified.
+    ///
+    /// Exits with an error if any check fails.
+    Check(CheckArgs),
+}
+
+#[derive(Args)]
+struct CheckArgs {
+    /// Only check the config file itself, without contacting the torrent client or OMDb.
+    #[arg(long)]
+    offline: bool,
+}
+
+/// An IMDb ID that is known to exist, for checking the OMDb API key.
+const OMDB_TEST_IMDB_ID: &str = "tt0245429";
+
+#[derive(Debug, PartialEq)]
+enum Status {
+    Pass,
+    Fail,
+    /// Not set, or not checked; not a failure.
+    Skip,
+}
+
+#[derive(Debug)]
+struct Check {
+    name: String,
+    status: Status,
+    detail: String,
+}
+
+impl Check {
+    fn new(name: &str, status: Status, detail: impl Into<String>) -> Self {
+        Check {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl ConfigArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        match self.command {
+            ConfigCommands::Check(args) => args.exec(),
+        }
+    }
+}
+
+impl CheckArgs {
+    fn exec(self) -> anyhow::Result<()> {
+        let (file_check, config) = match crate::config::load() {
+            Ok(Some(config)) => (Check::new(CONFIG_PATH, Status::Pass, "loaded"), config),
+            Ok(None) => (
+                Check::new(CONFIG_PATH, Status::Skip, "not found; using defaults"),
+                Config::default(),
+            ),
+            Err(err) => {
+                print_check(&Check::new(CONFIG_PATH, Status::Fail, format!("{err:#}")));
+                bail!("{CONFIG_PATH} could not be loaded");
+            }
+        };
+        let mut checks = vec![file_check];
+        checks.extend(check_settings(&config));
+        if !self.offline {
+            checks.push(check_torrent_client());
+            checks.push(check_omdb(&config));
+        }
+
+        for check in &checks {
+            print_check(check);
+        }
+        let failed = checks
+            .iter()
+            .filter(|check| check.status == Status::Fail)
+            .count();
+        if failed > 0 {
+            bail!("{failed} check(s) failed");
+        }
+        Ok(())
+    }
+}
+
+fn print_check(check: &Check) {
+    let status = match check.status {
+        Status::Pass => style("ok").green(),
+        Status::Fail => style("FAIL").red(),
+        Status::Skip => style("--").yellow(),
+    };
+    println!("[{status:^4}] {}: {}", check.name, check.detail);
+}
+
+/// Reports the value of each setting in `config`, failing those that are invalid.
+fn check_settings(config: &Config) -> Vec<Check> {
+    let mut checks = vec![];
+
+    checks.push(match &config.api_keys.omdb {
+        Some(_) => Check::new("api_keys.omdb", Status::Pass, "set"),
+        None => Check::new(
+            "api_keys.omdb",
+            Status::Skip,
+            "not set; make-episode-links will not work",
+        ),
+    });
+
+    checks.push(match &config.sycli.binary {
+        Some(binary) if binary.is_file() => {
+            Check::new("sycli.binary", Status::Pass, binary.display().to_string())
+        }
+        Some(binary) => Check::new(
+            "sycli.binary",
+            Status::Fail,
+            format!("{} does not exist", binary.display()),
+        ),
+        None => Check::new(
+            "sycli.binary",
+            Status::Skip,
+            "not set; using sycli from PATH",
+        ),
+    });
+    checks.push(Check::new(
+        "sycli.retries",
+        Status::Pass,
+        config.sycli.retries.to_string(),
+    ));
+
+    for (name, secs) in [
+        ("http.timeout_secs", config.http.timeout_secs),
+        (
+            "http.connect_timeout_secs",
+            config.http.connect_timeout_secs,
+        ),
+    ] {
+        checks.push(if secs == 0 {
+            Check::new(name, Status::Fail, "must be greater than zero")
+        } else {
+            Check::new(name, Status::Pass, format!("{secs}s"))
+        });
+    }
+
+    checks.push(match &config.defaults.move_strategy {
+        Some(strategy) => match super::r#move::resolve_strategy(None, Some(strategy)) {
+            Ok(_) => Check::new("defaults.move_strategy", Status::Pass, strategy.as_str()),
+            Err(err) => Check::new("defaults.move_strategy", Status::Fail, err.to_string()),
+        },
+        None => Check::new("defaults.move_strategy", Status::Skip, "not set"),
+    });
+
+    checks.push(if config.tracker_names.is_empty() {
+        Check::new("tracker_names", Status::Skip, "not set")
+    } else {
+        Check::new(
+            "tracker_names",
+            Status::Pass,
+            format!("{} tracker(s)", config.tracker_names.len()),
+        )
+    });
+
+    if config.symlink_roots.is_empty() {
+        checks.push(Check::new("symlink_roots", Status::Skip, "not set"));
+    }
+    for root in &config.symlink_roots {
+        checks.push(if root.is_dir() {
+            Check::new("symlink_roots", Status::Pass, root.display().to_string())
+        } else {
+            Check::new(
+                "symlink_roots",
+                Status::Fail,
+                format!("{} is not a directory", root.display()),
+            )
+        });
+    }
+
+    checks.push(match config.dht_dir.as_deref() {
+        Some("") => Check::new("dht_dir", Status::Fail, "must not be empty"),
+        Some(dir) => Check::new("dht_dir", Status::Pass, dir),
+        None => Check::new(
+            "dht_dir",
+            Status::Skip,
+            format!("not set; using {}", super::import::DEFAULT_DHT_DIR),
+        ),
+    });
+
+    checks
+}
+
+fn check_torrent_client() -> Check {
+    match sycli::new_instance(false).get_torrents() {
+        Ok(torrents) => Check::new(
+            "torrent client",
+            Status::Pass,
+            format!("reachable; {} torrent(s)", torrents.len()),
+        ),
+        Err(err) => Check::new("torrent client", Status::Fail, format!("{err:#}")),
+    }
+}
+
+fn check_omdb(config: &Config) -> Check {
+    let Some(api_key) = &config.api_keys.omdb else {
+        return Check::new("OMDb", Status::Skip, "no API key to check");
+    };
+    match super::make_episode_links::lookup_omdb(OMDB_TEST_IMDB_ID, api_key) {
+        Ok(result) => Check::new(
+            "OMDb",
+            Status::Pass,
+            format!("API key works; looked up {}", result.title),
+        ),
+        Err(err) => Check::new("OMDb", Status::Fail, format!("{err:#}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statuses(checks: &[Check]) -> Vec<(&str, &Status)> {
+        checks
+            .iter()
+            .map(|check| (check.name.as_str(), &check.status))
+            .collect()
+    }
+
+    #[test]
+    fn check_settings_defaults() {
+        assert_eq!(
+            statuses(&check_settings(&Config::default())),
+            [
+                ("api_keys.omdb", &Status::Skip),
+                ("sycli.binary", &Status::Skip),
+                ("sycli.retries", &Status::Pass),
+                ("http.timeout_secs", &Status::Pass),
+                ("http.connect_timeout_secs", &Status::Pass),
+                ("defaults.move_strategy", &Status::Skip),
+                ("tracker_names", &Status::Skip),
+                ("symlink_roots", &Status::Skip),
+                ("dht_dir", &Status::Skip),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_settings_invalid() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let config: Config = toml::from_str(&format!(
+            r#"
+            dht_dir = ""
+            symlink_roots = ["{}", "{}"]
+
+            [defaults]
+            move_strategy = "teleport"
+
+            [http]
+            timeout_secs = 0
+
+            [sycli]
+            binary = "{}"
+            "#,
+            tmp_dir.path().display(),
+            tmp_dir.path().join("missing").display(),
+            tmp_dir.path().join("sycli").display(),
+        ))
+        .unwrap();
+        let failed: Vec<_> = check_settings(&config)
+            .into_iter()
+            .filter(|check| check.status == Status::Fail)
+            .map(|check| check.name)
+            .collect();
+        assert_eq!(
+            failed,
+            [
+                "sycli.binary",
+                "http.timeout_secs",
+                "defaults.move_strategy",
+                "symlink_roots",
+                "dht_dir",
+            ]
+        );
+    }
+}
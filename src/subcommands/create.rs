@@ -0,0 +1,293 @@
+use anyhow::{anyhow, bail};
+use clap::Args;
+use indicatif::ProgressBar;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use serde::Serialize;
+use serde_bytes::ByteBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::fs;
+use crate::torrent;
+use crate::util;
+
+#[derive(Args)]
+pub struct CreateArgs {
+    /// File or directory to create a torrent for.
+    path: PathBuf,
+
+    /// Tracker announce URL.
+    #[arg(long)]
+    announce: String,
+
+    /// Piece length in bytes. Must be a power of two and at least 16 KiB.
+    #[arg(default_value_t = 1 << 20, long, value_parser = parse_piece_length)]
+    piece_length: u64,
+
+    /// Mark the torrent as private, so clients only get peers from its tracker.
+    #[arg(long)]
+    private: bool,
+
+    /// Comment to embed in the torrent.
+    #[arg(long)]
+    comment: Option<String>,
+
+    /// Where to write the torrent file. Defaults to the name of `path` plus ".torrent" in the
+    /// current directory.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+fn parse_piece_length(value: &str) -> Result<u64, String> {
+    let piece_length = value.parse::<u64>().map_err(|err| err.to_string())?;
+    if piece_length < 16 * 1024 || !piece_length.is_power_of_two() {
+        return Err(format!(
+            "{piece_length} is not a power of two of at least 16384"
+        ));
+    }
+    Ok(piece_length)
+}
+
+#[derive(Serialize)]
+struct MetaInfo {
+    announce: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    #[serde(rename = "created by")]
+    created_by: String,
+    #[serde(rename = "creation date")]
+    creation_date: i64,
+    info: InfoDict,
+}
+
+#[derive(Serialize)]
+struct InfoDict {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<FileDict>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    length: Option<u64>,
+    name: String,
+    #[serde(rename = "piece length")]
+    piece_length: u64,
+    pieces: ByteBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    private: Option<u8>,
+}
+
+#[derive(Serialize)]
+struct FileDict {
+    length: u64,
+    path: Vec<String>,
+}
+
+impl CreateArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        let path = std::path::absolute(&self.path)?;
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("{} does not have a UTF-8 file name", path.display()))?;
+        let output = self
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(format!("{name}.torrent")));
+        if output.exists() {
+            bail!("{} already exists", output.display());
+        }
+
+        let bar = util::new_progress_bar().with_message("hashing...");
+        let bytes = self.build(&path, &bar)?;
+        bar.finish_using_style();
+        std::fs::write(&output, &bytes)?;
+        util::info!(
+            "wrote {} (info hash {})",
+            output.display(),
+            torrent::info_hash(&bytes)?
+        );
+        Ok(())
+    }
+
+    /// Hashes the data at `path` and returns the bencoded torrent.
+    fn build(&self, path: &Path, bar: &ProgressBar) -> anyhow::Result<Vec<u8>> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow!("{} has no file name", path.display()))?;
+        let is_single_file = path.is_file();
+
+        // Use the same representation as parsed torrents, where every file path starts with the
+        // torrent name, so the slice and hashing helpers can be shared.
+        let mut disk_paths = fs::collect_files(path, &fs::CollectOptions::default(), None)?
+            .into_iter()
+            .collect::<Vec<_>>();
+        disk_paths.sort();
+        let files = disk_paths
+            .iter()
+            .map(|(disk_path, length)| {
+                Ok(torrent::File {
+                    length: *length,
+                    path: Path::new(name).join(disk_path.strip_prefix(path)?),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let mapping = files
+            .iter()
+            .zip(&disk_paths)
+            .map(|(file, (disk_path, _))| (file.path.as_path(), disk_path.as_path()))
+            .collect::<HashMap<_, _>>();
+
+        let file_slices = torrent::compute_file_slices(&files, self.piece_length);
+        bar.set_length(files.iter().map(|file| file.length).sum());
+        let hashes = file_slices
+            .par_iter()
+            .map(|slices| {
+                let hash = torrent::hash_slices(slices, &mapping)?
+                    .ok_or_else(|| anyhow!("files changed while hashing"))?;
+                bar.inc(slices.iter().map(|slice| slice.length).sum());
+                Ok(hash)
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let (files, length) = if is_single_file {
+            (None, Some(files[0].length))
+        } else {
+            let files = files
+                .iter()
+                .map(|file| {
+                    Ok(FileDict {
+                        length: file.length,
+                        path: file
+                            .path
+                            .strip_prefix(name)?
+                            .iter()
+                            .map(|component| {
+                                component
+                                    .to_str()
+                                    .map(str::to_string)
+                                    .ok_or_else(|| anyhow!("{} is not UTF-8", file.path.display()))
+                            })
+                            .collect::<anyhow::Result<_>>()?,
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?;
+            (Some(files), None)
+        };
+        let meta_info = MetaInfo {
+            announce: self.announce.clone(),
+            comment: self.comment.clone(),
+            created_by: format!("toru {}", env!("CARGO_PKG_VERSION")),
+            creation_date: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_secs() as i64,
+            info: InfoDict {
+                files,
+                length,
+                name: name
+                    .to_str()
+                    .ok_or_else(|| anyhow!("{} is not UTF-8", path.display()))?
+                    .to_string(),
+                piece_length: self.piece_length,
+                pieces: ByteBuf::from(
+                    hashes
+                        .iter()
+                        .flat_map(|hash| hash.bytes())
+                        .collect::<Vec<_>>(),
+                ),
+                private: self.private.then_some(1),
+            },
+        };
+        Ok(serde_bencode::to_bytes(&meta_info)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(path: &Path, private: bool) -> CreateArgs {
+        CreateArgs {
+            path: path.to_path_buf(),
+            announce: "https://tracker.example.com/announce".to_string(),
+            piece_length: 16 * 1024,
+            private,
+            comment: Some("test".to_string()),
+            output: None,
+        }
+    }
+
+    #[test]
+    fn parse_piece_length_validates() {
+        assert_eq!(parse_piece_length("16384"), Ok(16384));
+        assert_eq!(parse_piece_length("1048576"), Ok(1 << 20));
+        assert!(parse_piece_length("16383").is_err());
+        assert!(parse_piece_length("24576").is_err());
+        assert!(parse_piece_length("8192").is_err());
+    }
+
+    #[test]
+    fn build_round_trips_directory() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let dir = tmp_dir.path().join("content");
+        std::fs::create_dir_all(dir.join("sub")).expect("failed to create test dir");
+        std::fs::write(dir.join("a"), vec![1; 20_000]).expect("failed to create test file");
+        std::fs::write(dir.join("sub/b"), vec![2; 30_000]).expect("failed to create test file");
+
+        let bytes = args(&dir, true)
+            .build(&dir, &ProgressBar::hidden())
+            .unwrap();
+        let parsed: torrent::Torrent = serde_bencode::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            parsed.announce.as_deref(),
+            Some("https://tracker.example.com/announce")
+        );
+        assert_eq!(parsed.comment.as_deref(), Some("test"));
+        assert!(parsed.info.private);
+        assert!(!parsed.info.is_single_file);
+        assert_eq!(parsed.info.name, "content");
+        assert_eq!(
+            parsed
+                .info
+                .files
+                .iter()
+                .map(|file| (file.path.as_path(), file.length))
+                .collect::<Vec<_>>(),
+            [
+                (Path::new("content/a"), 20_000),
+                (Path::new("content/sub/b"), 30_000)
+            ]
+        );
+        assert_eq!(parsed.info.pieces.len(), 4);
+
+        let disk_a = dir.join("a");
+        let disk_b = dir.join("sub/b");
+        let mapping = HashMap::from([
+            (Path::new("content/a"), disk_a.as_path()),
+            (Path::new("content/sub/b"), disk_b.as_path()),
+        ]);
+        assert!(
+            parsed
+                .info
+                .pieces
+                .iter()
+                .all(|piece| piece.check(&mapping).unwrap())
+        );
+    }
+
+    #[test]
+    fn build_round_trips_single_file() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let file = tmp_dir.path().join("file.bin");
+        std::fs::write(&file, vec![3; 100]).expect("failed to create test file");
+
+        let bytes = args(&file, false)
+            .build(&file, &ProgressBar::hidden())
+            .unwrap();
+        let parsed: torrent::Torrent = serde_bencode::from_bytes(&bytes).unwrap();
+        assert!(!parsed.info.private);
+        assert!(parsed.info.is_single_file);
+        assert_eq!(parsed.info.files[0].path, Path::new("file.bin"));
+        assert_eq!(parsed.info.files[0].length, 100);
+
+        let mapping = HashMap::from([(Path::new("file.bin"), file.as_path())]);
+        assert!(parsed.info.pieces[0].check(&mapping).unwrap());
+    }
+}
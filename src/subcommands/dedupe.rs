@@ -0,0 +1,197 @@
+use clap::Args;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use sha1_smol::Sha1;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::fs;
+use crate::sycli;
+use crate::util;
+
+#[derive(Args)]
+pub struct DedupeArgs {
+    /// Directory to search for duplicate files.
+    path: PathBuf,
+
+    /// If true, only prints out the changes that would have been made.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl DedupeArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        self.run(&*sycli::new_instance(self.dry_run))
+    }
+
+    fn run(&self, client: &dyn sycli::Client) -> anyhow::Result<()> {
+        let path = std::path::absolute(&self.path)?;
+        let files = fs::collect_files_with_spinner(&path, &fs::CollectOptions::default())?;
+
+        // Files belonging to incomplete torrents may still be written to by the client, so they
+        // must never be replaced.
+        let incomplete_files = client
+            .get_torrents()?
+            .into_iter()
+            .filter(|torrent| !torrent.is_complete())
+            .flat_map(|torrent| {
+                torrent
+                    .files
+                    .into_keys()
+                    .map(move |path| torrent.base_path.join(path))
+            })
+            .collect::<HashSet<_>>();
+
+        // Hardlinks cannot span filesystems, and files with the same inode are already
+        // deduplicated, so only keep one path per inode.
+        let mut by_size = HashMap::<_, BTreeMap<_, _>>::new();
+        for (path, size) in files {
+            if size == 0 || incomplete_files.contains(&path) {
+                continue;
+            }
+            let metadata = std::fs::metadata(&path)?;
+            by_size
+                .entry((metadata.dev(), size))
+                .or_default()
+                .entry(metadata.ino())
+                .or_insert(path);
+        }
+        let candidates = by_size
+            .into_iter()
+            .filter(|(_, inodes)| inodes.len() > 1)
+            .flat_map(|((dev, size), inodes)| {
+                inodes.into_values().map(move |path| (dev, size, path))
+            })
+            .collect::<Vec<_>>();
+
+        let bar = util::new_progress_bar().with_message("hashing...");
+        bar.set_length(candidates.iter().map(|(_, size, _)| size).sum());
+        let hashed = candidates
+            .into_par_iter()
+            .map(|(dev, size, path)| {
+                let digest = hash_file(&path)?;
+                bar.inc(size);
+                Ok(((dev, size, digest), path))
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        bar.finish_using_style();
+
+        let mut duplicates = HashMap::<_, Vec<_>>::new();
+        for (key, path) in hashed {
+            duplicates.entry(key).or_default().push(path);
+        }
+
+        let mut saved_bytes = 0;
+        for ((_, size, _), mut paths) in duplicates {
+            if paths.len() < 2 {
+                continue;
+            }
+            paths.sort();
+            let (original, paths) = paths.split_first().unwrap();
+            for path in paths {
+                util::info!(
+                    "replacing {} with hardlink to {}",
+                    path.display(),
+                    original.display()
+                );
+                if !self.dry_run {
+                    replace_with_hard_link(original, path)?;
+                }
+                saved_bytes += size;
+            }
+        }
+        eprintln!(
+            "{} {}",
+            if self.dry_run { "would save" } else { "saved" },
+            util::format_bytes(saved_bytes)
+        );
+
+        Ok(())
+    }
+}
+
+fn hash_file(path: &Path) -> std::io::Result<[u8; sha1_smol::DIGEST_LENGTH]> {
+    let mut file = std::fs::File::open(path)?;
+    let mut sha1 = Sha1::new();
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        sha1.update(&buffer[..bytes_read]);
+    }
+    Ok(sha1.digest().bytes())
+}
+
+/// Atomically replaces `path` with a hardlink to `original` by linking to a temporary name in the
+/// same directory and renaming it over `path`.
+fn replace_with_hard_link(original: &Path, path: &Path) -> std::io::Result<()> {
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(path.file_name().unwrap_or_default());
+    temp_name.push(".toru-dedupe");
+    let temp_path = path.with_file_name(temp_name);
+    std::fs::hard_link(original, &temp_path)?;
+    std::fs::rename(&temp_path, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp_path);
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replace_with_hard_link_shares_inode() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let original = tmp_dir.path().join("original");
+        let duplicate = tmp_dir.path().join("duplicate");
+        std::fs::write(&original, "contents").expect("failed to create test file");
+        std::fs::write(&duplicate, "contents").expect("failed to create test file");
+        assert_eq!(
+            hash_file(&original).unwrap(),
+            hash_file(&duplicate).unwrap()
+        );
+
+        replace_with_hard_link(&original, &duplicate).expect("failed to replace with hardlink");
+
+        assert_eq!(
+            std::fs::metadata(&original).unwrap().ino(),
+            std::fs::metadata(&duplicate).unwrap().ino()
+        );
+        assert_eq!(std::fs::read_dir(tmp_dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn run_links_duplicates_but_skips_incomplete_torrents() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        for name in ["a", "b", "c"] {
+            std::fs::write(tmp_dir.path().join(name), "contents")
+                .expect("failed to create test file");
+        }
+        std::fs::write(tmp_dir.path().join("other"), "different")
+            .expect("failed to create test file");
+        let inode = |name: &str| std::fs::metadata(tmp_dir.path().join(name)).unwrap().ino();
+        let original = inode("a");
+        let downloading = inode("c");
+        let client = sycli::FakeClient::new(vec![sycli::Torrent {
+            progress: 0.5,
+            ..sycli::Torrent::for_test("downloading", tmp_dir.path(), &[("c", 8)])
+        }]);
+        let args = DedupeArgs {
+            path: tmp_dir.path().to_path_buf(),
+            dry_run: false,
+        };
+
+        args.run(&client).unwrap();
+        assert_eq!(inode("a"), original);
+        assert_eq!(inode("b"), original);
+        assert_eq!(inode("c"), downloading);
+        assert_ne!(inode("other"), original);
+        assert_eq!(
+            std::fs::read_to_string(tmp_dir.path().join("b")).unwrap(),
+            "contents"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use clap::Args;
+use console::style;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use std::path::PathBuf;
+
+use crate::fs;
+use crate::sycli;
+
+#[derive(Args)]
+pub struct DoctorArgs {
+    /// A directory to scan for files that belong to no torrent. May be specified more than once.
+    #[arg(long)]
+    scan_dir: Vec<PathBuf>,
+
+    /// A directory to scan for dangling symlinks. May be specified more than once.
+    #[arg(long)]
+    symlink_dir: Vec<PathBuf>,
+
+    /// Print the report as JSON instead of human-readable text.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Default, Serialize)]
+struct Report {
+    missing: Vec<MissingFiles>,
+    orphaned: BTreeSet<PathBuf>,
+    dangling_symlinks: Vec<DanglingSymlink>,
+}
+
+#[derive(Serialize)]
+struct MissingFiles {
+    id: String,
+    name: String,
+    paths: BTreeSet<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct DanglingSymlink {
+    link: PathBuf,
+    target: PathBuf,
+}
+
+impl DoctorArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        let torrents = sycli::new_instance(false).get_torrents()?;
+
+        let mut report = Report {
+            missing: find_missing(&torrents),
+            ..Default::default()
+        };
+
+        let torrent_paths = torrents
+            .iter()
+            .flat_map(|torrent| {
+                torrent
+                    .files
+                    .keys()
+                    .map(|path| torrent.base_path.join(path))
+            })
+            .collect::<HashSet<_>>();
+        let options = fs::CollectOptions {
+            skip_non_files: true,
+            ..Default::default()
+        };
+        for scan_dir in &self.scan_dir {
+            let scan_dir = std::path::absolute(scan_dir)?;
+            let files = match fs::collect_files(&scan_dir, &options, None) {
+                Err(fs::CollectFilesError::NoFiles) => continue,
+                result => result?,
+            };
+            report.orphaned.extend(
+                files
+                    .into_keys()
+                    .filter(|path| !torrent_paths.contains(path)),
+            );
+        }
+
+        for symlink_dir in &self.symlink_dir {
+            let mut dangling = fs::collect_symlinks(&std::path::absolute(symlink_dir)?, true)?
+                .into_iter()
+                .filter(|(link, _)| fs::is_dangling(link))
+                .map(|(link, target)| DanglingSymlink { link, target })
+                .collect::<Vec<_>>();
+            dangling.sort_by(|a, b| a.link.cmp(&b.link));
+            report.dangling_symlinks.extend(dangling);
+        }
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            return Ok(());
+        }
+
+        println!("{} torrent(s) with missing files", report.missing.len());
+        for missing in &report.missing {
+            println!("  {} {}", missing.id, missing.name);
+            for path in &missing.paths {
+                println!("    {}", style(path.display()).red());
+            }
+        }
+        println!("{} file(s) belonging to no torrent", report.orphaned.len());
+        for path in &report.orphaned {
+            println!("  {}", path.display());
+        }
+        println!("{} dangling symlink(s)", report.dangling_symlinks.len());
+        for symlink in &report.dangling_symlinks {
+            println!(
+                "  {} -> {}",
+                symlink.link.display(),
+                style(symlink.target.display()).red()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Returns the complete torrents that have files missing on disk. Incomplete torrents are skipped,
+/// since the client may not have created all their files yet, as are deselected files, which are
+/// never downloaded.
+fn find_missing(torrents: &[sycli::Torrent]) -> Vec<MissingFiles> {
+    let mut missing = torrents
+        .iter()
+        .filter(|torrent| torrent.is_complete())
+        .filter_map(|torrent| {
+            let paths = torrent
+                .files
+                .iter()
+                .filter(|(_, file)| file.wanted)
+                .map(|(path, _)| torrent.base_path.join(path))
+                .filter(|path| !path.exists())
+                .collect::<BTreeSet<_>>();
+            (!paths.is_empty()).then(|| MissingFiles {
+                id: torrent.id.clone(),
+                name: torrent.name.clone(),
+                paths,
+            })
+        })
+        .collect::<Vec<_>>();
+    missing.sort_by(|a, b| a.id.cmp(&b.id));
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_missing_reports_only_complete_torrents() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        std::fs::write(tmp_dir.path().join("present"), "").expect("failed to create test file");
+        let torrents = [
+            sycli::Torrent::for_test("ok", tmp_dir.path(), &[("present", 1)]),
+            sycli::Torrent::for_test("broken", tmp_dir.path(), &[("present", 1), ("gone", 1)]),
+            sycli::Torrent {
+                progress: 0.5,
+                ..sycli::Torrent::for_test("downloading", tmp_dir.path(), &[("gone", 1)])
+            },
+        ];
+
+        let missing = find_missing(&torrents);
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].id, "broken");
+        assert_eq!(
+            missing[0].paths,
+            BTreeSet::from([tmp_dir.path().join("gone")])
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
+
+use crate::sycli;
+
+#[derive(Args)]
+pub struct ExportArgs {
+    /// Output format.
+    #[arg(default_value = "json", long, value_enum)]
+    format: Format,
+
+    /// File to write to, or "-" for stdout.
+    #[arg(default_value = "-", long)]
+    output: PathBuf,
+}
+
+#[derive(Copy, Clone, Default, ValueEnum)]
+enum Format {
+    /// A JSON array with one object per torrent.
+    #[default]
+    Json,
+    /// One row per file, with the torrent's fields repeated on each row.
+    Csv,
+}
+
+#[derive(Serialize)]
+struct ExportEntry {
+    id: String,
+    name: String,
+    base_path: PathBuf,
+    size: usize,
+    progress: f64,
+    label: Option<String>,
+    tracker_urls: Vec<String>,
+    files: BTreeMap<PathBuf, usize>,
+}
+
+impl From<sycli::Torrent> for ExportEntry {
+    fn from(torrent: sycli::Torrent) -> Self {
+        ExportEntry {
+            id: torrent.id,
+            name: torrent.name,
+            base_path: torrent.base_path,
+            size: torrent.size,
+            progress: torrent.progress,
+            label: torrent.label,
+            tracker_urls: torrent.tracker_urls,
+            files: torrent
+                .files
+                .into_iter()
+                .map(|(path, file)| (path, file.size))
+                .collect(),
+        }
+    }
+}
+
+impl ExportArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        let mut entries = sycli::new_instance(false)
+            .get_torrents()?
+            .into_iter()
+            .map(ExportEntry::from)
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let mut output: Box<dyn Write> = if self.output.as_os_str() == "-" {
+            Box::new(std::io::stdout().lock())
+        } else {
+            Box::new(std::io::BufWriter::new(std::fs::File::create(
+                &self.output,
+            )?))
+        };
+        match self.format {
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut output, &entries)?;
+                writeln!(output)?;
+            }
+            Format::Csv => write_csv(&mut output, &entries)?,
+        }
+        output.flush()?;
+        Ok(())
+    }
+}
+
+fn write_csv(output: &mut dyn Write, entries: &[ExportEntry]) -> std::io::Result<()> {
+    writeln!(
+        output,
+        "id,name,base_path,size,progress,label,tracker_urls,file_path,file_size"
+    )?;
+    for entry in entries {
+        for (path, size) in &entry.files {
+            let fields = [
+                entry.id.clone(),
+                entry.name.clone(),
+                entry.base_path.display().to_string(),
+                entry.size.to_string(),
+                entry.progress.to_string(),
+                entry.label.clone().unwrap_or_default(),
+                entry.tracker_urls.join(" "),
+                path.display().to_string(),
+                size.to_string(),
+            ];
+            let row = fields
+                .iter()
+                .map(|field| csv_escape(field))
+                .collect::<Vec<_>>();
+            writeln!(output, "{}", row.join(","))?;
+        }
+    }
+    Ok(())
+}
+
+/// Quotes `field` if needed, per RFC 4180.
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_escape_quotes_when_needed() {
+        assert_eq!(csv_escape("plain"), "plain");
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn write_csv_one_row_per_file() {
+        let entry = ExportEntry {
+            id: "abc".to_string(),
+            name: "Name, With Comma".to_string(),
+            base_path: "/data".into(),
+            size: 3,
+            progress: 1.0,
+            label: None,
+            tracker_urls: vec!["https://a/announce".to_string()],
+            files: BTreeMap::from([("x".into(), 1), ("y".into(), 2)]),
+        };
+        let mut output = vec![];
+        write_csv(&mut output, &[entry]).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "id,name,base_path,size,progress,label,tracker_urls,file_path,file_size\n\
+             abc,\"Name, With Comma\",/data,3,1,,https://a/announce,x,1\n\
+             abc,\"Name, With Comma\",/data,3,1,,https://a/announce,y,2\n"
+        );
+    }
+}
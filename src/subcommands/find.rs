@@ -1,31 +1,142 @@
 use clap::Args;
+use std::collections::HashMap;
 use std::path::PathBuf;
+use thiserror::Error;
 
 use crate::fs;
 use crate::sycli;
+use crate::util;
 
 #[derive(Args)]
 pub struct FindArgs {
-    /// Destination directory.
-    path: PathBuf,
+    /// Files or directories to search for. May be specified more than once.
+    #[arg(required(true))]
+    paths: Vec<PathBuf>,
+
+    /// List the files under `paths` covered by each matched torrent, grouped by input path.
+    #[arg(long)]
+    verbose: bool,
+
+    /// Only show torrents with this label.
+    #[arg(long)]
+    label: Option<String>,
+
+    /// Only show torrents with a tracker whose host contains this string, ignoring case. May
+    /// be specified more than once to match any of several trackers.
+    #[arg(long)]
+    tracker: Vec<String>,
+
+    /// Leave out torrents with a tracker whose host contains this string, ignoring case, even if
+    /// they match --tracker. May be specified more than once.
+    #[arg(long)]
+    exclude_tracker: Vec<String>,
+
+    /// Only show torrents whose progress is at least this fraction, from 0.0 to 1.0.
+    #[arg(long, value_parser = util::parse_progress)]
+    min_progress: Option<f64>,
+
+    /// Skip symlinks, sockets, and other non-file entries with a warning instead of failing.
+    #[arg(long)]
+    skip_non_files: bool,
+
+    /// Follow symlinks when collecting files.
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Skip files and directories that cannot be read due to missing permissions with a warning,
+    /// instead of failing.
+    #[arg(long)]
+    skip_unreadable: bool,
 }
 
+/// Returned by `find` when no torrents match, so that it can exit with a distinct status.
+#[derive(Debug, Error)]
+#[error("no torrents matched")]
+pub struct NoMatchesError;
+
 impl FindArgs {
+    fn collect_options(&self) -> fs::CollectOptions {
+        fs::CollectOptions {
+            skip_non_files: self.skip_non_files,
+            follow_symlinks: self.follow_symlinks,
+            skip_unreadable: self.skip_unreadable,
+            ..Default::default()
+        }
+    }
+
     pub fn exec(self) -> anyhow::Result<()> {
-        let path = std::path::absolute(self.path)?;
+        let paths = self
+            .paths
+            .iter()
+            .map(std::path::absolute)
+            .collect::<Result<Vec<_>, _>>()?;
 
-        let files = fs::collect_files(&path)?;
+        let mut files = HashMap::new();
+        for path in &paths {
+            files.extend(fs::collect_files_with_spinner(
+                path,
+                &self.collect_options(),
+            )?);
+        }
 
         // TODO: Abstract this out so multiple torrent client backends can be used.
-        let torrents = sycli::filter_torrents(&sycli::get_torrents()?, &files)?;
+        let mut torrents = sycli::filter_torrents_with_matches(&sycli::get_torrents()?, &files)?;
+        if let Some(label) = &self.label {
+            torrents.retain(|matched| matched.torrent.label.as_ref() == Some(label));
+        }
+        torrents.retain(|matched| {
+            matched
+                .torrent
+                .matches_tracker_filters(&self.tracker, &self.exclude_tracker)
+        });
+        if let Some(min_progress) = self.min_progress {
+            torrents.retain(|matched| matched.torrent.progress >= min_progress);
+        }
 
         println!(
             "Found {} torrent(s) seeded from {}",
             torrents.len(),
-            path.display()
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
         );
-        for torrent in torrents {
-            println!("  {}", torrent.id);
+        if torrents.is_empty() {
+            return Err(NoMatchesError.into());
+        }
+        for sycli::MatchedTorrent {
+            torrent,
+            matched_paths,
+        } in torrents
+        {
+            println!(
+                "  {} {} ({}, {:.1}%)",
+                torrent.id,
+                torrent.name,
+                util::format_bytes(torrent.size as u64),
+                torrent.progress * 100.0
+            );
+            if self.verbose {
+                for path in &paths {
+                    let mut matched_under_path = matched_paths
+                        .iter()
+                        .filter(|matched_path| matched_path.starts_with(path))
+                        .peekable();
+                    if matched_under_path.peek().is_none() {
+                        continue;
+                    }
+                    let indent = if paths.len() > 1 {
+                        println!("    from {}:", path.display());
+                        "      "
+                    } else {
+                        "    "
+                    };
+                    for matched_path in matched_under_path {
+                        println!("{indent}{}", matched_path.display());
+                    }
+                }
+            }
         }
         Ok(())
     }
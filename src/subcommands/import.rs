@@ -1,18 +1,25 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
-use std::fs::File;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::{Duration, Instant};
 
 // TODO: Migrate to `thiserror`
-use anyhow::{anyhow, bail, Result};
-use clap::Args;
+use anyhow::{Context, Result, bail};
+use clap::{Args, ValueEnum};
 use console::style;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use notify::{EventKind, Watcher};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use sha1_smol::Sha1;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, trace};
 
 use crate::fs;
 use crate::sycli;
 use crate::torrent;
+use crate::torrent::cache::TorrentCache;
 use crate::util;
 
 #[derive(Args)]
@@ -27,275 +34,871 @@ pub struct ImportArgs {
     #[arg(long, required(true))]
     source: Vec<PathBuf>,
 
-    /// Torrent files to import.
-    #[arg(required(true))]
+    /// Torrent files to import. Use `-` to read a single torrent from stdin.
+    #[arg(required_unless_present = "watch")]
     torrents: Vec<PathBuf>,
 
+    /// After importing any torrent files given on the command line, keep running and import
+    /// `.torrent` files as they appear in this directory. Each file is only imported once it has
+    /// stopped changing, and is then moved into a `done` subdirectory. Files that fail to import
+    /// are left in place and retried if they change again. The source directories are only
+    /// enumerated once at startup.
+    #[arg(long, value_name = "DIR")]
+    watch: Option<PathBuf>,
+
     /// Find matching files and create symlinks, but do not actually import the torrent file into a
     /// client.
     #[arg(long)]
     skip_add: bool,
 
+    /// Import torrents even if the client already has a torrent with the same infohash. Without
+    /// this, such torrents are skipped before any matching or hash checking is done.
+    #[arg(long)]
+    force: bool,
+
+    /// Add torrents to the client paused instead of starting them, e.g. to force a recheck first.
+    #[arg(long, conflicts_with = "skip_add")]
+    add_paused: bool,
+
     /// If true, only prints out the changes that would have been made.
     #[arg(long)]
     dry_run: bool,
 
+    /// With --dry-run, print the plan for each torrent as a line of JSON instead of
+    /// human-readable text.
+    #[arg(long, requires = "dry_run")]
+    json: bool,
+
+    /// Hash check every piece even with --dry-run or --skip-add, instead of only sampling
+    /// --pieces-to-test pieces per file.
+    #[arg(long)]
+    full_check: bool,
+
     /// How many pieces should be tested per file when checking for a match.
     #[arg(long, default_value_t = 3)]
     pieces_to_test: usize,
+
+    /// Seed for choosing which pieces to test, to make matches reproducible. If unset, a random
+    /// seed is used.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Maximum number of threads to use for hash checking. 0 uses all logical CPUs.
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+
+    /// Hash check pieces one at a time, in file and offset order, instead of in parallel. This is
+    /// usually slower, but avoids seeking back and forth on spinning disks.
+    #[arg(long, conflicts_with = "threads")]
+    sequential: bool,
+
+    /// Also consider files whose size differs from the expected size by at most this many bytes.
+    /// Useful when a release has been retagged or padded. Candidates are still hash checked.
+    #[arg(long, default_value_t = 0)]
+    size_slack: u64,
+
+    /// Replace the announce URL of each torrent before importing it. This also changes the
+    /// directory that symlinks are created in, since it is named after the announce host.
+    #[arg(long, value_name = "URL")]
+    announce_override: Option<String>,
+
+    /// Follow symlinks in the source directories, indexing the files they point to. Files are
+    /// indexed by their canonical path, so a file reachable through several links is only
+    /// considered once.
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Only index files at most this many directories deep in each source directory, where 1
+    /// only indexes the files directly in it, e.g. to skip deep backup snapshot trees.
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+
+    /// When a file has several candidate matches, prefer the ones under this directory, instead
+    /// of guessing from where the largest file was found.
+    #[arg(long, value_name = "PATH")]
+    prefer_prefix: Option<PathBuf>,
+
+    /// Create symlinks with targets relative to the symlink instead of absolute ones, so the
+    /// symlink directory and the source directories can be moved together.
+    #[arg(long)]
+    relative_symlinks: bool,
+
+    /// How to lay out the directories created under --symlink-dir: one per announce host, all
+    /// torrents directly in --symlink-dir, or one per tracker name from `tracker_names` in
+    /// config.toml.
+    #[arg(default_value = "host", long, value_enum)]
+    layout: Layout,
+
+    /// Glob of paths under the source directories to ignore, e.g. "**/.Trash*". Globs are matched
+    /// against absolute paths, and excluding a directory excludes everything under it. May be
+    /// specified more than once.
+    #[arg(long, value_parser = Glob::new)]
+    exclude: Vec<Glob>,
+
+    /// Translate the directory given to the client when adding a torrent, for clients that see
+    /// the filesystem under a different mount, e.g. `/mnt/data:/data` in a container. If several
+    /// mappings match, the longest `from` prefix wins. May be specified more than once.
+    #[arg(long, value_name = "FROM:TO", value_parser = parse_path_map)]
+    path_map: Vec<PathMap>,
+
+    /// Keep going if parts of the source directories cannot be read, logging a warning for each
+    /// path that was skipped instead of aborting before any torrent is imported. Unusable
+    /// --source or --symlink-dir arguments are still fatal. A tally of skipped paths and failed
+    /// torrents is printed at the end.
+    #[arg(long)]
+    continue_on_error: bool,
+
+    /// Do not read or update the cache of parsed torrent files in `$XDG_CACHE_HOME/toru`. The
+    /// cache lets repeated runs over the same torrent files skip parsing them.
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Append a line of JSON to this file for each torrent that gets imported, recording its
+    /// infohash, the seed directory, the symlinks that were created, and whether it was added to
+    /// the client. Records are written as soon as each torrent is done, including for torrents
+    /// that failed part way through, so an interrupted batch can still be undone.
+    #[arg(long, value_name = "PATH", conflicts_with = "dry_run")]
+    manifest: Option<PathBuf>,
+}
+
+/// Maps paths under `from`, as toru sees them, to the same paths under `to`, as the client sees
+/// them.
+#[derive(Clone, Debug, PartialEq)]
+struct PathMap {
+    from: PathBuf,
+    to: PathBuf,
+}
+
+fn parse_path_map(value: &str) -> Result<PathMap, String> {
+    let Some((from, to)) = value.split_once(':') else {
+        return Err(format!("{value} is not of the form <from>:<to>"));
+    };
+    if from.is_empty() || to.is_empty() {
+        return Err(format!("{value} is not of the form <from>:<to>"));
+    }
+    Ok(PathMap {
+        from: PathBuf::from(from),
+        to: PathBuf::from(to),
+    })
+}
+
+/// Applies the mapping with the longest matching `from` prefix to `path`, if any.
+fn apply_path_map(path: &Path, path_map: &[PathMap]) -> Option<PathBuf> {
+    path_map
+        .iter()
+        .filter_map(|map| Some((map, path.strip_prefix(&map.from).ok()?)))
+        .max_by_key(|(map, _)| map.from.components().count())
+        .map(|(map, rest)| map.to.join(rest))
 }
 
 impl ImportArgs {
     pub fn exec(self) -> anyhow::Result<()> {
-        let entries = enumerate_files_with_sizes(&self.source);
-        for torrent in self.torrents {
-            if let Err(err) = process_torrent(
-                &torrent,
-                &self.symlink_dir,
-                &entries,
-                self.pieces_to_test,
-                self.dry_run,
-                self.skip_add,
-            ) {
-                println!("{} {:?}", style("error:").red(), style(err).red());
+        let pool = rayon::ThreadPoolBuilder::new()
+            // A single worker runs rayon's parallel iterators in order.
+            .num_threads(if self.sequential { 1 } else { self.threads })
+            .build()?;
+        // Hashing can take a long time, so catch unusable directories before doing any work.
+        self.validate_dirs()?;
+        let sources = self
+            .source
+            .iter()
+            .map(std::path::absolute)
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut exclude = GlobSetBuilder::new();
+        for glob in &self.exclude {
+            exclude.add(glob.clone());
+        }
+        let (entries, errors) = enumerate_files_with_sizes(
+            &sources,
+            &exclude.build()?,
+            self.follow_symlinks,
+            self.max_depth,
+        );
+        if !errors.is_empty() && !self.continue_on_error {
+            bail!(
+                "unable to read {} paths in the source directories, first: {:#}; pass \
+                 --continue-on-error to skip them",
+                errors.len(),
+                errors[0]
+            );
+        }
+        for err in &errors {
+            util::warning!("skipping {err:#}");
+        }
+        // Cross-seeds of the same release often share pieces, so remember hashes across torrents.
+        let cache = torrent::PieceCache::new(PIECE_CACHE_CAPACITY);
+        let torrent_cache = if self.no_cache {
+            None
+        } else {
+            TorrentCache::open()
+        };
+        let manifest = self.manifest.as_deref().map(Manifest::open).transpose()?;
+        // The client is not involved at all with --skip-add, so there is nothing to check.
+        let client_hashes = if self.force || self.skip_add {
+            HashSet::new()
+        } else {
+            sycli::new_instance(self.dry_run)
+                .get_torrents()?
+                .into_iter()
+                .map(|torrent| torrent.id.to_ascii_lowercase())
+                .collect()
+        };
+        let mut batch = Batch {
+            entries: &entries,
+            pool: &pool,
+            cache: &cache,
+            torrent_cache: torrent_cache.as_ref(),
+            manifest: manifest.as_ref(),
+            client_hashes,
+        };
+        let mut failed = 0;
+        for torrent in &self.torrents {
+            if let Err(err) = self.process_torrent(torrent, &mut batch) {
+                eprintln!("{} {:?}", style("error:").red(), style(err).red());
+                failed += 1;
             }
         }
+        if self.continue_on_error && (failed > 0 || !errors.is_empty()) {
+            util::warning!(
+                "{failed} of {} torrents failed to import; {} source paths were skipped",
+                self.torrents.len(),
+                errors.len()
+            );
+        }
+        if let Some(dir) = &self.watch {
+            self.watch(dir, &mut batch)?;
+        }
         Ok(())
     }
+
+    fn watch(&self, dir: &Path, batch: &mut Batch) -> Result<()> {
+        let done_dir = dir.join("done");
+        if !self.dry_run {
+            std::fs::create_dir_all(&done_dir)?;
+        }
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(dir, notify::RecursiveMode::NonRecursive)?;
+
+        let mut pending = PendingTorrents::default();
+        // Anything already waiting in the directory is treated like a fresh arrival.
+        for entry in std::fs::read_dir(dir)? {
+            pending.touch(entry?.path(), Instant::now());
+        }
+        util::info!("watching {} for new torrents", dir.display());
+        loop {
+            match rx.recv_timeout(WATCH_SETTLE_TIME) {
+                Ok(event) => {
+                    let event = event?;
+                    trace!(?event, "watch event");
+                    if !matches!(event.kind, EventKind::Access(_) | EventKind::Remove(_)) {
+                        for path in event.paths {
+                            pending.touch(path, Instant::now());
+                        }
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    bail!("watcher for {} stopped", dir.display())
+                }
+            }
+            for torrent in pending.take_settled(Instant::now()) {
+                if !torrent.is_file() {
+                    continue;
+                }
+                if let Err(err) = self.process_torrent(&torrent, batch) {
+                    eprintln!("{} {:?}", style("error:").red(), style(err).red());
+                    continue;
+                }
+                let fs = fs::new_instance(self.dry_run);
+                if let Err(err) = fs.rename(&torrent, &done_dir.join(torrent.file_name().unwrap()))
+                {
+                    eprintln!("{} {:?}", style("error:").red(), style(err).red());
+                }
+            }
+        }
+    }
+
+    fn validate_dirs(&self) -> Result<()> {
+        for source in &self.source {
+            if !source.is_dir() {
+                bail!("source {} is not a directory", source.display());
+            }
+        }
+        // The symlink directory is created on demand, so check the closest existing ancestor.
+        let existing = self
+            .symlink_dir
+            .ancestors()
+            .find(|dir| dir.exists())
+            .unwrap_or(Path::new("."));
+        if !existing.is_dir() {
+            bail!(
+                "symlink dir {} is not usable: {} is not a directory",
+                self.symlink_dir.display(),
+                existing.display()
+            );
+        }
+        if !self.dry_run {
+            rustix::fs::access(existing, rustix::fs::Access::WRITE_OK).map_err(|err| {
+                anyhow::anyhow!(
+                    "symlink dir {} is not usable: {} is not writable: {err}",
+                    self.symlink_dir.display(),
+                    existing.display()
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    fn process_torrent(&self, path: &Path, batch: &mut Batch) -> Result<()> {
+        let (mut torrent, info_hash, bytes) = load_torrent(path, batch.torrent_cache)?;
+        // The client can only add torrents from a file, so anything read from stdin or changed
+        // here needs to be saved to a new one.
+        let new_bytes = match &self.announce_override {
+            Some(announce) => {
+                let bytes = match bytes {
+                    Some(bytes) => bytes,
+                    None => util::read_input(path)?,
+                };
+                torrent.announce = Some(announce.clone());
+                Some(torrent::replace_announce(&bytes, announce)?)
+            }
+            None => bytes.filter(|_| util::is_stdin(path)),
+        };
+        // A torrent read from stdin has no file name, so refer to it by its own name instead.
+        let name = if util::is_stdin(path) {
+            Path::new(&torrent.info.name)
+        } else {
+            path
+        };
+        let _span = tracing::info_span!("import", torrent = %name.display()).entered();
+        if !self.force && batch.client_hashes.contains(&info_hash.to_string()) {
+            util::info!(
+                "skipping {}: already present in client as {info_hash}",
+                name.display()
+            );
+            return Ok(());
+        }
+        let mut details = vec![util::format_bytes(
+            torrent.info.files.iter().map(|file| file.length).sum(),
+        )];
+        if torrent.info.private {
+            details.push("private".to_string());
+        }
+        if let Some(source) = &torrent.info.source {
+            details.push(format!("source {source}"));
+        }
+        util::info!("processing {} ({})", name.display(), details.join(", "));
+        // By definition, potential candidates must have matching file sizes, give or take the
+        // configured slack.
+        let candidates = torrent
+            .info
+            .files
+            .iter()
+            .map(|file| {
+                let sizes = file.length.saturating_sub(self.size_slack)
+                    ..=file.length.saturating_add(self.size_slack);
+                let entry = batch
+                    .entries
+                    .range(sizes)
+                    .flat_map(|(_, paths)| paths.iter().map(PathBuf::as_path))
+                    .collect::<Vec<_>>();
+                if entry.is_empty() {
+                    bail!(
+                        "unable to find candidate matches for file {} with size {} ({} bytes)",
+                        file.path.display(),
+                        util::format_bytes(file.length),
+                        file.length
+                    );
+                }
+                Ok(((&file.path, file.length), entry))
+            })
+            .collect::<Result<HashMap<_, _>, _>>()?;
+        let prefer_prefix = self
+            .prefer_prefix
+            .as_ref()
+            .map(std::path::absolute)
+            .transpose()?;
+        let candidates = pick_candidates(candidates, prefer_prefix.as_deref());
+        let pieces = if (self.dry_run || self.skip_add) && !self.full_check {
+            // Sample a number of pieces per file as a quick correctness check.
+            let mut rng = match self.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_os_rng(),
+            };
+            std::borrow::Cow::Owned(sample_pieces(
+                &torrent.info.pieces,
+                self.pieces_to_test,
+                &mut rng,
+            ))
+        } else {
+            // Otherwise, do a full check: the hash checks are parallelized and can run faster than
+            // hash checks in many common torrent clients.
+            std::borrow::Cow::Borrowed(&torrent.info.pieces)
+        };
+        let total_bytes = pieces.iter().map(torrent::Piece::length).sum();
+        debug!(
+            pieces = pieces.len(),
+            total_bytes, "hash checking candidates"
+        );
+        let bar = util::new_two_line_progress_bar().with_message("hashing...");
+        bar.set_length(total_bytes);
+        let failed_paths = batch
+            .pool
+            .install(|| torrent::find_failed_paths(&pieces, &candidates, Some(batch.cache), &bar));
+        bar.finish_using_style();
+        let failed_paths = failed_paths.context("unable to hash check candidates")?;
+        if !failed_paths.is_empty() {
+            let failed_paths = failed_paths.into_iter().collect::<BTreeSet<_>>();
+            let candidates = candidates.into_iter().collect::<BTreeMap<_, _>>();
+            bail!("hash check failed for paths: {failed_paths:#?}\n\ncandidates: {candidates:#?}");
+        }
+
+        // The client reads the torrent while it is being added, so the file must outlive `plan`.
+        let new_file = match new_bytes {
+            Some(bytes) if !self.dry_run && !self.skip_add => {
+                let mut file = tempfile::Builder::new()
+                    .prefix("toru-")
+                    .suffix(".torrent")
+                    .tempfile()?;
+                file.write_all(&bytes)?;
+                Some(file)
+            }
+            _ => None,
+        };
+        let path = new_file.as_ref().map_or(name, |file| file.path());
+        let mut plan = ImportPlan {
+            paused: self.add_paused,
+            ..torrent.plan_cross_seed(path, &self.symlink_dir, self.layout, &candidates)?
+        };
+        plan.client_seed_dir =
+            apply_path_map(&std::path::absolute(&plan.seed_dir)?, &self.path_map);
+        if self.relative_symlinks {
+            for link in &mut plan.links {
+                let link_dir = std::path::absolute(link.link.parent().unwrap())?;
+                link.target = fs::relative_path(&link_dir, &std::path::absolute(&link.target)?);
+            }
+        }
+        if self.json {
+            println!("{}", serde_json::to_string(&plan)?);
+            return Ok(());
+        }
+        let mut applied = Applied::default();
+        let result = plan.apply(self.dry_run, self.skip_add, &mut applied);
+        if applied.added {
+            batch.client_hashes.insert(info_hash.to_string());
+        }
+        if let Some(manifest) = batch.manifest {
+            manifest.append(&ManifestRecord {
+                torrent: if util::is_stdin(path) {
+                    path.to_path_buf()
+                } else {
+                    std::path::absolute(path)?
+                },
+                info_hash: info_hash.to_string(),
+                action: plan.action,
+                seed_dir: std::path::absolute(&plan.seed_dir)?,
+                client_seed_dir: plan.client_seed_dir.clone(),
+                links: plan.links[..applied.links]
+                    .iter()
+                    .map(|link| {
+                        Ok(Link {
+                            link: std::path::absolute(&link.link)?,
+                            target: link.target.clone(),
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+                added: applied.added,
+                error: result.as_ref().err().map(|err| format!("{err:#}")),
+            })?;
+        }
+        result
+    }
 }
 
-fn enumerate_files_with_sizes<P: AsRef<Path>>(dirs: &[P]) -> HashMap<u64, Vec<PathBuf>> {
-    let mut results = HashMap::<_, Vec<_>>::new();
+/// Reads and parses the torrent at `path`, along with its infohash, using `torrent_cache` if
+/// possible. The raw bytes are only returned if the file actually had to be read.
+fn load_torrent(
+    path: &Path,
+    torrent_cache: Option<&TorrentCache>,
+) -> Result<(torrent::Torrent, torrent::Digest, Option<Vec<u8>>)> {
+    // Stdin has no modification time to invalidate entries with.
+    let torrent_cache = torrent_cache.filter(|_| !util::is_stdin(path));
+    if let Some((torrent, info_hash)) = torrent_cache.and_then(|cache| cache.get(path)) {
+        debug!(path = %path.display(), "using cached torrent");
+        return Ok((torrent, info_hash, None));
+    }
+    let bytes = util::read_input(path)?;
+    let torrent: torrent::Torrent = serde_bencode::from_bytes(&bytes)?;
+    let info_hash = torrent::info_hash(&bytes)?;
+    if let Some(cache) = torrent_cache {
+        cache.put(path, &torrent, &info_hash);
+    }
+    Ok((torrent, info_hash, Some(bytes)))
+}
+
+/// Subdirectory of --symlink-dir for trackerless torrents, unless `dht_dir` is set in config.toml.
+pub(super) const DEFAULT_DHT_DIR: &str = "_dht";
+
+/// State shared by every torrent imported in one run.
+struct Batch<'a> {
+    entries: &'a BTreeMap<u64, Vec<PathBuf>>,
+    pool: &'a rayon::ThreadPool,
+    cache: &'a torrent::PieceCache,
+    torrent_cache: Option<&'a TorrentCache>,
+    manifest: Option<&'a Manifest>,
+    /// Lowercase infohashes of the torrents in the client, including those added during the run.
+    client_hashes: HashSet<String>,
+}
+
+/// Maximum number of piece hashes to remember across torrents in a single run. Each entry is on
+/// the order of a hundred bytes, so this bounds the cache to tens of MiB.
+const PIECE_CACHE_CAPACITY: usize = 256 * 1024;
+
+/// How long a watched torrent file must go without changes before it is considered fully written.
+const WATCH_SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Tracks torrent files in a watched directory until they stop changing.
+#[derive(Default)]
+struct PendingTorrents {
+    last_changed: HashMap<PathBuf, Instant>,
+}
+
+impl PendingTorrents {
+    /// Records a change to `path`. Paths that do not look like torrent files, such as editor or
+    /// download temp files, are ignored; renaming them to their final name produces a new event.
+    fn touch(&mut self, path: PathBuf, now: Instant) {
+        let is_hidden = path
+            .file_name()
+            .is_none_or(|name| name.as_encoded_bytes().starts_with(b"."));
+        if is_hidden || path.extension().is_none_or(|ext| ext != "torrent") {
+            return;
+        }
+        self.last_changed.insert(path, now);
+    }
+
+    /// Removes and returns the paths that have not changed for at least `WATCH_SETTLE_TIME`, in a
+    /// stable order.
+    fn take_settled(&mut self, now: Instant) -> Vec<PathBuf> {
+        let mut settled = self
+            .last_changed
+            .iter()
+            .filter(|(_, changed)| now.duration_since(**changed) >= WATCH_SETTLE_TIME)
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        settled.sort();
+        for path in &settled {
+            self.last_changed.remove(path);
+        }
+        settled
+    }
+}
+
+/// Picks pieces so that every file has data in at least `per_file` of the chosen pieces, or in all
+/// of its pieces if it has fewer. Pieces that span several files count towards each of them, so
+/// files are visited from the fewest pieces to the most and only sample what they still lack. The
+/// result is in torrent order, and a given `rng` state always picks the same pieces.
+fn sample_pieces<R: rand::Rng>(
+    pieces: &[torrent::Piece],
+    per_file: usize,
+    rng: &mut R,
+) -> Vec<torrent::Piece> {
+    let mut path_to_pieces = BTreeMap::<_, Vec<_>>::new();
+    for (index, piece) in pieces.iter().enumerate() {
+        for slice in &piece.file_slices {
+            path_to_pieces.entry(&slice.path).or_default().push(index);
+        }
+    }
+    let mut path_to_pieces = path_to_pieces.into_values().collect::<Vec<_>>();
+    // The sort is stable, so ties stay in path order.
+    path_to_pieces.sort_by_key(Vec::len);
+    let mut chosen = BTreeSet::new();
+    for mut indices in path_to_pieces {
+        let covered = indices
+            .iter()
+            .filter(|index| chosen.contains(*index))
+            .count();
+        let wanted = std::cmp::min(per_file, indices.len()).saturating_sub(covered);
+        indices.retain(|index| !chosen.contains(index));
+        indices.shuffle(rng);
+        chosen.extend(indices.into_iter().take(wanted));
+    }
+    chosen
+        .into_iter()
+        .map(|index| pieces[index].clone())
+        .collect()
+}
+
+/// Indexes the files under `dirs` by size, skipping any entries that match `exclude`. If
+/// `follow_symlinks` is set, symlinks are followed and files are indexed by canonical path instead.
+/// If `max_depth` is set, directories deeper than that are not descended into.
+pub(super) fn enumerate_files_with_sizes<P: AsRef<Path>>(
+    dirs: &[P],
+    exclude: &GlobSet,
+    follow_symlinks: bool,
+    max_depth: Option<usize>,
+) -> (BTreeMap<u64, Vec<PathBuf>>, Vec<anyhow::Error>) {
+    let mut results = BTreeMap::<_, Vec<_>>::new();
+    let mut errors = vec![];
     let bar = util::new_progress_spinner();
-    bar.enable_steady_tick(std::time::Duration::from_millis(125));
     let mut count = 0;
+    let mut visited = HashSet::new();
     for dir in dirs {
         bar.set_message(format!("enumerating {}", dir.as_ref().display()));
-        for entry in walkdir::WalkDir::new(dir) {
-            let Ok(entry) = entry else {
-                // TODO: error handling?
-                continue;
+        // walkdir reports symlink loops as errors rather than descending forever.
+        for entry in walkdir::WalkDir::new(dir)
+            .follow_links(follow_symlinks)
+            .max_depth(max_depth.unwrap_or(usize::MAX))
+            .into_iter()
+            .filter_entry(|entry| !exclude.is_match(entry.path()))
+        {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(err) if err.loop_ancestor().is_some() => {
+                    debug!(%err, "skipping symlink loop");
+                    continue;
+                }
+                Err(err) => {
+                    errors.push(err.into());
+                    continue;
+                }
             };
-            // TODO: handle symlinks?
             if !entry.file_type().is_file() {
                 continue;
             }
-            let Ok(metadata) = entry.metadata() else {
-                // TODO: error handling?
-                continue;
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    errors.push(err.into());
+                    continue;
+                }
             };
-            results
-                .entry(metadata.len())
-                .or_default()
-                .push(entry.into_path());
+            let path = if follow_symlinks {
+                let path =
+                    match entry.path().canonicalize() {
+                        Ok(path) => path,
+                        Err(err) => {
+                            errors.push(anyhow::Error::new(err).context(format!(
+                                "failed to canonicalize {}",
+                                entry.path().display()
+                            )));
+                            continue;
+                        }
+                    };
+                if !visited.insert(path.clone()) {
+                    continue;
+                }
+                path
+            } else {
+                entry.into_path()
+            };
+            results.entry(metadata.len()).or_default().push(path);
             count += 1;
             bar.set_position(count);
         }
     }
-    bar.finish_with_message(format!("enumerated {} files", results.len()));
-    results
+    bar.finish_with_message(format!("enumerated {count} files"));
+    (results, errors)
 }
 
-fn process_torrent(
-    path: &Path,
-    target_dir: &Path,
-    entries: &HashMap<u64, Vec<PathBuf>>,
-    pieces_to_test: usize,
-    dry_run: bool,
-    skip_add: bool,
-) -> Result<()> {
-    let torrent: torrent::Torrent = serde_bencode::from_bytes(&std::fs::read(path)?)?;
-    println!("processing {}", path.display());
-    // By definition, potential candidates must have matching file sizes.
-    let candidates = torrent
-        .info
-        .files
-        .iter()
-        .map(|file| {
-            let Some(entry) = entries.get(&file.length) else {
-                bail!(
-                    "unable to find candidate matches for file {} with size {}",
-                    file.path.display(),
-                    file.length
-                );
-            };
-            Ok(((&file.path, file.length), entry))
-        })
-        .collect::<Result<HashMap<_, _>, _>>()?;
-    let candidates = pick_candidates(candidates);
-    let pieces = if dry_run || skip_add {
-        // Sample a number of pieces to file as a quick correctness check.
-        let mut path_to_pieces = HashMap::<_, Vec<_>>::new();
-        for piece in &torrent.info.pieces {
-            for slice in &piece.file_slices {
-                path_to_pieces.entry(&slice.path).or_default().push(piece);
-            }
-        }
-        std::borrow::Cow::Owned(
-            path_to_pieces
-                .into_iter()
-                .flat_map(|(_path, mut pieces)| {
-                    let piece_count = std::cmp::min(pieces_to_test, pieces.len());
-                    pieces.shuffle(&mut rand::rng());
-                    pieces.truncate(piece_count);
-                    pieces
-                })
-                .collect::<HashSet<_>>()
-                .into_iter()
-                .cloned()
-                .collect::<Vec<_>>(),
-        )
-    } else {
-        // Otherwise, do a full check: the hash checks are parallelized and can run faster than
-        // hash checks in many common torrent clients.
-        std::borrow::Cow::Borrowed(&torrent.info.pieces)
-    };
-    let total_bytes  = pieces
-        .iter()
-        .map(|piece| piece.file_slices.iter().map(|f| f.length).sum::<u64>())
-        .sum();
-    let bar = util::new_progress_bar().with_message("hashing...");
-    bar.set_length(total_bytes);
-    let failed_paths: HashSet<_> = pieces
-        .par_iter()
-        .inspect(|piece| bar.inc(piece.file_slices.iter().map(|f| f.length).sum()))
-        // TODO: Probably want some sort of error handling here.
-        .filter(|piece| !piece.check(&candidates).unwrap())
-        .flat_map_iter(|piece| piece.file_slices.iter().map(|slice| &slice.path))
-        .collect();
-    bar.finish_using_style();
-    if !failed_paths.is_empty() {
-        let failed_paths = failed_paths.into_iter().collect::<BTreeSet<_>>();
-        let candidates = candidates.into_iter().collect::<BTreeMap<_, _>>();
-        bail!("hash check failed for paths: {failed_paths:#?}\n\ncandidates: {candidates:#?}");
-    }
-
-    torrent.cross_seed(dry_run, skip_add, path, target_dir, &candidates)
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum Action {
+    /// The matched files are already laid out as the torrent expects.
+    DirectSeed,
+    /// The matched files need to be symlinked into a new directory first.
+    Symlink,
 }
 
-trait CheckWithFileMapping {
-    fn check(&self, mapping: &HashMap<&Path, &Path>) -> Result<bool>;
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub(super) struct Link {
+    pub(super) link: PathBuf,
+    pub(super) target: PathBuf,
 }
 
-impl CheckWithFileMapping for torrent::Piece {
-    fn check(&self, mapping: &HashMap<&Path, &Path>) -> Result<bool> {
-        let mut sha1 = Sha1::new();
-        for slice in &self.file_slices {
-            let file = File::open(
-                mapping
-                    .get::<Path>(slice.path.as_ref())
-                    .ok_or_else(|| anyhow!("no mapping for {}", slice.path.display()))?,
-            )?;
-            let mut buffer = vec![0; slice.length.try_into()?];
-            let bytes_read = rustix::io::pread(file, &mut buffer, slice.offset)?;
-            if bytes_read as u64 != slice.length {
-                bail!(
-                    "pread failed for {}: read {} bytes at offset {} instead of {} bytes",
-                    slice.path.display(),
-                    bytes_read,
-                    slice.offset,
-                    slice.length
-                );
+/// Everything needed to import a matched torrent, computed before touching the filesystem.
+#[derive(Serialize)]
+struct ImportPlan {
+    torrent: PathBuf,
+    action: Action,
+    seed_dir: PathBuf,
+    links: Vec<Link>,
+    /// Whether the torrent should be added to the client without starting it.
+    paused: bool,
+    /// `seed_dir` as the client sees it, if --path-map translated it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_seed_dir: Option<PathBuf>,
+}
+
+/// How far `ImportPlan::apply` got, so that a failure part way through can still be recorded.
+#[derive(Default)]
+struct Applied {
+    /// How many of the plan's links were created, in order.
+    links: usize,
+    added: bool,
+}
+
+impl ImportPlan {
+    fn apply(&self, dry_run: bool, skip_add: bool, applied: &mut Applied) -> Result<()> {
+        match self.action {
+            Action::DirectSeed => util::info!(
+                "torrent can be directly seeded from {}",
+                self.seed_dir.display()
+            ),
+            Action::Symlink => util::info!(
+                "{} {}",
+                style("found matches with different filenames; creating symlinks in").blue(),
+                self.seed_dir.display()
+            ),
+        }
+        let fs = fs::new_instance(dry_run);
+        for link in &self.links {
+            if let Some(parent) = link.link.parent() {
+                fs.create_dir_all(parent)?;
             }
-            sha1.update(&buffer);
+            fs.symlink(&link.target, &link.link)?;
+            applied.links += 1;
+        }
+        if !skip_add {
+            let seed_dir = self.client_seed_dir.as_deref().unwrap_or(&self.seed_dir);
+            sycli::new_instance(dry_run).add_torrent(&self.torrent, seed_dir, self.paused)?;
+            applied.added = true;
         }
-        Ok(sha1.digest().bytes() == self.hash.bytes())
+        Ok(())
+    }
+}
+
+/// A line of the --manifest file, recording what importing a single torrent changed.
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub(super) struct ManifestRecord {
+    /// The imported torrent file, or `-` if it was read from stdin.
+    pub(super) torrent: PathBuf,
+    pub(super) info_hash: String,
+    pub(super) action: Action,
+    pub(super) seed_dir: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) client_seed_dir: Option<PathBuf>,
+    /// The symlinks that were actually created, which may be fewer than planned on failure.
+    pub(super) links: Vec<Link>,
+    /// Whether the torrent was added to the client.
+    pub(super) added: bool,
+    /// Why the import failed part way through, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(super) error: Option<String>,
+}
+
+/// The --manifest file, opened for appending so that records from earlier runs are kept.
+struct Manifest(std::fs::File);
+
+impl Manifest {
+    fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("unable to open manifest {}", path.display()))?;
+        Ok(Manifest(file))
     }
+
+    /// Appends `record` as a single line.
+    fn append(&self, record: &ManifestRecord) -> Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        (&self.0).write_all(&line)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Default, ValueEnum)]
+enum Layout {
+    /// `<symlink-dir>/<announce host>`, e.g. `links/tracker.example.com`.
+    #[default]
+    Host,
+    /// `<symlink-dir>` itself.
+    Flat,
+    /// `<symlink-dir>/<tracker name>`, using `tracker_names` in config.toml.
+    TrackerName,
 }
 
 trait CrossSeed {
-    fn base_dir(&self, target_dir: &Path) -> Result<PathBuf>;
-    fn cross_seed(
+    fn base_dir(&self, target_dir: &Path, layout: Layout) -> Result<PathBuf>;
+    fn plan_cross_seed(
         &self,
-        dry_run: bool,
-        skip_add: bool,
         path: &Path,
         target_dir: &Path,
+        layout: Layout,
         candidates: &HashMap<&Path, &Path>,
-    ) -> Result<()>;
+    ) -> Result<ImportPlan>;
 }
 
 impl CrossSeed for torrent::Torrent {
-    fn base_dir(&self, target_dir: &Path) -> Result<PathBuf> {
-        Ok(target_dir.join(
-            url::Url::parse(&self.announce)?
-                .host_str()
-                .ok_or_else(|| anyhow!("announce URL {} has no hostname", self.announce))?,
-        ))
+    fn base_dir(&self, target_dir: &Path, layout: Layout) -> Result<PathBuf> {
+        let host = self
+            .announce
+            .as_deref()
+            .and_then(|announce| util::url_host(announce).ok());
+        match (layout, host) {
+            (Layout::Flat, _) => Ok(target_dir.to_path_buf()),
+            // Trackerless torrents have no host to group by, so they all share one directory.
+            (Layout::Host | Layout::TrackerName, None) => Ok(target_dir.join(
+                crate::config::config()
+                    .dht_dir
+                    .as_deref()
+                    .unwrap_or(DEFAULT_DHT_DIR),
+            )),
+            (Layout::Host, Some(host)) => Ok(target_dir.join(host)),
+            (Layout::TrackerName, Some(host)) => {
+                let Some(name) = crate::config::config().tracker_names.get(&host) else {
+                    bail!("no tracker name for {host}; add one to tracker_names in config.toml");
+                };
+                Ok(target_dir.join(name))
+            }
+        }
     }
 
-    fn cross_seed(
+    fn plan_cross_seed(
         &self,
-        dry_run: bool,
-        skip_add: bool,
         path: &Path,
         target_dir: &Path,
+        layout: Layout,
         candidates: &HashMap<&Path, &Path>,
-    ) -> Result<()> {
-        if self.info.is_single_file {
-            let (source, target) = candidates.iter().next().unwrap();
-            return if *source == target.file_name().unwrap() {
-                println!(
-                    "torrent can be directly seeded from {}",
-                    target.parent().unwrap().display()
-                );
-                if !skip_add {
-                    sycli::new_instance(dry_run).add_torrent(path, target.parent().unwrap())?;
-                }
-                Ok(())
-            } else {
-                let base_dir = self.base_dir(target_dir)?;
-                println!(
-                    "{} {}",
-                    style("found matches with different filenames; creating symlinks in").blue(),
-                    base_dir.display()
-                );
-                let fs = fs::new_instance(dry_run);
-                fs.create_dir_all(&base_dir)?;
-                fs.symlink(target, &base_dir.join(source))?;
-                if !skip_add {
-                    sycli::new_instance(dry_run).add_torrent(path, &base_dir)
-                } else {
-                    Ok(())
-                }
-            };
-        }
-
+    ) -> Result<ImportPlan> {
+        let direct_seed = |seed_dir: &Path| ImportPlan {
+            torrent: path.to_path_buf(),
+            action: Action::DirectSeed,
+            seed_dir: seed_dir.to_path_buf(),
+            links: vec![],
+            paused: false,
+            client_seed_dir: None,
+        };
         // Check if symlinks are needed at all; if the same prefix can be used for all selected
-        // candidate paths, then a symlink is sufficient.
+        // candidate paths, then a symlink is sufficient. This also covers single-file torrents,
+        // including ones whose name has multiple path components.
         let path_prefix: HashSet<Option<PathBuf>> = candidates
             .iter()
             .map(|(source, target)| target.remove_common_suffix(source))
             .collect();
         if !path_prefix.contains(&None) && path_prefix.len() == 1 {
             let seed_path = path_prefix.into_iter().next().unwrap().unwrap();
-            println!(
-                "torrent can be directly seeded from {}",
-                seed_path.display()
-            );
-            if !skip_add {
-                sycli::new_instance(dry_run).add_torrent(path, &seed_path)?;
-            }
-            return Ok(());
+            return Ok(direct_seed(&seed_path));
         }
-        let base_dir = self.base_dir(target_dir)?;
-        println!(
-            "{} {}",
-            style("found matches with different filenames; creating symlinks in").blue(),
-            base_dir.display()
-        );
-        let fs = fs::new_instance(dry_run);
-        for (source_path, target_path) in candidates {
-            if let Some(parent) = source_path.parent() {
-                fs.create_dir_all(&base_dir.join(parent))?;
-            }
-            fs.symlink(target_path, &base_dir.join(source_path))?;
-        }
-        if !skip_add {
-            sycli::new_instance(dry_run).add_torrent(path, &base_dir)?;
-        }
-
-        Ok(())
+        let base_dir = self.base_dir(target_dir, layout)?;
+        let mut links = candidates
+            .iter()
+            .map(|(source_path, target_path)| Link {
+                link: base_dir.join(source_path),
+                target: target_path.to_path_buf(),
+            })
+            .collect::<Vec<_>>();
+        links.sort_by(|a, b| a.link.cmp(&b.link));
+        Ok(ImportPlan {
+            torrent: path.to_path_buf(),
+            action: Action::Symlink,
+            seed_dir: base_dir,
+            links,
+            paused: false,
+            client_seed_dir: None,
+        })
     }
 }
 
@@ -319,20 +922,18 @@ impl PathHelper for Path {
     }
 }
 
-fn get_best_candidate<'a, P, Q>(
+pub(super) fn get_best_candidate<'a, 'b, Q>(
     path: &'a Path,
-    candidates: &'a [P],
+    candidates: &[&'b Path],
     preferred_prefix: Option<&Q>,
-) -> Option<(&'a Path, &'a Path)>
+) -> Option<(&'a Path, &'b Path)>
 where
-    P: AsRef<Path> + Ord,
     Q: AsRef<Path> + ?Sized,
 {
     let candidate = candidates
         .iter()
         .map(|candidate| {
             let common_suffix = candidate
-                .as_ref()
                 .iter()
                 .rev()
                 .zip(path.iter().rev())
@@ -340,38 +941,55 @@ where
                 .count();
             let common_prefix = preferred_prefix.as_ref().map_or(0, |path| {
                 candidate
-                    .as_ref()
                     .iter()
                     .zip(path.as_ref().iter())
                     .take_while(|(x, y)| x == y)
                     .count()
             });
-            (common_suffix, common_prefix, candidate)
+            trace!(
+                candidate = %candidate.display(),
+                common_suffix,
+                common_prefix,
+                "scored candidate"
+            );
+            (common_suffix, common_prefix, *candidate)
         })
         .max()?;
-    Some((path, candidate.2.as_ref()))
+    Some((path, candidate.2))
 }
 
+/// Picks the best candidate for each file. Candidates under `preferred_prefix` are favored; if it
+/// is unset, the prefix is guessed instead.
 fn pick_candidates<'a>(
-    candidates: HashMap<(&'a PathBuf, u64), &'a Vec<PathBuf>>,
+    candidates: HashMap<(&'a PathBuf, u64), Vec<&'a Path>>,
+    preferred_prefix: Option<&Path>,
 ) -> HashMap<&'a Path, &'a Path> {
     // Heuristic: If the file with the largest size has a single unique match, prefer matches that
     // share a common prefix.
-    let largest_file_candidate_path = candidates
-        .iter()
-        .max_by_key(|((_path, len), _candidates)| len)
-        .and_then(|(_, candidates)| {
-            if candidates.len() == 1 {
-                candidates.iter().next()
-            } else {
-                None
-            }
-        });
+    let preferred_prefix = preferred_prefix.or_else(|| {
+        candidates
+            .iter()
+            .max_by_key(|((_path, len), _candidates)| len)
+            .and_then(|(_, candidates)| {
+                if candidates.len() == 1 {
+                    candidates.first().copied()
+                } else {
+                    None
+                }
+            })
+    });
     // TODO: This doesn't prevent duplicate assignments, which is probably not desirable.
     candidates
         .into_iter()
         .map(|((path, _len), candidates)| {
-            get_best_candidate(path, candidates, largest_file_candidate_path.as_ref()).unwrap()
+            let best = get_best_candidate(path, &candidates, preferred_prefix).unwrap();
+            debug!(
+                path = %path.display(),
+                candidates = candidates.len(),
+                picked = %best.1.display(),
+                "picked candidate"
+            );
+            best
         })
         .collect()
 }
@@ -380,6 +998,53 @@ fn pick_candidates<'a>(
 mod tests {
     use super::*;
 
+    #[test]
+    fn parse_path_map_validates() {
+        assert_eq!(
+            parse_path_map("/mnt/data:/data"),
+            Ok(PathMap {
+                from: PathBuf::from("/mnt/data"),
+                to: PathBuf::from("/data"),
+            })
+        );
+        assert!(parse_path_map("/mnt/data").is_err());
+        assert!(parse_path_map(":/data").is_err());
+        assert!(parse_path_map("/mnt/data:").is_err());
+    }
+
+    #[test]
+    fn apply_path_map_translates_prefix() {
+        let path_map = [parse_path_map("/mnt/data:/data").unwrap()];
+        assert_eq!(
+            apply_path_map(Path::new("/mnt/data/show"), &path_map),
+            Some(PathBuf::from("/data/show"))
+        );
+        assert_eq!(
+            apply_path_map(Path::new("/mnt/data"), &path_map),
+            Some(PathBuf::from("/data"))
+        );
+        // Prefixes are matched by component, not by string.
+        assert_eq!(apply_path_map(Path::new("/mnt/database"), &path_map), None);
+        assert_eq!(apply_path_map(Path::new("/other"), &path_map), None);
+        assert_eq!(apply_path_map(Path::new("/other"), &[]), None);
+    }
+
+    #[test]
+    fn apply_path_map_prefers_longest_prefix() {
+        let path_map = [
+            parse_path_map("/mnt/data/links:/links").unwrap(),
+            parse_path_map("/mnt:/host").unwrap(),
+        ];
+        assert_eq!(
+            apply_path_map(Path::new("/mnt/data/links/a"), &path_map),
+            Some(PathBuf::from("/links/a"))
+        );
+        assert_eq!(
+            apply_path_map(Path::new("/mnt/data/show"), &path_map),
+            Some(PathBuf::from("/host/data/show"))
+        );
+    }
+
     #[test]
     fn remove_common_suffix_all_unique() {
         // Absolute
@@ -441,7 +1106,7 @@ mod tests {
     #[test]
     fn get_best_candidate_single_option() {
         assert_eq!(
-            get_best_candidate(Path::new("b/c"), &vec![Path::new("/a/b/c")], None::<&Path>),
+            get_best_candidate(Path::new("b/c"), &[Path::new("/a/b/c")], None::<&Path>),
             Some((Path::new("b/c"), Path::new("/a/b/c")))
         );
 
@@ -449,7 +1114,7 @@ mod tests {
         assert_eq!(
             get_best_candidate(
                 Path::new("b/c"),
-                &vec![Path::new("/a/b/c")],
+                &[Path::new("/a/b/c")],
                 Some(&Path::new("/a2/b2/c2"))
             ),
             Some((Path::new("b/c"), Path::new("/a/b/c")))
@@ -458,7 +1123,7 @@ mod tests {
         assert_eq!(
             get_best_candidate(
                 Path::new("b/c"),
-                &vec![Path::new("/a/b/c")],
+                &[Path::new("/a/b/c")],
                 Some(&Path::new("/a/b/c"))
             ),
             Some((Path::new("b/c"), Path::new("/a/b/c")))
@@ -470,7 +1135,7 @@ mod tests {
         assert_eq!(
             get_best_candidate(
                 Path::new("b/c"),
-                &vec![Path::new("/a/b/c"), Path::new("/a2/b/c")],
+                &[Path::new("/a/b/c"), Path::new("/a2/b/c")],
                 Some(&Path::new("/a"))
             ),
             Some((Path::new("b/c"), Path::new("/a/b/c")))
@@ -479,7 +1144,7 @@ mod tests {
         assert_eq!(
             get_best_candidate(
                 Path::new("b/c"),
-                &vec![Path::new("/a/b/c"), Path::new("/a2/b/c")],
+                &[Path::new("/a/b/c"), Path::new("/a2/b/c")],
                 Some(&Path::new("/a/b"))
             ),
             Some((Path::new("b/c"), Path::new("/a/b/c")))
@@ -488,7 +1153,7 @@ mod tests {
         assert_eq!(
             get_best_candidate(
                 Path::new("b/c"),
-                &vec![Path::new("/a/b/c"), Path::new("/a2/b/c")],
+                &[Path::new("/a/b/c"), Path::new("/a2/b/c")],
                 Some(&Path::new("/a/b2"))
             ),
             Some((Path::new("b/c"), Path::new("/a/b/c")))
@@ -502,7 +1167,7 @@ mod tests {
         assert_eq!(
             get_best_candidate(
                 Path::new("b/c"),
-                &vec![Path::new("/a/b/c"), Path::new("/a2/b/c")],
+                &[Path::new("/a/b/c"), Path::new("/a2/b/c")],
                 Some(&Path::new("/e"))
             ),
             Some((Path::new("b/c"), Path::new("/a2/b/c")))
@@ -514,7 +1179,7 @@ mod tests {
         assert_eq!(
             get_best_candidate(
                 Path::new("b/c"),
-                &vec![Path::new("/a/b/c"), Path::new("/a/b2/c")],
+                &[Path::new("/a/b/c"), Path::new("/a/b2/c")],
                 None::<&Path>,
             ),
             Some((Path::new("b/c"), Path::new("/a/b/c")))
@@ -528,7 +1193,7 @@ mod tests {
         assert_eq!(
             get_best_candidate(
                 Path::new("b/c"),
-                &vec![Path::new("/a/b/c"), Path::new("/a2/b/c")],
+                &[Path::new("/a/b/c"), Path::new("/a2/b/c")],
                 None::<&Path>,
             ),
             Some((Path::new("b/c"), Path::new("/a2/b/c")))
@@ -540,10 +1205,438 @@ mod tests {
         assert_eq!(
             get_best_candidate(
                 Path::new("b/c"),
-                &vec![Path::new("/a/b/c"), Path::new("/a/b2/c")],
+                &[Path::new("/a/b/c"), Path::new("/a/b2/c")],
                 Some(&Path::new("/a/b2"))
             ),
             Some((Path::new("b/c"), Path::new("/a/b/c")))
         );
     }
+
+    #[test]
+    fn enumerate_files_with_sizes_excludes() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        std::fs::create_dir_all(tmp_dir.path().join(".Trash-1000/files"))
+            .expect("failed to create test dir");
+        std::fs::write(tmp_dir.path().join("kept"), "1").expect("failed to create test file");
+        std::fs::write(tmp_dir.path().join("skipped.part"), "22")
+            .expect("failed to create test file");
+        std::fs::write(tmp_dir.path().join(".Trash-1000/files/trashed"), "333")
+            .expect("failed to create test file");
+
+        let mut exclude = GlobSetBuilder::new();
+        exclude.add(Glob::new("**/.Trash-*").unwrap());
+        exclude.add(Glob::new("*.part").unwrap());
+        let (entries, errors) =
+            enumerate_files_with_sizes(&[tmp_dir.path()], &exclude.build().unwrap(), false, None);
+        assert!(errors.is_empty());
+        assert_eq!(
+            entries,
+            BTreeMap::from([(1, vec![tmp_dir.path().join("kept")])])
+        );
+    }
+
+    #[test]
+    fn enumerate_files_with_sizes_respects_max_depth() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        std::fs::create_dir_all(tmp_dir.path().join("snapshots/daily"))
+            .expect("failed to create test dir");
+        std::fs::write(tmp_dir.path().join("kept"), "1").expect("failed to create test file");
+        std::fs::write(tmp_dir.path().join("snapshots/daily/old"), "22")
+            .expect("failed to create test file");
+
+        let (entries, errors) =
+            enumerate_files_with_sizes(&[tmp_dir.path()], &GlobSet::empty(), false, Some(2));
+        assert!(errors.is_empty());
+        assert_eq!(
+            entries,
+            BTreeMap::from([(1, vec![tmp_dir.path().join("kept")])])
+        );
+    }
+
+    #[test]
+    fn enumerate_files_with_sizes_follows_symlinks() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let real = tmp_dir.path().join("real");
+        let library = tmp_dir.path().join("library");
+        std::fs::create_dir_all(&real).expect("failed to create test dir");
+        std::fs::create_dir_all(&library).expect("failed to create test dir");
+        std::fs::write(real.join("file"), "1").expect("failed to create test file");
+        std::os::unix::fs::symlink(real.join("file"), library.join("link"))
+            .expect("failed to create symlink");
+        std::os::unix::fs::symlink(real.join("file"), library.join("other link"))
+            .expect("failed to create symlink");
+        std::os::unix::fs::symlink(&library, library.join("loop"))
+            .expect("failed to create symlink");
+
+        let (entries, errors) =
+            enumerate_files_with_sizes(&[&library], &GlobSet::empty(), false, None);
+        assert!(entries.is_empty());
+        assert!(errors.is_empty());
+
+        let canonical = real.join("file").canonicalize().unwrap();
+        let (entries, errors) =
+            enumerate_files_with_sizes(&[&library, &real], &GlobSet::empty(), true, None);
+        assert_eq!(entries, BTreeMap::from([(1, vec![canonical])]));
+        assert!(errors.is_empty(), "symlink loops are not errors");
+    }
+
+    #[test]
+    fn enumerate_files_with_sizes_collects_errors() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        std::fs::write(tmp_dir.path().join("file"), "1").expect("failed to create test file");
+        std::os::unix::fs::symlink(
+            tmp_dir.path().join("missing"),
+            tmp_dir.path().join("dangling"),
+        )
+        .expect("failed to create symlink");
+
+        let (entries, errors) =
+            enumerate_files_with_sizes(&[tmp_dir.path()], &GlobSet::empty(), true, None);
+        assert_eq!(entries.values().flatten().count(), 1);
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn sample_pieces_covers_every_file() {
+        // One large file followed by several tiny ones that share the large file's last piece and
+        // the pieces after it, so the tiny files are easy to leave untested.
+        let piece_length = 16;
+        let mut files = vec![torrent::File {
+            length: 10 * piece_length,
+            path: "big".into(),
+        }];
+        files.extend((0..8).map(|i| torrent::File {
+            length: 5,
+            path: format!("small{i}").into(),
+        }));
+        let pieces = torrent::compute_file_slices(&files, piece_length)
+            .into_iter()
+            .map(|file_slices| torrent::Piece {
+                hash: torrent::Digest::default(),
+                file_slices,
+            })
+            .collect::<Vec<_>>();
+
+        for per_file in [1, 2, 3] {
+            for seed in 0..20 {
+                let sampled = sample_pieces(&pieces, per_file, &mut StdRng::seed_from_u64(seed));
+                assert_eq!(
+                    sampled.iter().collect::<HashSet<_>>().len(),
+                    sampled.len(),
+                    "pieces are distinct"
+                );
+                for file in &files {
+                    let available = pieces
+                        .iter()
+                        .filter(|piece| piece.file_slices.iter().any(|s| s.path == file.path))
+                        .count();
+                    let tested = sampled
+                        .iter()
+                        .filter(|piece| piece.file_slices.iter().any(|s| s.path == file.path))
+                        .count();
+                    assert!(
+                        tested >= std::cmp::min(per_file, available),
+                        "{} has {tested} of {available} pieces tested with per_file {per_file}",
+                        file.path.display()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pending_torrents_waits_for_settle() {
+        let start = Instant::now();
+        let mut pending = PendingTorrents::default();
+        pending.touch(PathBuf::from("/watch/a.torrent"), start);
+        pending.touch(PathBuf::from("/watch/.a.torrent.swp"), start);
+        pending.touch(PathBuf::from("/watch/.hidden.torrent"), start);
+        pending.touch(PathBuf::from("/watch/b.torrent.part"), start);
+        pending.touch(PathBuf::from("/watch/b.torrent"), start);
+        assert!(
+            pending
+                .take_settled(start + Duration::from_secs(1))
+                .is_empty()
+        );
+
+        // Another write restarts the clock for that file only.
+        pending.touch(
+            PathBuf::from("/watch/b.torrent"),
+            start + Duration::from_secs(1),
+        );
+        assert_eq!(
+            pending.take_settled(start + WATCH_SETTLE_TIME),
+            vec![PathBuf::from("/watch/a.torrent")]
+        );
+        assert!(pending.take_settled(start + WATCH_SETTLE_TIME).is_empty());
+        assert_eq!(
+            pending.take_settled(start + Duration::from_secs(1) + WATCH_SETTLE_TIME),
+            vec![PathBuf::from("/watch/b.torrent")]
+        );
+    }
+
+    #[test]
+    fn pick_candidates_prefer_prefix_overrides_heuristic() {
+        let big = PathBuf::from("show/big.mkv");
+        let small = PathBuf::from("show/small.srt");
+        let candidates = || {
+            HashMap::from([
+                ((&big, 1000), vec![Path::new("/old/show/big.mkv")]),
+                (
+                    (&small, 10),
+                    vec![
+                        Path::new("/new/show/small.srt"),
+                        Path::new("/old/show/small.srt"),
+                    ],
+                ),
+            ])
+        };
+
+        // Without an override, the unique match for the largest file decides.
+        let picked = pick_candidates(candidates(), None);
+        assert_eq!(picked[small.as_path()], Path::new("/old/show/small.srt"));
+
+        let picked = pick_candidates(candidates(), Some(Path::new("/new")));
+        assert_eq!(picked[small.as_path()], Path::new("/new/show/small.srt"));
+        assert_eq!(picked[big.as_path()], Path::new("/old/show/big.mkv"));
+    }
+
+    fn make_torrent(is_single_file: bool, paths: &[&str]) -> torrent::Torrent {
+        torrent::Torrent {
+            announce: Some("https://tracker.example.com/announce".to_string()),
+            creation_date: None,
+            created_by: None,
+            comment: None,
+            url_list: vec![],
+            info: torrent::Info {
+                files: paths
+                    .iter()
+                    .map(|path| torrent::File {
+                        length: 1,
+                        path: path.into(),
+                    })
+                    .collect(),
+                is_single_file,
+                name: String::new(),
+                piece_length: 1,
+                pieces: vec![],
+                private: false,
+                source: None,
+            },
+        }
+    }
+
+    #[test]
+    fn base_dir_without_announce() {
+        let mut torrent = make_torrent(false, &["show/a"]);
+        let target_dir = Path::new("/links");
+        assert_eq!(
+            torrent.base_dir(target_dir, Layout::Host).unwrap(),
+            Path::new("/links/tracker.example.com")
+        );
+        for announce in [None, Some(String::new()), Some("not a url".to_string())] {
+            torrent.announce = announce;
+            assert_eq!(
+                torrent.base_dir(target_dir, Layout::Host).unwrap(),
+                Path::new("/links/_dht")
+            );
+            assert_eq!(
+                torrent.base_dir(target_dir, Layout::TrackerName).unwrap(),
+                Path::new("/links/_dht")
+            );
+            assert_eq!(
+                torrent.base_dir(target_dir, Layout::Flat).unwrap(),
+                Path::new("/links")
+            );
+        }
+    }
+
+    #[test]
+    fn plan_cross_seed_direct_seed() {
+        let torrent = make_torrent(false, &["show/a", "show/b"]);
+        let candidates = HashMap::from([
+            (Path::new("show/a"), Path::new("/data/show/a")),
+            (Path::new("show/b"), Path::new("/data/show/b")),
+        ]);
+        let plan = torrent
+            .plan_cross_seed(
+                Path::new("t.torrent"),
+                Path::new("/links"),
+                Layout::Host,
+                &candidates,
+            )
+            .unwrap();
+        assert!(matches!(plan.action, Action::DirectSeed));
+        assert_eq!(plan.seed_dir, Path::new("/data"));
+        assert!(plan.links.is_empty());
+    }
+
+    #[test]
+    fn plan_cross_seed_symlink() {
+        let torrent = make_torrent(false, &["show/a", "show/b"]);
+        let candidates = HashMap::from([
+            (Path::new("show/b"), Path::new("/other/b.mkv")),
+            (Path::new("show/a"), Path::new("/data/show/a")),
+        ]);
+        let plan = torrent
+            .plan_cross_seed(
+                Path::new("t.torrent"),
+                Path::new("/links"),
+                Layout::Host,
+                &candidates,
+            )
+            .unwrap();
+        assert!(matches!(plan.action, Action::Symlink));
+        assert_eq!(plan.seed_dir, Path::new("/links/tracker.example.com"));
+        assert_eq!(
+            serde_json::to_value(&plan.links).unwrap(),
+            serde_json::json!([
+                {"link": "/links/tracker.example.com/show/a", "target": "/data/show/a"},
+                {"link": "/links/tracker.example.com/show/b", "target": "/other/b.mkv"},
+            ])
+        );
+    }
+
+    #[test]
+    fn plan_cross_seed_symlink_flat_layout() {
+        let torrent = make_torrent(false, &["show/a", "show/b"]);
+        let candidates = HashMap::from([
+            (Path::new("show/b"), Path::new("/other/b.mkv")),
+            (Path::new("show/a"), Path::new("/data/show/a")),
+        ]);
+        let plan = torrent
+            .plan_cross_seed(
+                Path::new("t.torrent"),
+                Path::new("/links"),
+                Layout::Flat,
+                &candidates,
+            )
+            .unwrap();
+        assert_eq!(plan.seed_dir, Path::new("/links"));
+        assert_eq!(plan.links[0].link, Path::new("/links/show/a"));
+    }
+
+    #[test]
+    fn plan_cross_seed_single_file() {
+        let torrent = make_torrent(true, &["file.mkv"]);
+        let candidates = HashMap::from([(Path::new("file.mkv"), Path::new("/data/file.mkv"))]);
+        let plan = torrent
+            .plan_cross_seed(
+                Path::new("t.torrent"),
+                Path::new("/links"),
+                Layout::Host,
+                &candidates,
+            )
+            .unwrap();
+        assert!(matches!(plan.action, Action::DirectSeed));
+        assert_eq!(plan.seed_dir, Path::new("/data"));
+
+        let candidates = HashMap::from([(Path::new("file.mkv"), Path::new("/data/other.mkv"))]);
+        let plan = torrent
+            .plan_cross_seed(
+                Path::new("t.torrent"),
+                Path::new("/links"),
+                Layout::Host,
+                &candidates,
+            )
+            .unwrap();
+        assert!(matches!(plan.action, Action::Symlink));
+        assert_eq!(
+            plan.links[0].link,
+            Path::new("/links/tracker.example.com/file.mkv")
+        );
+    }
+
+    #[test]
+    fn plan_cross_seed_single_file_with_subdirectory() {
+        let torrent = make_torrent(true, &["dir/file.mkv"]);
+        let candidates =
+            HashMap::from([(Path::new("dir/file.mkv"), Path::new("/data/dir/file.mkv"))]);
+        let plan = torrent
+            .plan_cross_seed(
+                Path::new("t.torrent"),
+                Path::new("/links"),
+                Layout::Host,
+                &candidates,
+            )
+            .unwrap();
+        assert!(matches!(plan.action, Action::DirectSeed));
+        assert_eq!(plan.seed_dir, Path::new("/data"));
+
+        let candidates = HashMap::from([(Path::new("dir/file.mkv"), Path::new("/data/file.mkv"))]);
+        let plan = torrent
+            .plan_cross_seed(
+                Path::new("t.torrent"),
+                Path::new("/links"),
+                Layout::Host,
+                &candidates,
+            )
+            .unwrap();
+        assert!(matches!(plan.action, Action::Symlink));
+        assert_eq!(
+            plan.links[0].link,
+            Path::new("/links/tracker.example.com/dir/file.mkv")
+        );
+    }
+
+    #[test]
+    fn apply_records_links_created_before_failure() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let seed_dir = tmp_dir.path().join("links");
+        std::fs::create_dir_all(&seed_dir).expect("failed to create test dir");
+        // The second link already exists, so creating it fails.
+        std::fs::write(seed_dir.join("b"), "").expect("failed to create test file");
+        let plan = ImportPlan {
+            torrent: "t.torrent".into(),
+            action: Action::Symlink,
+            seed_dir: seed_dir.clone(),
+            links: ["a", "b", "c"]
+                .map(|name| Link {
+                    link: seed_dir.join(name),
+                    target: tmp_dir.path().join(name),
+                })
+                .into(),
+            paused: false,
+            client_seed_dir: None,
+        };
+        let mut applied = Applied::default();
+        assert!(plan.apply(false, true, &mut applied).is_err());
+        assert_eq!(applied.links, 1);
+        assert!(!applied.added);
+        assert!(seed_dir.join("a").is_symlink());
+    }
+
+    #[test]
+    fn manifest_appends_records() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tmp_dir.path().join("manifest.jsonl");
+        let record = |info_hash: &str| ManifestRecord {
+            torrent: "/torrents/t.torrent".into(),
+            info_hash: info_hash.into(),
+            action: Action::Symlink,
+            seed_dir: "/links/tracker.example.com".into(),
+            client_seed_dir: None,
+            links: vec![Link {
+                link: "/links/tracker.example.com/show/a".into(),
+                target: "/data/show/a".into(),
+            }],
+            added: true,
+            error: None,
+        };
+        Manifest::open(&path)
+            .unwrap()
+            .append(&record("aaaa"))
+            .unwrap();
+        // Reopening keeps the existing records.
+        Manifest::open(&path)
+            .unwrap()
+            .append(&record("bbbb"))
+            .unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let records = contents
+            .lines()
+            .map(|line| serde_json::from_str::<ManifestRecord>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(records, [record("aaaa"), record("bbbb")]);
+    }
 }
@@ -0,0 +1,129 @@
+use clap::Args;
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::torrent;
+use crate::util;
+
+#[derive(Args)]
+pub struct InspectArgs {
+    /// Torrent file to inspect, or `-` to read it from stdin.
+    torrent: PathBuf,
+
+    /// Print the metadata as JSON instead of human-readable text.
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct Metadata {
+    name: String,
+    announce: Option<String>,
+    info_hash: String,
+    creation_date: Option<i64>,
+    created_by: Option<String>,
+    comment: Option<String>,
+    url_list: Vec<String>,
+    private: bool,
+    source: Option<String>,
+    single_file: bool,
+    size: u64,
+    piece_length: u64,
+    piece_count: usize,
+    files: Vec<File>,
+}
+
+#[derive(Serialize)]
+struct File {
+    path: PathBuf,
+    length: u64,
+}
+
+impl InspectArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        let bytes = util::read_input(&self.torrent)?;
+        let info_hash = torrent::info_hash(&bytes)?;
+        let torrent: torrent::Torrent = serde_bencode::from_bytes(&bytes)?;
+        let metadata = Metadata {
+            name: torrent.info.name,
+            announce: torrent.announce,
+            info_hash: info_hash.to_string(),
+            creation_date: torrent.creation_date,
+            created_by: torrent.created_by,
+            comment: torrent.comment,
+            url_list: torrent.url_list,
+            private: torrent.info.private,
+            source: torrent.info.source,
+            single_file: torrent.info.is_single_file,
+            size: torrent.info.files.iter().map(|file| file.length).sum(),
+            piece_length: torrent.info.piece_length,
+            piece_count: torrent.info.pieces.len(),
+            files: torrent
+                .info
+                .files
+                .into_iter()
+                .map(|file| File {
+                    path: file.path,
+                    length: file.length,
+                })
+                .collect(),
+        };
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&metadata)?);
+            return Ok(());
+        }
+
+        println!("name:         {}", metadata.name);
+        if let Some(announce) = &metadata.announce {
+            println!("announce:     {announce}");
+        }
+        println!("info hash:    {}", metadata.info_hash);
+        if let Some(creation_date) = metadata.creation_date {
+            println!("created on:   {}", util::format_timestamp(creation_date));
+        }
+        if let Some(created_by) = &metadata.created_by {
+            println!("created by:   {created_by}");
+        }
+        if let Some(comment) = &metadata.comment {
+            println!("comment:      {comment}");
+        }
+        for url in &metadata.url_list {
+            println!("web seed:     {url}");
+        }
+        println!(
+            "private:      {}",
+            if metadata.private { "yes" } else { "no" }
+        );
+        if let Some(source) = &metadata.source {
+            println!("source:       {source}");
+        }
+        println!(
+            "size:         {} ({} bytes)",
+            util::format_bytes(metadata.size),
+            metadata.size
+        );
+        println!(
+            "piece length: {}",
+            util::format_bytes(metadata.piece_length)
+        );
+        println!("pieces:       {}", metadata.piece_count);
+        println!(
+            "files:        {}{}",
+            metadata.files.len(),
+            if metadata.single_file {
+                " (single file)"
+            } else {
+                ""
+            }
+        );
+        for file in &metadata.files {
+            println!(
+                "  {:>10}  {}",
+                util::format_bytes(file.length),
+                file.path.display()
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+use clap::{Args, ValueEnum};
+use console::style;
+use serde::Serialize;
+use std::path::PathBuf;
+
+use crate::sycli;
+use crate::util;
+
+#[derive(Args)]
+pub struct ListArgs {
+    /// How to sort the listed torrents.
+    #[arg(default_value = "name", long, value_enum)]
+    sort: SortKey,
+
+    /// Print the torrents as JSON instead of human-readable text.
+    #[arg(long)]
+    json: bool,
+
+    /// Only list torrents with this label.
+    #[arg(long)]
+    label: Option<String>,
+
+    /// Only include torrents with a tracker whose host contains this string, ignoring case. May
+    /// be specified more than once to match any of several trackers.
+    #[arg(long)]
+    tracker: Vec<String>,
+
+    /// Leave out torrents with a tracker whose host contains this string, ignoring case, even if
+    /// they match --tracker. May be specified more than once.
+    #[arg(long)]
+    exclude_tracker: Vec<String>,
+
+    /// Only list torrents added to the client since this date ("2024-01-31") or this long ago
+    /// ("24h", "7d", "2w"). Torrents whose added date is unknown are left out.
+    #[arg(long, value_parser = util::parse_since)]
+    since: Option<i64>,
+}
+
+#[derive(Copy, Clone, Default, ValueEnum)]
+enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Progress,
+}
+
+#[derive(Serialize)]
+struct ListEntry {
+    id: String,
+    name: String,
+    base_path: PathBuf,
+    size: usize,
+    files: usize,
+    progress: f64,
+    label: Option<String>,
+    /// When the torrent was added to the client, as a Unix timestamp.
+    added: Option<i64>,
+    tracker_errors: Vec<TrackerError>,
+}
+
+#[derive(Serialize)]
+struct TrackerError {
+    url: String,
+    error: String,
+    last_report: Option<String>,
+}
+
+impl From<sycli::Torrent> for ListEntry {
+    fn from(torrent: sycli::Torrent) -> Self {
+        let tracker_errors = torrent
+            .tracker_errors()
+            .map(|tracker| TrackerError {
+                url: tracker.url.clone(),
+                error: tracker.error.clone().unwrap_or_default(),
+                last_report: tracker.last_report.clone(),
+            })
+            .collect();
+        ListEntry {
+            id: torrent.id,
+            name: torrent.name,
+            base_path: torrent.base_path,
+            size: torrent.size,
+            files: torrent.files.len(),
+            progress: torrent.progress,
+            label: torrent.label,
+            added: torrent.added,
+            tracker_errors,
+        }
+    }
+}
+
+impl ListArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        let mut entries = sycli::new_instance(false)
+            .get_torrents()?
+            .into_iter()
+            .filter(|torrent| self.label.is_none() || torrent.label.as_ref() == self.label.as_ref())
+            .filter(|torrent| torrent.matches_tracker_filters(&self.tracker, &self.exclude_tracker))
+            .filter(|torrent| {
+                self.since
+                    .is_none_or(|since| torrent.added.is_some_and(|added| added >= since))
+            })
+            .map(ListEntry::from)
+            .collect::<Vec<_>>();
+        match self.sort {
+            SortKey::Name => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortKey::Size => entries.sort_by_key(|entry| entry.size),
+            SortKey::Progress => entries.sort_by(|a, b| a.progress.total_cmp(&b.progress)),
+        }
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+            return Ok(());
+        }
+
+        println!("{} torrent(s)", entries.len());
+        for entry in entries {
+            println!(
+                "  {} {} ({}, {} file(s), {:.1}%)",
+                entry.id,
+                entry.name,
+                util::format_bytes(entry.size as u64),
+                entry.files,
+                entry.progress * 100.0
+            );
+            println!("    {}", entry.base_path.display());
+            if let Some(label) = &entry.label {
+                println!("    label: {label}");
+            }
+            if let Some(added) = entry.added {
+                println!("    added: {}", util::format_timestamp(added));
+            }
+            for tracker in &entry.tracker_errors {
+                println!(
+                    "    {} {}: {} (last report: {})",
+                    style("tracker error:").red(),
+                    tracker.url,
+                    tracker.error,
+                    tracker.last_report.as_deref().unwrap_or("never")
+                );
+            }
+        }
+        Ok(())
+    }
+}
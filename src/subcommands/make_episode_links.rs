@@ -1,77 +1,360 @@
-use anyhow::anyhow;
-use clap::Args;
+use anyhow::{anyhow, bail};
+use clap::{Args, ValueEnum};
 use dialoguer::Confirm;
+use regex::Regex;
 use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
 use thiserror::Error;
+use tracing::debug;
+use ureq::http::StatusCode;
+
+use crate::util;
 
 #[derive(Args)]
 pub struct MakeEpisodeLinksArgs {
     /// Files to create episode links for.
+    #[arg(required_unless_present = "series_dir")]
     files: Vec<PathBuf>,
 
+    /// Instead of a list of files for a single season, create links for every season of a
+    /// series at once. Each directory directly under this one named like "Season 1", "S02", or
+    /// "Specials" is treated as a season, and links are created next to the files in it.
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["files", "season"])]
+    series_dir: Option<PathBuf>,
+
     /// IMDB API for the title, e.g. tt0245429.
     #[arg(long)]
     imdb_id: String,
 
+    /// Season number for the episodes. Use 0 for specials. Required with season numbering.
     #[arg(long)]
-    season: u32,
+    season: Option<u32>,
+
+    /// Extensions of sidecar files, such as subtitles, that may be mixed in with the episodes.
+    /// The files with each sidecar extension are paired with the episodes by sorted position and
+    /// linked with the same name, e.g. `Title.S01E05.srt` next to `Title.S01E05.mkv`. May be
+    /// specified more than once.
+    #[arg(long = "sidecar", value_name = "EXTENSION", default_values = ["srt", "ass"])]
+    sidecar_extensions: Vec<String>,
+
+    /// Extension to give the links for files that have none, e.g. "mkv". Without this, files
+    /// without an extension are skipped with a warning.
+    #[arg(long, value_name = "EXTENSION")]
+    extension: Option<String>,
+
+    /// Include the year the title started in the link names, e.g. `Title.(2003).S01E05.mkv`,
+    /// which helps media servers tell apart shows with the same name.
+    #[arg(long, conflicts_with = "name_template")]
+    include_year: bool,
+
+    /// Format for the link names instead of the default `Title.S01E05.mkv`, e.g.
+    /// "{title} - S{season}E{episode}.{ext}". Placeholders are {title}, {dotted_title} (the title
+    /// with dots instead of spaces), {year} (empty if unknown), {season} and {episode}
+    /// (zero-padded), and {ext}. A `/` creates subdirectories, e.g.
+    /// "{title}/Season {season}/{title} - S{season}E{episode}.{ext}".
+    #[arg(long, value_parser = parse_name_template)]
+    name_template: Option<NameTemplate>,
+
+    /// How to number the episodes: `Title.S01E01.ext`, or `Title.001.ext` for absolute numbering.
+    #[arg(default_value = "season", long, value_enum)]
+    numbering: Numbering,
+}
+
+#[derive(Copy, Clone, Default, ValueEnum)]
+enum Numbering {
+    #[default]
+    Season,
+    Absolute,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum EpisodeNumber {
+    /// An episode within a season, where season 0 holds the specials.
+    Season { season: u32, episode: u32 },
+    /// An episode numbered from the start of the series, ignoring seasons.
+    Absolute(u32),
+}
+
+/// A link name format from --name-template, split into literal text and placeholders.
+#[derive(Clone, Debug, PartialEq)]
+struct NameTemplate(Vec<TemplatePart>);
+
+#[derive(Clone, Debug, PartialEq)]
+enum TemplatePart {
+    Literal(String),
+    Title,
+    DottedTitle,
+    Year,
+    Season,
+    Episode,
+    Extension,
+}
+
+fn parse_name_template(value: &str) -> Result<NameTemplate, String> {
+    let mut parts = vec![];
+    let mut rest = value;
+    while let Some(start) = rest.find(['{', '}']) {
+        if rest[start..].starts_with('}') {
+            return Err(format!("unmatched }} in {value}"));
+        }
+        if start > 0 {
+            parts.push(TemplatePart::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unmatched {{ in {value}"))?
+            + start;
+        parts.push(match &rest[start + 1..end] {
+            "title" => TemplatePart::Title,
+            "dotted_title" => TemplatePart::DottedTitle,
+            "year" => TemplatePart::Year,
+            "season" => TemplatePart::Season,
+            "episode" => TemplatePart::Episode,
+            "ext" => TemplatePart::Extension,
+            name => {
+                return Err(format!(
+                    "unknown placeholder {{{name}}}; expected one of {{title}}, {{dotted_title}}, \
+                     {{year}}, {{season}}, {{episode}}, or {{ext}}"
+                ));
+            }
+        });
+        rest = &rest[end + 1..];
+    }
+    if !rest.is_empty() {
+        parts.push(TemplatePart::Literal(rest.to_string()));
+    }
+    if !parts.contains(&TemplatePart::Episode) {
+        return Err(format!(
+            "{value} has no {{episode}}, so every episode would get the same name"
+        ));
+    }
+    Ok(NameTemplate(parts))
+}
+
+impl NameTemplate {
+    fn render(&self, result: &OMDbResult, number: EpisodeNumber, extension: &str) -> String {
+        let mut name = String::new();
+        for part in &self.0 {
+            match (part, number) {
+                (TemplatePart::Literal(text), _) => name.push_str(text),
+                (TemplatePart::Title, _) => name.push_str(&strip_unsafe_chars(&result.title)),
+                (TemplatePart::DottedTitle, _) => {
+                    name.push_str(&util::sanitize_title(&result.title))
+                }
+                (TemplatePart::Year, _) => name.push_str(result.first_year().unwrap_or_default()),
+                (TemplatePart::Season, EpisodeNumber::Season { season, .. }) => {
+                    name.push_str(&format!("{season:02}"))
+                }
+                // Checked up front, since absolute numbering has no seasons.
+                (TemplatePart::Season, EpisodeNumber::Absolute(_)) => {}
+                (TemplatePart::Episode, EpisodeNumber::Season { episode, .. }) => {
+                    name.push_str(&format!("{episode:02}"))
+                }
+                (TemplatePart::Episode, EpisodeNumber::Absolute(episode)) => {
+                    name.push_str(&format!("{episode:03}"))
+                }
+                (TemplatePart::Extension, _) => name.push_str(extension),
+            }
+        }
+        name
+    }
+}
+
+/// Drops characters from `title` that are not allowed in file names on common filesystems,
+/// including path separators, but otherwise keeps it as is.
+fn strip_unsafe_chars(title: &str) -> String {
+    title
+        .chars()
+        .filter(|c| !matches!(c, '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+        .filter(|c| !c.is_control())
+        .collect::<String>()
+        .trim()
+        .to_string()
 }
 
 #[derive(Debug, Deserialize)]
-struct OMDbResult {
+pub(super) struct OMDbResult {
     #[serde(rename = "Title")]
-    title: String,
+    pub(super) title: String,
     #[serde(rename = "Year")]
     year: String,
 }
 
+/// OMDb reports failures in the response body, e.g.
+/// `{"Response":"False","Error":"Incorrect IMDb ID."}`, rather than with the result fields.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OMDbResponse {
+    Error {
+        #[serde(rename = "Error")]
+        error: String,
+    },
+    Success(OMDbResult),
+}
+
 impl OMDbResult {
-    fn get_name(&self, season: u32, episode: u32, extension: &str) -> String {
-        // If there are multiple years, – delimits the first year. But for now, it's not used at
-        // all.
-        format!(
-            "{}.S{season:02}E{episode:02}.{extension}",
-            self.title.replace(' ', ".")
-        )
+    /// Returns the year the title started, e.g. "2003" for both "2003" and "2003–2010".
+    fn first_year(&self) -> Option<&str> {
+        // Series that span multiple years use an en dash, and ongoing ones end with one.
+        let year = self.year.split(['–', '-']).next()?.trim();
+        (year.len() == 4 && year.bytes().all(|byte| byte.is_ascii_digit())).then_some(year)
+    }
+
+    fn get_name(&self, number: EpisodeNumber, extension: &str, include_year: bool) -> String {
+        let title = match self.first_year() {
+            Some(year) if include_year => util::sanitize_title(&format!("{} ({year})", self.title)),
+            _ => util::sanitize_title(&self.title),
+        };
+        match number {
+            EpisodeNumber::Season { season, episode } => {
+                format!("{title}.S{season:02}E{episode:02}.{extension}")
+            }
+            EpisodeNumber::Absolute(episode) => format!("{title}.{episode:03}.{extension}"),
+        }
     }
 }
 
 impl MakeEpisodeLinksArgs {
     pub fn exec(self) -> anyhow::Result<()> {
-        let (first_path, paths) = self
-            .files
-            .split_first()
-            .ok_or_else(|| anyhow!("no files provided"))?;
+        let seasons = match &self.series_dir {
+            Some(series_dir) => {
+                if let Numbering::Absolute = self.numbering {
+                    bail!("--series-dir requires season numbering");
+                }
+                find_season_dirs(series_dir)?
+                    .into_iter()
+                    .map(|(season, files)| (Some(season), files))
+                    .collect()
+            }
+            None => {
+                let season = match self.numbering {
+                    Numbering::Season => Some(
+                        self.season
+                            .ok_or_else(|| anyhow!("--season is required with season numbering"))?,
+                    ),
+                    Numbering::Absolute => None,
+                };
+                vec![(season, self.files.clone())]
+            }
+        };
+        if let Numbering::Absolute = self.numbering
+            && let Some(template) = &self.name_template
+            && template.0.contains(&TemplatePart::Season)
+        {
+            bail!("--name-template cannot use {{season}} with absolute numbering");
+        }
+        // Check every season before doing any network requests.
+        let seasons = seasons
+            .iter()
+            .map(|(season, files)| self.number_season(*season, files))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let result = lookup_omdb(
+            &self.imdb_id,
+            crate::config::config()
+                .api_keys
+                .omdb
+                .as_ref()
+                .ok_or_else(|| anyhow!("No OMDb API key!"))?,
+        )?;
+        let get_name = |number, extension: &str| match &self.name_template {
+            Some(template) => template.render(&result, number, extension),
+            None => result.get_name(number, extension, self.include_year),
+        };
+
+        for season in &seasons {
+            eprintln!("Creating the following links in {}:", season.dir.display());
+            for (number, extension, file) in &season.files {
+                eprintln!("  {} => {}", get_name(*number, extension), file.display());
+            }
+        }
+
+        if !Confirm::new()
+            .with_prompt("Continue?")
+            .default(false)
+            .interact()?
+        {
+            return Ok(());
+        }
+
+        for season in &seasons {
+            for (number, extension, file) in &season.files {
+                let link = season.dir.join(get_name(*number, extension));
+                // Links in subdirectories need to climb back up to the episode they point to.
+                let link_dir = link.parent().unwrap();
+                std::fs::create_dir_all(link_dir)?;
+                std::os::unix::fs::symlink(
+                    crate::fs::relative_path(link_dir, &season.dir.join(file)),
+                    link,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `files` are all episodes or sidecars in the same directory, and numbers them
+    /// by sorted position.
+    fn number_season(
+        &self,
+        season: Option<u32>,
+        files: &[PathBuf],
+    ) -> anyhow::Result<NumberedSeason> {
+        let extension_override = self.extension.as_deref();
+        let files: Vec<PathBuf> = files
+            .iter()
+            .filter(|path| {
+                if path.extension().is_some() || extension_override.is_some() {
+                    return true;
+                }
+                util::warning!(
+                    "skipping {}: it has no extension; pass --extension to link it anyway",
+                    path.display()
+                );
+                false
+            })
+            .cloned()
+            .collect();
+        let first_path = files.first().ok_or_else(|| anyhow!("no files provided"))?;
         let expected_parent = first_path
             .parent()
             .ok_or_else(|| anyhow!("{} has no parent", first_path.display()))?;
-        let expected_extension = first_path
-            .extension()
-            .ok_or_else(|| anyhow!("{} has no extension", first_path.display()))?
-            .to_str()
-            .ok_or_else(|| anyhow!("{} has a non-UTF8 extension", first_path.display()))?;
-        check_matching_parent_and_extension(paths, expected_parent, expected_extension)?;
-
-        let body = ureq::get("http://www.omdbapi.com/")
-            .query("i", &self.imdb_id)
-            .query(
-                "apikey",
-                crate::config::config()
-                    .api_keys
-                    .omdb
-                    .as_ref()
-                    .ok_or_else(|| anyhow!("No OMDb API key!"))?,
-            )
-            .call()?
-            .body_mut()
-            .read_to_string()?;
-        let result: OMDbResult = serde_json::from_str(&body)?;
-
-        let parent = expected_parent.canonicalize()?;
-
-        let mut sorted_files: Vec<_> = self
-            .files
+        // The episodes themselves determine the extension everything else must match.
+        let primary_path = files
+            .iter()
+            .find(|path| {
+                !path.extension().is_some_and(|extension| {
+                    self.sidecar_extensions
+                        .iter()
+                        .any(|sidecar| extension == sidecar.as_str())
+                })
+            })
+            .ok_or_else(|| anyhow!("no files provided other than sidecar files"))?;
+        let expected_extension = match primary_path.extension() {
+            Some(extension) => extension
+                .to_str()
+                .ok_or_else(|| anyhow!("{} has a non-UTF8 extension", primary_path.display()))?,
+            None => extension_override
+                .ok_or_else(|| anyhow!("{} has no extension", primary_path.display()))?,
+        };
+        check_matching_parent_and_extension(
+            &files,
+            expected_parent,
+            expected_extension,
+            &self.sidecar_extensions,
+            extension_override,
+        )?;
+        let number = |episode| match season {
+            Some(season) => EpisodeNumber::Season { season, episode },
+            None => EpisodeNumber::Absolute(episode),
+        };
+
+        let mut sorted_files: Vec<_> = files
             .iter()
             .map(|file| {
                 file.file_name()
@@ -80,42 +363,183 @@ impl MakeEpisodeLinksArgs {
             })
             .collect::<Result<_, _>>()?;
         sorted_files.sort();
-        let sorted_files = sorted_files;
-
-        eprintln!("Creating the following links in {}:", parent.display());
-        for (episode, file) in (1..).zip(&sorted_files) {
-            eprintln!(
-                "  {} => {}",
-                result.get_name(self.season, episode, expected_extension),
-                file.display()
+        let numbered_files = number_files(&sorted_files, expected_extension, extension_override)?
+            .into_iter()
+            .map(|(episode, extension, file)| {
+                (number(episode), extension.to_string(), file.to_path_buf())
+            })
+            .collect();
+        Ok(NumberedSeason {
+            dir: expected_parent.canonicalize()?,
+            files: numbered_files,
+        })
+    }
+}
+
+/// The files of one season, numbered and ready to be linked.
+struct NumberedSeason {
+    /// The canonical directory containing the files, where the links are created.
+    dir: PathBuf,
+    /// The file name of each episode or sidecar, along with its number and extension.
+    files: Vec<(EpisodeNumber, String, PathBuf)>,
+}
+
+/// Returns the season number for a season directory name, e.g. "Season 1", "S02", or "Specials".
+fn parse_season_dir(name: &str) -> Option<u32> {
+    static SEASON: OnceLock<Regex> = OnceLock::new();
+    let season = SEASON.get_or_init(|| {
+        Regex::new(r"(?i)^(?:season|series|s)[ ._-]*(\d+)$|^(specials?)$").unwrap()
+    });
+    let captures = season.captures(name)?;
+    match captures.get(1) {
+        Some(number) => number.as_str().parse().ok(),
+        None => Some(0),
+    }
+}
+
+/// Finds the season directories directly under `series_dir`, along with the files in each,
+/// ordered by season. Hidden files are skipped, as are other subdirectories.
+fn find_season_dirs(series_dir: &Path) -> anyhow::Result<Vec<(u32, Vec<PathBuf>)>> {
+    let mut seasons = BTreeMap::new();
+    for entry in std::fs::read_dir(series_dir)? {
+        let entry = entry?;
+        let Some(season) = entry.file_name().to_str().and_then(parse_season_dir) else {
+            continue;
+        };
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let mut files = vec![];
+        for file in std::fs::read_dir(entry.path())? {
+            let file = file?;
+            if file.file_type()?.is_file() && !file.file_name().as_bytes().starts_with(b".") {
+                files.push(file.path());
+            }
+        }
+        if files.is_empty() {
+            continue;
+        }
+        if let Some(other) = seasons.insert(season, files) {
+            bail!(
+                "found season {season} more than once in {}, including {}",
+                series_dir.display(),
+                other[0].parent().unwrap().display()
             );
         }
+    }
+    if seasons.is_empty() {
+        bail!("no season directories found in {}", series_dir.display());
+    }
+    Ok(seasons.into_iter().collect())
+}
 
-        if !Confirm::new()
-            .with_prompt("Continue?")
-            .default(false)
-            .interact()?
-        {
-            return Ok(());
-        }
+/// How many times to retry an OMDb request that failed for a transient reason.
+const OMDB_RETRIES: u32 = 3;
 
-        std::env::set_current_dir(parent)?;
-        for (episode, file) in (1..).zip(&sorted_files) {
-            std::os::unix::fs::symlink(
-                file,
-                result.get_name(self.season, episode, expected_extension),
-            )?;
+/// Looks up `imdb_id` on OMDb. Network and server errors are retried with exponential backoff;
+/// errors reported by OMDb itself, such as an unknown ID or an exhausted API key, are not.
+pub(super) fn lookup_omdb(imdb_id: &str, api_key: &str) -> anyhow::Result<OMDbResult> {
+    let mut delay = Duration::from_millis(500);
+    let mut attempt = 0;
+    let mut response = loop {
+        // OMDb reports its own errors as JSON with a 4xx status, so read the body regardless.
+        let result = util::http_agent()
+            .get("https://www.omdbapi.com/")
+            .config()
+            .http_status_as_error(false)
+            .build()
+            .query("i", imdb_id)
+            .query("apikey", api_key)
+            .call();
+        let is_transient = is_transient(result.as_ref().map(|response| response.status()));
+        if !is_transient || attempt == OMDB_RETRIES {
+            break result.map_err(|err| anyhow!("OMDb request failed: {err}"))?;
+        }
+        debug!(?delay, "OMDb request failed; retrying");
+        std::thread::sleep(delay);
+        delay *= 2;
+        attempt += 1;
+    };
+    let status = response.status();
+    let body = response.body_mut().read_to_string()?;
+    parse_omdb_response(&body).map_err(|err| {
+        if status.is_success() {
+            err
+        } else {
+            err.context(format!("OMDb request failed with status {status}"))
         }
+    })
+}
 
-        Ok(())
+/// Whether an OMDb request that ended with `result` is worth retrying. Server errors, rate
+/// limiting and network failures are; anything else, including errors OMDb reports itself, is not.
+fn is_transient(result: Result<StatusCode, &ureq::Error>) -> bool {
+    match result {
+        Ok(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+        Err(ureq::Error::Io(err)) => util::TRANSIENT_IO_ERRORS.contains(&err.kind()),
+        Err(
+            ureq::Error::Timeout(_) | ureq::Error::HostNotFound | ureq::Error::ConnectionFailed,
+        ) => true,
+        Err(_) => false,
+    }
+}
+
+fn parse_omdb_response(body: &str) -> anyhow::Result<OMDbResult> {
+    match serde_json::from_str(body)? {
+        OMDbResponse::Error { error } => bail!("OMDb lookup failed: {error}"),
+        OMDbResponse::Success(result) => Ok(result),
+    }
+}
+
+/// Numbers the files with `primary_extension` from one, by their position in `sorted_files`. Files
+/// with any other extension are sidecars, and are given the number of the primary file in the same
+/// position among files with their extension. Files without an extension are treated as having
+/// `missing_extension`, if given. Returns the episode number, extension, and file for each file.
+fn number_files<'a>(
+    sorted_files: &[&'a Path],
+    primary_extension: &'a str,
+    missing_extension: Option<&'a str>,
+) -> anyhow::Result<Vec<(u32, &'a str, &'a Path)>> {
+    let mut files_by_extension = BTreeMap::<&str, Vec<&Path>>::new();
+    for file in sorted_files {
+        let extension = match file.extension() {
+            Some(extension) => extension.to_str(),
+            None => missing_extension,
+        }
+        .ok_or_else(|| anyhow!("{} has no UTF-8 extension", file.display()))?;
+        files_by_extension.entry(extension).or_default().push(file);
     }
+    let primary_count = files_by_extension
+        .get(primary_extension)
+        .map_or(0, Vec::len);
+    let mut numbered_files = vec![];
+    for (extension, files) in files_by_extension {
+        if files.len() != primary_count {
+            bail!(
+                "found {} .{extension} file(s) but {primary_count} .{primary_extension} file(s); \
+                 sidecar files are paired with episodes by position, so the counts must match",
+                files.len()
+            );
+        }
+        numbered_files.extend(
+            (1..)
+                .zip(files)
+                .map(|(episode, file)| (episode, extension, file)),
+        );
+    }
+    numbered_files
+        .sort_by_key(|(episode, extension, _)| (*episode, *extension != primary_extension));
+    Ok(numbered_files)
 }
 
 #[derive(Debug, Error, PartialEq)]
 enum CheckMatchingParentAndExtensionError {
     #[error("mismatched parents: {actual} does not have expected parent {expected:?}")]
     MismatchedParents { actual: PathBuf, expected: PathBuf },
-    #[error("mismatched extensions: {actual} does not have expected extension {expected}")]
+    #[error(
+        "mismatched extensions: {actual} does not have expected extension {expected} or a sidecar \
+         extension"
+    )]
     MismatchedExtensions { actual: PathBuf, expected: String },
 }
 
@@ -123,6 +547,8 @@ fn check_matching_parent_and_extension(
     paths: &[PathBuf],
     expected_parent: &Path,
     expected_extension: &str,
+    sidecar_extensions: &[String],
+    missing_extension: Option<&str>,
 ) -> Result<(), CheckMatchingParentAndExtensionError> {
     type Error = CheckMatchingParentAndExtensionError;
 
@@ -133,8 +559,15 @@ fn check_matching_parent_and_extension(
                 expected: expected_parent.to_owned(),
             });
         }
-        match path.extension() {
+        match path.extension().or(missing_extension.map(OsStr::new)) {
             Some(extension) if extension == expected_extension => Ok(()),
+            Some(extension)
+                if sidecar_extensions
+                    .iter()
+                    .any(|sidecar| extension == sidecar.as_str()) =>
+            {
+                Ok(())
+            }
             _ => Err(Error::MismatchedExtensions {
                 actual: path.clone(),
                 expected: expected_extension.to_owned(),
@@ -148,14 +581,333 @@ fn check_matching_parent_and_extension(
 mod tests {
     use super::*;
 
+    #[test]
+    fn is_transient_classifies_omdb_failures() {
+        assert!(is_transient(Ok(StatusCode::SERVICE_UNAVAILABLE)));
+        assert!(is_transient(Ok(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(!is_transient(Ok(StatusCode::OK)));
+        assert!(!is_transient(Ok(StatusCode::UNAUTHORIZED)));
+        assert!(is_transient(Err(&ureq::Error::ConnectionFailed)));
+        assert!(is_transient(Err(&ureq::Error::Timeout(
+            ureq::Timeout::Connect
+        ))));
+        assert!(is_transient(Err(&ureq::Error::Io(
+            std::io::ErrorKind::ConnectionReset.into()
+        ))));
+        assert!(!is_transient(Err(&ureq::Error::Io(
+            std::io::ErrorKind::PermissionDenied.into()
+        ))));
+        assert!(!is_transient(Err(&ureq::Error::BadUri("nope".into()))));
+    }
+
+    #[test]
+    fn parse_omdb_response_surfaces_error() {
+        let err = parse_omdb_response(r#"{"Response":"False","Error":"Request limit reached!"}"#)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "OMDb lookup failed: Request limit reached!"
+        );
+
+        let err = parse_omdb_response(r#"{"Response":"False","Error":"Incorrect IMDb ID."}"#)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "OMDb lookup failed: Incorrect IMDb ID.");
+    }
+
+    #[test]
+    fn check_matching_parent_and_extension_sidecars() {
+        let sidecars = ["srt".to_string()];
+        assert_eq!(
+            check_matching_parent_and_extension(
+                &["test/1.mkv".into(), "test/1.srt".into()],
+                Path::new("test"),
+                "mkv",
+                &sidecars,
+                None
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            check_matching_parent_and_extension(
+                &["test/1.mkv".into(), "test/1.nfo".into()],
+                Path::new("test"),
+                "mkv",
+                &sidecars,
+                None
+            ),
+            Err(CheckMatchingParentAndExtensionError::MismatchedExtensions {
+                actual: "test/1.nfo".into(),
+                expected: "mkv".into(),
+            })
+        );
+    }
+
+    #[test]
+    fn number_files_pairs_sidecars_by_position() {
+        let files = ["a.mkv", "a.srt", "b.mkv", "b.srt"].map(Path::new);
+        assert_eq!(
+            number_files(&files, "mkv", None).unwrap(),
+            vec![
+                (1, "mkv", Path::new("a.mkv")),
+                (1, "srt", Path::new("a.srt")),
+                (2, "mkv", Path::new("b.mkv")),
+                (2, "srt", Path::new("b.srt")),
+            ]
+        );
+    }
+
+    #[test]
+    fn number_files_rejects_unpaired_sidecars() {
+        let files = ["a.mkv", "a.srt", "b.mkv"].map(Path::new);
+        assert!(number_files(&files, "mkv", None).is_err());
+    }
+
+    #[test]
+    fn number_files_uses_missing_extension() {
+        let files = ["a", "a.srt", "b", "b.srt"].map(Path::new);
+        assert!(number_files(&files, "mkv", None).is_err());
+        assert_eq!(
+            number_files(&files, "mkv", Some("mkv")).unwrap(),
+            vec![
+                (1, "mkv", Path::new("a")),
+                (1, "srt", Path::new("a.srt")),
+                (2, "mkv", Path::new("b")),
+                (2, "srt", Path::new("b.srt")),
+            ]
+        );
+    }
+
+    #[test]
+    fn check_matching_parent_and_extension_missing_extension() {
+        assert_eq!(
+            check_matching_parent_and_extension(
+                &["test/1".into()],
+                Path::new("test"),
+                "mkv",
+                &[],
+                None
+            ),
+            Err(CheckMatchingParentAndExtensionError::MismatchedExtensions {
+                actual: "test/1".into(),
+                expected: "mkv".into(),
+            })
+        );
+        assert_eq!(
+            check_matching_parent_and_extension(
+                &["test/1".into(), "test/2.mkv".into()],
+                Path::new("test"),
+                "mkv",
+                &[],
+                Some("mkv")
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn get_name_season_numbering() {
+        let result = OMDbResult {
+            title: "Cowboy Bebop".into(),
+            year: "1998–1999".into(),
+        };
+        assert_eq!(
+            result.get_name(
+                EpisodeNumber::Season {
+                    season: 1,
+                    episode: 2
+                },
+                "mkv",
+                false
+            ),
+            "Cowboy.Bebop.S01E02.mkv"
+        );
+        assert_eq!(
+            result.get_name(
+                EpisodeNumber::Season {
+                    season: 0,
+                    episode: 12
+                },
+                "mkv",
+                false
+            ),
+            "Cowboy.Bebop.S00E12.mkv"
+        );
+    }
+
+    #[test]
+    fn get_name_absolute_numbering() {
+        let result = OMDbResult {
+            title: "One Piece".into(),
+            year: "1999–".into(),
+        };
+        assert_eq!(
+            result.get_name(EpisodeNumber::Absolute(7), "mkv", false),
+            "One.Piece.007.mkv"
+        );
+        assert_eq!(
+            result.get_name(EpisodeNumber::Absolute(1071), "mp4", false),
+            "One.Piece.1071.mp4"
+        );
+    }
+
+    #[test]
+    fn get_name_include_year() {
+        let number = EpisodeNumber::Season {
+            season: 1,
+            episode: 5,
+        };
+        let result = OMDbResult {
+            title: "The Office".into(),
+            year: "2005".into(),
+        };
+        assert_eq!(
+            result.get_name(number, "mkv", true),
+            "The.Office.(2005).S01E05.mkv"
+        );
+        assert_eq!(
+            result.get_name(number, "mkv", false),
+            "The.Office.S01E05.mkv"
+        );
+
+        let result = OMDbResult {
+            title: "Arrested Development".into(),
+            year: "2003–2019".into(),
+        };
+        assert_eq!(
+            result.get_name(number, "mkv", true),
+            "Arrested.Development.(2003).S01E05.mkv"
+        );
+
+        let result = OMDbResult {
+            title: "One Piece".into(),
+            year: "1999–".into(),
+        };
+        assert_eq!(
+            result.get_name(EpisodeNumber::Absolute(7), "mkv", true),
+            "One.Piece.(1999).007.mkv"
+        );
+    }
+
+    #[test]
+    fn parse_season_dir_names() {
+        assert_eq!(parse_season_dir("Season 1"), Some(1));
+        assert_eq!(parse_season_dir("season.02"), Some(2));
+        assert_eq!(parse_season_dir("S03"), Some(3));
+        assert_eq!(parse_season_dir("Series 10"), Some(10));
+        assert_eq!(parse_season_dir("Specials"), Some(0));
+        assert_eq!(parse_season_dir("Extras"), None);
+        assert_eq!(parse_season_dir("Season 1 Extras"), None);
+    }
+
+    #[test]
+    fn find_season_dirs_orders_seasons() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        for (dir, file) in [
+            ("Season 2", "b.mkv"),
+            ("Season 1", "a.mkv"),
+            ("Season 1", ".hidden"),
+            ("Specials", "sp.mkv"),
+            ("Extras", "x.mkv"),
+        ] {
+            std::fs::create_dir_all(tmp_dir.path().join(dir)).expect("failed to create test dir");
+            std::fs::write(tmp_dir.path().join(dir).join(file), "")
+                .expect("failed to create test file");
+        }
+        assert_eq!(
+            find_season_dirs(tmp_dir.path()).unwrap(),
+            [
+                (0, vec![tmp_dir.path().join("Specials/sp.mkv")]),
+                (1, vec![tmp_dir.path().join("Season 1/a.mkv")]),
+                (2, vec![tmp_dir.path().join("Season 2/b.mkv")]),
+            ]
+        );
+
+        std::fs::create_dir_all(tmp_dir.path().join("S01")).expect("failed to create test dir");
+        std::fs::write(tmp_dir.path().join("S01/c.mkv"), "").expect("failed to create test file");
+        assert!(find_season_dirs(tmp_dir.path()).is_err());
+    }
+
+    #[test]
+    fn parse_name_template_validates_placeholders() {
+        assert_eq!(
+            parse_name_template("{title} - S{season}E{episode}.{ext}"),
+            Ok(NameTemplate(vec![
+                TemplatePart::Title,
+                TemplatePart::Literal(" - S".into()),
+                TemplatePart::Season,
+                TemplatePart::Literal("E".into()),
+                TemplatePart::Episode,
+                TemplatePart::Literal(".".into()),
+                TemplatePart::Extension,
+            ]))
+        );
+        assert!(parse_name_template("{title}.{episode}.{extension}").is_err());
+        assert!(parse_name_template("{title}.{episode").is_err());
+        assert!(parse_name_template("{title}}.{episode}").is_err());
+        assert!(parse_name_template("{title}.{ext}").is_err());
+    }
+
+    #[test]
+    fn name_template_render() {
+        let result = OMDbResult {
+            title: "Star Wars: The Clone Wars".into(),
+            year: "2008–2020".into(),
+        };
+        let number = EpisodeNumber::Season {
+            season: 1,
+            episode: 5,
+        };
+        let render = |template| {
+            parse_name_template(template)
+                .unwrap()
+                .render(&result, number, "mkv")
+        };
+        assert_eq!(
+            render("{title} - S{season}E{episode}.{ext}"),
+            "Star Wars The Clone Wars - S01E05.mkv"
+        );
+        assert_eq!(
+            render("{title} ({year})/Season {season}/{dotted_title}.S{season}E{episode}.{ext}"),
+            "Star Wars The Clone Wars (2008)/Season 01/Star.Wars.The.Clone.Wars.S01E05.mkv"
+        );
+        assert_eq!(
+            parse_name_template("{dotted_title}.{episode}.{ext}")
+                .unwrap()
+                .render(&result, EpisodeNumber::Absolute(7), "mp4"),
+            "Star.Wars.The.Clone.Wars.007.mp4"
+        );
+    }
+
+    #[test]
+    fn get_name_include_year_without_valid_year() {
+        let result = OMDbResult {
+            title: "Unknown".into(),
+            year: "N/A".into(),
+        };
+        assert_eq!(
+            result.get_name(EpisodeNumber::Absolute(1), "mkv", true),
+            "Unknown.001.mkv"
+        );
+    }
+
+    #[test]
+    fn parse_omdb_response_success() {
+        let result = parse_omdb_response(
+            r#"{"Title":"Spirited Away","Year":"2001","Response":"True","imdbID":"tt0245429"}"#,
+        )
+        .unwrap();
+        assert_eq!(result.title, "Spirited Away");
+        assert_eq!(result.year, "2001");
+    }
+
     #[test]
     fn check_matching_parent_and_extension_no_paths() {
         assert_eq!(
-            check_matching_parent_and_extension(&[], Path::new(""), "mkv".into()),
+            check_matching_parent_and_extension(&[], Path::new(""), "mkv", &[], None),
             Ok(())
         );
         assert_eq!(
-            check_matching_parent_and_extension(&[], Path::new("test"), "mkv".into()),
+            check_matching_parent_and_extension(&[], Path::new("test"), "mkv", &[], None),
             Ok(())
         );
     }
@@ -163,14 +915,22 @@ mod tests {
     #[test]
     fn check_matching_parent_and_extension_ok() {
         assert_eq!(
-            check_matching_parent_and_extension(&["test.mkv".into()], Path::new(""), "mkv".into()),
+            check_matching_parent_and_extension(
+                &["test.mkv".into()],
+                Path::new(""),
+                "mkv",
+                &[],
+                None
+            ),
             Ok(())
         );
         assert_eq!(
             check_matching_parent_and_extension(
                 &["test/test.mkv".into()],
                 Path::new("test"),
-                "mkv".into()
+                "mkv",
+                &[],
+                None
             ),
             Ok(())
         );
@@ -182,7 +942,9 @@ mod tests {
             check_matching_parent_and_extension(
                 &["test.mkv".into()],
                 Path::new("test"),
-                "mkv".into()
+                "mkv",
+                &[],
+                None
             ),
             Err(CheckMatchingParentAndExtensionError::MismatchedParents {
                 actual: "test.mkv".into(),
@@ -193,7 +955,9 @@ mod tests {
             check_matching_parent_and_extension(
                 &["test/test.mkv".into()],
                 Path::new(""),
-                "mkv".into()
+                "mkv",
+                &[],
+                None
             ),
             Err(CheckMatchingParentAndExtensionError::MismatchedParents {
                 actual: "test/test.mkv".into(),
@@ -205,7 +969,13 @@ mod tests {
     #[test]
     fn check_matching_parent_and_extension_mismatched_extensions() {
         assert_eq!(
-            check_matching_parent_and_extension(&["test.mp4".into()], Path::new(""), "mkv".into()),
+            check_matching_parent_and_extension(
+                &["test.mp4".into()],
+                Path::new(""),
+                "mkv",
+                &[],
+                None
+            ),
             Err(CheckMatchingParentAndExtensionError::MismatchedExtensions {
                 actual: "test.mp4".into(),
                 expected: "mkv".into(),
@@ -215,7 +985,9 @@ mod tests {
             check_matching_parent_and_extension(
                 &["test/test.mp4".into()],
                 Path::new("test"),
-                "mkv".into()
+                "mkv",
+                &[],
+                None
             ),
             Err(CheckMatchingParentAndExtensionError::MismatchedExtensions {
                 actual: "test/test.mp4".into(),
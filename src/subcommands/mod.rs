@@ -1,13 +1,41 @@
 mod batch_episodes;
+mod completions;
+mod config;
+mod create;
+mod dedupe;
+mod doctor;
+mod export;
 mod find;
 mod import;
+mod inspect;
+mod list;
 mod make_episode_links;
 mod r#move;
+mod relink;
+mod remove;
+mod stats;
+mod unimport;
 mod update_paths;
+mod verify;
+mod r#where;
 
 pub use batch_episodes::BatchEpisodesArgs;
-pub use find::FindArgs;
+pub use completions::CompletionsArgs;
+pub use config::ConfigArgs;
+pub use create::CreateArgs;
+pub use dedupe::DedupeArgs;
+pub use doctor::DoctorArgs;
+pub use export::ExportArgs;
+pub use find::{FindArgs, NoMatchesError};
 pub use import::ImportArgs;
+pub use inspect::InspectArgs;
+pub use list::ListArgs;
 pub use make_episode_links::MakeEpisodeLinksArgs;
 pub use r#move::MoveArgs;
+pub use relink::RelinkArgs;
+pub use remove::RemoveArgs;
+pub use stats::StatsArgs;
+pub use unimport::UnimportArgs;
 pub use update_paths::UpdatePathsArgs;
+pub use verify::VerifyArgs;
+pub use r#where::WhereArgs;
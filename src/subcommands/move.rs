@@ -1,6 +1,10 @@
 use anyhow::{anyhow, bail};
 use clap::{Args, ValueEnum};
-use std::collections::HashMap;
+use dialoguer::Confirm;
+use indicatif::ProgressBar;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{Read, Write};
 use std::path::{Path, PathBuf};
 use thiserror::Error;
 
@@ -17,20 +21,74 @@ pub struct MoveArgs {
     /// Destination directory.
     target: PathBuf,
 
-    /// How to move the files.
-    #[arg(default_value = "copy-and-unlink", long, value_enum)]
-    strategy: Strategy,
+    /// Also move incomplete torrents whose progress is at least this fraction, from 0.0 to 1.0.
+    /// By default, any incomplete torrent matched by a source refuses the move.
+    #[arg(long, value_parser = util::parse_progress)]
+    min_progress: Option<f64>,
+
+    /// Refuse the move if any torrent matched by a source has no tracker whose host contains this
+    /// string, ignoring case. Since the whole source is moved, such a torrent cannot be left
+    /// behind. May be specified more than once to match any of several trackers.
+    #[arg(long)]
+    tracker: Vec<String>,
+
+    /// Refuse the move if any torrent matched by a source has a tracker whose host contains this
+    /// string, ignoring case, even if it matches --tracker. May be specified more than once.
+    #[arg(long)]
+    exclude_tracker: Vec<String>,
+
+    /// How to move the files. Defaults to `defaults.move_strategy` from config.toml if set, and
+    /// otherwise copy-and-unlink.
+    #[arg(long, value_enum)]
+    strategy: Option<Strategy>,
 
     /// A directory with symlinks to update. May be specified multiple times.
     #[arg(long)]
     symlink_dir: Vec<PathBuf>,
 
+    /// Also update symlinks in each of the `symlink_roots` directories from config.toml, in
+    /// addition to any directories passed with --symlink-dir.
+    #[arg(long)]
+    auto_symlink_dirs: bool,
+
     #[arg(long)]
     dry_run: bool,
+
+    /// With the copy-and-unlink strategy, copy the files in a directory in parallel rather than
+    /// one at a time.
+    #[arg(long)]
+    parallel_copy: bool,
+
+    /// With the copy-and-unlink strategy, leave the original files in place after copying. The
+    /// torrents are still updated to use the copies; the originals must be cleaned up manually.
+    #[arg(long)]
+    keep_source: bool,
+
+    /// Leave the moved torrents, and any torrents seeding from updated symlinks, paused
+    /// afterwards.
+    #[arg(long)]
+    no_resume: bool,
+
+    /// Print the full plan and ask for confirmation before changing anything. Has no effect with
+    /// --dry-run.
+    #[arg(long)]
+    confirm: bool,
+
+    /// Do not check that the target has enough free space before a copy-based move.
+    #[arg(long)]
+    skip_space_check: bool,
+
+    /// Skip symlinks, sockets, and other non-file entries with a warning instead of failing.
+    #[arg(long)]
+    skip_non_files: bool,
+
+    /// Follow symlinks when collecting files.
+    #[arg(long)]
+    follow_symlinks: bool,
 }
 
 #[derive(Copy, Clone, Default, ValueEnum)]
-enum Strategy {
+pub(super) enum Strategy {
     /// Copy-based approach; works across devices at the cost of requiring double the space
     /// temporarily.
     #[default]
@@ -40,7 +98,26 @@ enum Strategy {
 }
 
 impl MoveArgs {
+    fn collect_options(&self) -> fs::CollectOptions {
+        fs::CollectOptions {
+            skip_non_files: self.skip_non_files,
+            follow_symlinks: self.follow_symlinks,
+            ..Default::default()
+        }
+    }
+
     pub fn exec(self) -> anyhow::Result<()> {
+        self.run(&*sycli::new_instance(self.dry_run))
+    }
+
+    fn run(&self, client: &dyn sycli::Client) -> anyhow::Result<()> {
+        let strategy = resolve_strategy(
+            self.strategy,
+            crate::config::config().defaults.move_strategy.as_deref(),
+        )?;
+        if self.keep_source && matches!(strategy, Strategy::Rename) {
+            bail!("--keep-source requires the copy-and-unlink strategy");
+        }
         if !self.target.is_dir() {
             bail!("target {} is not a directory", self.target.display());
         }
@@ -50,26 +127,63 @@ impl MoveArgs {
             let source_is_file = source.is_file();
             let target = std::path::absolute(&self.target)?;
 
-            let source_files = fs::collect_files(&source)?;
+            let source_files = fs::collect_files_with_spinner(&source, &self.collect_options())?;
 
-            // TODO: Abstract this out so multiple torrent client backends can be used.
-            let unfiltered_torrents = sycli::get_torrents()?;
+            let unfiltered_torrents = client.get_torrents()?;
             let torrents = sycli::filter_torrents(&unfiltered_torrents, &source_files)?;
             if torrents.is_empty() {
                 bail!("could not find torrents that matched {}", source.display());
             }
 
-            if let Some(torrent) = torrents.iter().find(|torrent| torrent.progress != 1.0) {
-                bail!("{} is incomplete; cannot move!", torrent.id);
+            // The whole source is moved, so every matched torrent has to be moved along with it.
+            for torrent in &torrents {
+                if !torrent.is_complete()
+                    && self
+                        .min_progress
+                        .is_none_or(|min_progress| torrent.progress < min_progress)
+                {
+                    bail!(
+                        "{} is incomplete ({:.1}%); cannot move!",
+                        torrent.id,
+                        torrent.progress * 100.0
+                    );
+                }
+                if !torrent.matches_tracker_filters(&self.tracker, &self.exclude_tracker) {
+                    bail!(
+                        "{} is from a tracker excluded by --tracker or --exclude-tracker; cannot \
+                         move!",
+                        torrent.id
+                    );
+                }
             }
 
+            let symlink_dirs = if self.auto_symlink_dirs {
+                let roots = &crate::config::config().symlink_roots;
+                if roots.is_empty() {
+                    util::warning!(
+                        "--auto-symlink-dirs given, but no symlink_roots are configured"
+                    );
+                }
+                union_symlink_dirs(&self.symlink_dir, roots)
+            } else {
+                union_symlink_dirs(&self.symlink_dir, &[])
+            };
             let mut symlinks_to_update = HashMap::new();
-            for symlink_dir in &self.symlink_dir {
-                symlinks_to_update.extend(
-                    fs::collect_symlinks(&symlink_dir)?
-                        .into_iter()
-                        .filter(|(_, target_path)| source_files.contains_key(target_path)),
-                );
+            if !symlink_dirs.is_empty() {
+                let bar = util::new_progress_spinner();
+                for symlink_dir in &symlink_dirs {
+                    bar.set_message(format!("collecting symlinks in {}", symlink_dir.display()));
+                    symlinks_to_update.extend(
+                        fs::collect_symlinks(symlink_dir, true)?
+                            .into_iter()
+                            .filter(|(_, target_path)| source_files.contains_key(target_path)),
+                    );
+                    bar.set_position(symlinks_to_update.len() as u64);
+                }
+                bar.finish_with_message(format!(
+                    "found {} symlinks to update",
+                    symlinks_to_update.len()
+                ));
             }
             let symlinks_to_update = symlinks_to_update;
             // TODO: filter_torrents() should probably take a HashSet since the length value isn't
@@ -84,21 +198,97 @@ impl MoveArgs {
                 sycli::filter_torrents(&unfiltered_torrents, &symlinks_for_filter)?;
             if let Some(torrent) = symlinked_torrents
                 .iter()
-                .find(|torrent| torrent.progress != 1.0)
+                .find(|torrent| !torrent.is_complete())
             {
                 bail!("{} (symlinked) is incomplete; cannot move!", torrent.id);
             }
 
+            if matches!(strategy, Strategy::CopyAndUnlink) && !self.skip_space_check {
+                check_free_space(&target, source_files.values().sum())?;
+            }
+
+            if self.confirm && !self.dry_run {
+                println!("The following torrents will be paused and moved:");
+                for torrent in &torrents {
+                    println!("  {} ({})", torrent.id, torrent.name);
+                }
+                if !symlinked_torrents.is_empty() {
+                    println!("The following symlinked torrents will be paused:");
+                    for torrent in &symlinked_torrents {
+                        println!("  {} ({})", torrent.id, torrent.name);
+                    }
+                }
+                println!("The following files will be moved to {}:", target.display());
+                for path in source_files.keys().collect::<BTreeSet<_>>() {
+                    println!("  {}", path.display());
+                }
+                if !symlinks_to_update.is_empty() {
+                    println!("The following symlinks will be updated:");
+                    for (link, link_target) in symlinks_to_update.iter().collect::<BTreeMap<_, _>>()
+                    {
+                        println!("  {} -> {}", link.display(), link_target.display());
+                    }
+                }
+                if !Confirm::new()
+                    .with_prompt("Continue?")
+                    .default(false)
+                    .interact()?
+                {
+                    return Ok(());
+                }
+            }
+
+            if self.dry_run {
+                println!(
+                    "Plan for moving {} to {}:",
+                    source.display(),
+                    target.display()
+                );
+                println!("  Pause:");
+                for torrent in &torrents {
+                    println!("    {} ({})", torrent.id, torrent.name);
+                }
+                for torrent in &symlinked_torrents {
+                    println!("    {} ({}) (symlinked)", torrent.id, torrent.name);
+                }
+                println!("  Update base paths:");
+                for torrent in &torrents {
+                    let new_path =
+                        calculate_new_base_path(&source, source_is_file, &target, torrent)?;
+                    println!("    {} -> {}", torrent.id, new_path.display());
+                }
+                if !symlinks_to_update.is_empty() {
+                    println!("  Update symlinks:");
+                    for (link, link_target) in symlinks_to_update.iter().collect::<BTreeMap<_, _>>()
+                    {
+                        println!(
+                            "    {} -> {}",
+                            link.display(),
+                            new_symlink_target(&source, &target, link_target)?.display()
+                        );
+                    }
+                }
+                if !self.no_resume {
+                    println!("  Resume:");
+                    for torrent in &torrents {
+                        println!("    {} ({})", torrent.id, torrent.name);
+                    }
+                    for torrent in &symlinked_torrents {
+                        println!("    {} ({}) (symlinked)", torrent.id, torrent.name);
+                    }
+                }
+            }
+
             for torrent in &torrents {
-                eprintln!("pausing {}", torrent.id);
+                util::info!("pausing {}", torrent.id);
                 if !self.dry_run {
-                    sycli::pause_torrent(&torrent.id)?;
+                    client.pause_torrent(&torrent.id)?;
                 }
             }
             for torrent in &symlinked_torrents {
-                eprintln!("pausing {} (symlinked)", torrent.id);
+                util::info!("pausing {} (symlinked)", torrent.id);
                 if !self.dry_run {
-                    sycli::pause_torrent(&torrent.id)?;
+                    client.pause_torrent(&torrent.id)?;
                 }
             }
 
@@ -106,45 +296,54 @@ impl MoveArgs {
                 for torrent in &torrents {
                     let new_path =
                         calculate_new_base_path(&source, source_is_file, &target, torrent)?;
-                    eprintln!(
+                    util::info!(
                         "updating {} to directory {}",
                         torrent.id,
                         new_path.display()
                     );
                     if !self.dry_run {
-                        sycli::move_torrent(&torrent.id, &new_path)?;
+                        client.move_torrent(&torrent.id, &new_path)?;
                     }
                 }
                 Ok(())
             };
 
-            eprintln!(
+            util::info!(
                 "moving files from {} to {}",
                 source.display(),
                 target.display()
             );
-            match self.strategy {
+            match strategy {
                 Strategy::Rename => {
                     move_files_with_rename(self.dry_run, &source, &target, move_torrents)
                 }
-                Strategy::CopyAndUnlink => {
-                    move_files_with_copy(self.dry_run, &source, &target, move_torrents)
-                }
+                Strategy::CopyAndUnlink => move_files_with_copy(
+                    self.dry_run,
+                    self.parallel_copy,
+                    self.keep_source,
+                    &source,
+                    &target,
+                    move_torrents,
+                ),
             }?;
 
             update_symlinks(self.dry_run, &source, &target, &symlinks_to_update)?;
 
+            if self.no_resume {
+                continue;
+            }
+
             for torrent in &torrents {
-                eprintln!("resuming {}", torrent.id);
+                util::info!("resuming {}", torrent.id);
                 if !self.dry_run {
-                    sycli::resume_torrent(&torrent.id)?;
+                    client.resume_torrent(&torrent.id)?;
                 }
             }
 
             for torrent in &symlinked_torrents {
-                eprintln!("resuming {} (symlinked)", torrent.id);
+                util::info!("resuming {} (symlinked)", torrent.id);
                 if !self.dry_run {
-                    sycli::resume_torrent(&torrent.id)?;
+                    client.resume_torrent(&torrent.id)?;
                 }
             }
         }
@@ -170,7 +369,7 @@ where
         )
     })?);
 
-    eprintln!(
+    util::info!(
         "moving {} to {} using rename",
         source.display(),
         target_with_file_name.display()
@@ -182,8 +381,25 @@ where
     move_torrents()
 }
 
+/// Fails if the filesystem containing `target` has less than `required` bytes available.
+fn check_free_space(target: &Path, required: u64) -> anyhow::Result<()> {
+    let stat = rustix::fs::statvfs(target)?;
+    let available = stat.f_bavail * stat.f_frsize;
+    if required > available {
+        bail!(
+            "not enough free space in {}: need {}, but only {} available",
+            target.display(),
+            util::format_bytes(required),
+            util::format_bytes(available)
+        );
+    }
+    Ok(())
+}
+
 fn move_files_with_copy<M>(
     dry_run: bool,
+    parallel: bool,
+    keep_source: bool,
     source: &Path,
     target: &Path,
     move_torrents: M,
@@ -198,7 +414,7 @@ where
         )
     })?);
 
-    eprintln!(
+    util::info!(
         "moving {} to {} using copy",
         source.display(),
         target_with_file_name.display()
@@ -211,21 +427,27 @@ where
     let progress = util::new_progress_bar();
     if source.is_dir() {
         if !dry_run {
-            fs_extra::dir::copy_with_progress(
-                source,
-                target,
-                &fs_extra::dir::CopyOptions::new(),
-                |process| {
-                    progress.set_message(process.file_name);
-                    progress.set_length(process.total_bytes);
-                    progress.set_position(process.copied_bytes);
-                    fs_extra::dir::TransitProcessResult::ContinueOrAbort
-                },
-            )?;
+            if parallel {
+                copy_dir_parallel(source, &target_with_file_name, &progress)?;
+            } else {
+                fs_extra::dir::copy_with_progress(
+                    source,
+                    target,
+                    &fs_extra::dir::CopyOptions::new(),
+                    |process| {
+                        progress.set_message(process.file_name);
+                        progress.set_length(process.total_bytes);
+                        progress.set_position(process.copied_bytes);
+                        fs_extra::dir::TransitProcessResult::ContinueOrAbort
+                    },
+                )?;
+            }
         }
         progress.finish();
         move_torrents()?;
-        if !dry_run {
+        if keep_source {
+            util::info!("keeping source {}", source.display());
+        } else if !dry_run {
             std::fs::remove_dir_all(source)?;
         }
     } else {
@@ -242,7 +464,9 @@ where
         }
         progress.finish();
         move_torrents()?;
-        if !dry_run {
+        if keep_source {
+            util::info!("keeping source {}", source.display());
+        } else if !dry_run {
             std::fs::remove_file(source)?;
         }
     }
@@ -250,41 +474,155 @@ where
     Ok(())
 }
 
+/// Recursively copies the directory `source` to `target`, copying files in parallel. Like
+/// `fs_extra`, fails rather than overwriting any existing files. `progress` is advanced as bytes
+/// are copied.
+fn copy_dir_parallel(source: &Path, target: &Path, progress: &ProgressBar) -> anyhow::Result<()> {
+    const BUFFER_SIZE: usize = 1024 * 1024;
+
+    let mut files = vec![];
+    for entry in walkdir::WalkDir::new(source) {
+        let entry = entry?;
+        let destination = target.join(entry.path().strip_prefix(source)?);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&destination)?;
+        } else if entry.file_type().is_file() {
+            files.push((entry.into_path(), destination));
+        } else {
+            bail!("cannot copy non-file path {}", entry.path().display());
+        }
+    }
+    progress.set_length(
+        files
+            .iter()
+            .map(|(path, _)| Ok(std::fs::metadata(path)?.len()))
+            .sum::<std::io::Result<u64>>()?,
+    );
+
+    files
+        .into_par_iter()
+        .try_for_each(|(source, destination)| -> anyhow::Result<()> {
+            progress.set_message(source.display().to_string());
+            let mut reader = std::fs::File::open(&source)?;
+            let mut writer = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&destination)?;
+            let mut buffer = vec![0; BUFFER_SIZE];
+            loop {
+                let bytes_read = reader.read(&mut buffer)?;
+                if bytes_read == 0 {
+                    break;
+                }
+                writer.write_all(&buffer[..bytes_read])?;
+                progress.inc(bytes_read as u64);
+            }
+            writer.set_permissions(reader.metadata()?.permissions())?;
+            Ok(())
+        })
+}
+
 #[derive(Debug, Error)]
 enum UpdateSymlinksError {
     #[error("not a prefix: {0}")]
     NotAPrefix(#[from] std::path::StripPrefixError),
     #[error("path {0} has no parent")]
     NoParent(PathBuf),
-    #[error("IO error")]
-    Io(#[from] std::io::Error),
+    #[error("failed to update {} of {total} symlinks", failed.len())]
+    Update { failed: Vec<PathBuf>, total: usize },
+}
+
+/// Where a symlink to `symlink_target` should point once `source` has been moved into `target`.
+fn new_symlink_target(
+    source: &Path,
+    target: &Path,
+    symlink_target: &Path,
+) -> Result<PathBuf, UpdateSymlinksError> {
+    let source_dir = source
+        .parent()
+        .ok_or_else(|| UpdateSymlinksError::NoParent(source.to_path_buf()))?;
+    Ok(target.join(symlink_target.strip_prefix(source_dir)?))
 }
 
+/// Repoints every symlink from under `source` to the same path under `target`. A symlink that
+/// cannot be updated does not stop the others from being updated; each failure is reported with
+/// the target the link still points to, and the error lists every link that was not updated.
 fn update_symlinks(
     dry_run: bool,
     source: &Path,
     target: &Path,
     symlinks: &HashMap<PathBuf, PathBuf>,
 ) -> Result<(), UpdateSymlinksError> {
-    let source_dir = source
-        .parent()
-        .ok_or_else(|| UpdateSymlinksError::NoParent(source.to_path_buf()))?;
-    for (symlink, symlink_target) in symlinks {
-        let new_symlink_target = target.join(symlink_target.strip_prefix(source_dir)?);
-        eprintln!(
+    // Compute every new target before touching anything, so bad paths fail without side effects.
+    let updates = symlinks
+        .iter()
+        .collect::<BTreeMap<_, _>>()
+        .into_iter()
+        .map(|(symlink, symlink_target)| {
+            Ok((
+                symlink,
+                symlink_target,
+                new_symlink_target(source, target, symlink_target)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, UpdateSymlinksError>>()?;
+    let mut failed = vec![];
+    for (symlink, symlink_target, new_symlink_target) in &updates {
+        util::info!(
             "updating symlink {} from {} to {}",
             symlink.display(),
             symlink_target.display(),
             new_symlink_target.display()
         );
-        if !dry_run {
-            fs::create_or_update_symlink(symlink, &new_symlink_target)?;
+        if dry_run {
+            continue;
+        }
+        if let Err(err) = fs::create_or_update_symlink(symlink, new_symlink_target) {
+            util::warning!(
+                "failed to update symlink {}, which still points to {}: {err}",
+                symlink.display(),
+                symlink_target.display()
+            );
+            failed.push(symlink.to_path_buf());
+        } else if fs::is_dangling(symlink) {
+            util::warning!(
+                "updated symlink {} points to missing target {}",
+                symlink.display(),
+                new_symlink_target.display()
+            );
         }
     }
 
+    if !failed.is_empty() {
+        return Err(UpdateSymlinksError::Update {
+            failed,
+            total: updates.len(),
+        });
+    }
     Ok(())
 }
 
+/// Returns the strategy passed on the command line, falling back to the one configured in
+/// config.toml and then to the default.
+pub(super) fn resolve_strategy(
+    flag: Option<Strategy>,
+    configured: Option<&str>,
+) -> anyhow::Result<Strategy> {
+    if let Some(strategy) = flag {
+        return Ok(strategy);
+    }
+    let Some(configured) = configured else {
+        return Ok(Strategy::default());
+    };
+    Strategy::from_str(configured, true)
+        .map_err(|err| anyhow!("invalid defaults.move_strategy in config.toml: {err}"))
+}
+
+/// Returns the directories to scan for symlinks, without duplicates.
+fn union_symlink_dirs(explicit: &[PathBuf], roots: &[PathBuf]) -> BTreeSet<PathBuf> {
+    explicit.iter().chain(roots).cloned().collect()
+}
+
 // TODO: These error messages need improvement.
 #[derive(Debug, Error, PartialEq)]
 enum CalculateNewBasePathError {
@@ -348,13 +686,12 @@ mod tests {
     #[test]
     fn calculate_new_base_path_with_single_file_torrent() {
         let torrent = sycli::Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test.txt".into(), 123)]),
+            ..sycli::Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test.txt", 123)],
+            )
         };
         assert_eq!(
             calculate_new_base_path(
@@ -367,13 +704,12 @@ mod tests {
         );
 
         let torrent = sycli::Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test.txt".into(),
-            base_path: "/tmp/test torrent".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test.txt".into(), 123)]),
+            ..sycli::Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp/test torrent",
+                &[("test.txt", 123)],
+            )
         };
         assert_eq!(
             calculate_new_base_path(
@@ -386,13 +722,12 @@ mod tests {
         );
 
         let torrent = sycli::Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test.txt".into(),
-            base_path: "/tmp/test torrent/disc 1".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test.txt".into(), 123)]),
+            ..sycli::Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp/test torrent/disc 1",
+                &[("test.txt", 123)],
+            )
         };
         assert_eq!(
             calculate_new_base_path(
@@ -408,13 +743,13 @@ mod tests {
     #[test]
     fn calculate_new_base_path_with_multi_file_torrent() {
         let torrent = sycli::Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
             name: "test torrent".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test data/test.txt".into(), 123)]),
+            ..sycli::Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test data/test.txt", 123)],
+            )
         };
         assert_eq!(
             calculate_new_base_path(
@@ -427,13 +762,79 @@ mod tests {
         );
 
         let torrent = sycli::Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "disc 1".into(),
-            base_path: "/tmp/test torrent".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("disc 1/test.txt".into(), 123)]),
+            ..sycli::Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp/test torrent",
+                &[("disc 1/test.txt", 123)],
+            )
+        };
+        assert_eq!(
+            calculate_new_base_path(
+                Path::new("/tmp/test torrent"),
+                false,
+                Path::new("/home/test/data"),
+                &torrent
+            ),
+            Ok("/home/test/data/test torrent".into())
+        );
+    }
+
+    #[test]
+    fn calculate_new_base_path_with_trailing_slash() {
+        // `Path` ignores trailing slashes when splitting into components, so `/tmp/test torrent/`
+        // has the same file name and parent as `/tmp/test torrent`.
+        let torrent = sycli::Torrent {
+            tracker_urls: vec!["https://example.com:9999".into()],
+            ..sycli::Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp/test torrent",
+                &[("disc 1/test.txt", 123)],
+            )
+        };
+        for source in ["/tmp/test torrent", "/tmp/test torrent/"] {
+            assert_eq!(
+                calculate_new_base_path(
+                    Path::new(source),
+                    false,
+                    Path::new("/home/test/data/"),
+                    &torrent
+                ),
+                Ok("/home/test/data/test torrent".into()),
+                "source {source}"
+            );
+        }
+
+        let torrent = sycli::Torrent {
+            name: "test.txt".into(),
+            files: HashMap::from([("test.txt".into(), 123.into())]),
+            ..torrent
+        };
+        for source in ["/tmp/test torrent", "/tmp/test torrent/"] {
+            assert_eq!(
+                calculate_new_base_path(
+                    Path::new(source),
+                    false,
+                    Path::new("/home/test/data/"),
+                    &torrent
+                ),
+                Ok("/home/test/data/test torrent".into()),
+                "source {source}"
+            );
+        }
+    }
+
+    #[test]
+    fn calculate_new_base_path_with_single_file_torrent_at_source() {
+        // The torrent's data lives directly in the moved directory, so the directory is recreated
+        // under the target and nothing else is appended.
+        let torrent = sycli::Torrent {
+            tracker_urls: vec!["https://example.com:9999".into()],
+            ..sycli::Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp/test torrent",
+                &[("test.txt", 123)],
+            )
         };
         assert_eq!(
             calculate_new_base_path(
@@ -445,4 +846,375 @@ mod tests {
             Ok("/home/test/data/test torrent".into())
         );
     }
+
+    #[test]
+    fn calculate_new_base_path_with_root_source() {
+        // Moving `/` itself is not meaningful: there is no parent to strip for multi-file
+        // torrents and no directory name to recreate for single-file torrents.
+        let torrent = sycli::Torrent {
+            tracker_urls: vec!["https://example.com:9999".into()],
+            ..sycli::Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/",
+                &[("test torrent/test.txt", 123)],
+            )
+        };
+        assert_eq!(
+            calculate_new_base_path(
+                Path::new("/"),
+                false,
+                Path::new("/home/test/data"),
+                &torrent
+            ),
+            Err(CalculateNewBasePathError::NoParent("/".into()))
+        );
+
+        let torrent = sycli::Torrent {
+            name: "test.txt".into(),
+            files: HashMap::from([("test.txt".into(), 123.into())]),
+            ..torrent
+        };
+        assert_eq!(
+            calculate_new_base_path(
+                Path::new("/"),
+                false,
+                Path::new("/home/test/data"),
+                &torrent
+            ),
+            Err(CalculateNewBasePathError::NoFileName("/".into()))
+        );
+    }
+
+    #[test]
+    fn resolve_strategy_precedence() {
+        assert!(matches!(
+            resolve_strategy(None, None).unwrap(),
+            Strategy::CopyAndUnlink
+        ));
+        assert!(matches!(
+            resolve_strategy(None, Some("rename")).unwrap(),
+            Strategy::Rename
+        ));
+        assert!(matches!(
+            resolve_strategy(Some(Strategy::CopyAndUnlink), Some("rename")).unwrap(),
+            Strategy::CopyAndUnlink
+        ));
+        assert!(resolve_strategy(None, Some("teleport")).is_err());
+    }
+
+    #[test]
+    fn union_symlink_dirs_deduplicates() {
+        assert_eq!(
+            union_symlink_dirs(
+                &["/links/a".into(), "/links/b".into()],
+                &["/links/b".into(), "/roots/c".into()]
+            ),
+            BTreeSet::from([
+                PathBuf::from("/links/a"),
+                PathBuf::from("/links/b"),
+                PathBuf::from("/roots/c"),
+            ])
+        );
+        assert!(union_symlink_dirs(&[], &[]).is_empty());
+    }
+
+    #[test]
+    fn update_symlinks_reports_failed_links() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let source = tmp_dir.path().join("src/show");
+        let target = tmp_dir.path().join("dst");
+        let links = tmp_dir.path().join("links");
+        std::fs::create_dir_all(&links).expect("failed to create test dir");
+        let old_target = source.join("a.mkv");
+        std::os::unix::fs::symlink(&old_target, links.join("a")).expect("failed to create link");
+        std::os::unix::fs::symlink(source.join("c.mkv"), links.join("c"))
+            .expect("failed to create link");
+        // A directory can't be replaced by a symlink, so updating "b" fails, but "a" and "c" are
+        // still updated.
+        std::fs::create_dir(links.join("b")).expect("failed to create test dir");
+        let symlinks = HashMap::from([
+            (links.join("a"), old_target),
+            (links.join("b"), source.join("b.mkv")),
+            (links.join("c"), source.join("c.mkv")),
+        ]);
+
+        let err = update_symlinks(false, &source, &target, &symlinks).unwrap_err();
+        assert!(matches!(
+            err,
+            UpdateSymlinksError::Update { ref failed, total: 3 } if *failed == [links.join("b")]
+        ));
+        assert_eq!(
+            std::fs::read_link(links.join("a")).unwrap(),
+            target.join("show/a.mkv")
+        );
+        assert_eq!(
+            std::fs::read_link(links.join("c")).unwrap(),
+            target.join("show/c.mkv")
+        );
+    }
+
+    #[test]
+    fn new_symlink_target_rebases_onto_target() {
+        assert_eq!(
+            new_symlink_target(
+                Path::new("/src/show"),
+                Path::new("/dst"),
+                Path::new("/src/show/ep1.mkv")
+            )
+            .unwrap(),
+            PathBuf::from("/dst/show/ep1.mkv")
+        );
+        assert!(
+            new_symlink_target(
+                Path::new("/src/show"),
+                Path::new("/dst"),
+                Path::new("/elsewhere/ep1.mkv")
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn move_files_with_copy_keep_source() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let source = tmp_dir.path().join("source.txt");
+        let target = tmp_dir.path().join("target");
+        std::fs::write(&source, "hello").expect("failed to create test file");
+        std::fs::create_dir(&target).expect("failed to create target dir");
+
+        move_files_with_copy(false, false, true, &source, &target, || Ok(())).unwrap();
+        assert_eq!(std::fs::read_to_string(&source).unwrap(), "hello");
+        assert_eq!(
+            std::fs::read_to_string(target.join("source.txt")).unwrap(),
+            "hello"
+        );
+    }
+
+    #[test]
+    fn check_free_space_fails_when_too_large() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        assert!(check_free_space(tmp_dir.path(), 0).is_ok());
+        assert!(check_free_space(tmp_dir.path(), u64::MAX).is_err());
+    }
+
+    #[test]
+    fn copy_dir_parallel_copies_tree() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let source = tmp_dir.path().join("source");
+        std::fs::create_dir_all(source.join("sub")).expect("failed to create source dir");
+        std::fs::create_dir_all(source.join("empty")).expect("failed to create source dir");
+        std::fs::write(source.join("a"), "first").expect("failed to create test file");
+        std::fs::write(source.join("sub/b"), "second").expect("failed to create test file");
+        let target = tmp_dir.path().join("target");
+
+        let progress = ProgressBar::hidden();
+        copy_dir_parallel(&source, &target, &progress).unwrap();
+        assert_eq!(std::fs::read_to_string(target.join("a")).unwrap(), "first");
+        assert_eq!(
+            std::fs::read_to_string(target.join("sub/b")).unwrap(),
+            "second"
+        );
+        assert!(target.join("empty").is_dir());
+        assert_eq!(progress.position(), 11);
+
+        // Existing files are never overwritten.
+        assert!(copy_dir_parallel(&source, &target, &progress).is_err());
+    }
+
+    fn make_args(source: &Path, target: &Path, strategy: Strategy) -> MoveArgs {
+        MoveArgs {
+            sources: vec![source.to_path_buf()],
+            target: target.to_path_buf(),
+            min_progress: None,
+            tracker: vec![],
+            exclude_tracker: vec![],
+            strategy: Some(strategy),
+            symlink_dir: vec![],
+            auto_symlink_dirs: false,
+            dry_run: false,
+            parallel_copy: false,
+            keep_source: false,
+            no_resume: false,
+            confirm: false,
+            skip_space_check: true,
+            skip_non_files: false,
+            follow_symlinks: false,
+        }
+    }
+
+    /// Lays out `src/show` with two episodes, an empty `dst`, and a `links` directory with a
+    /// symlink to the first episode, seeded by its own torrent.
+    fn make_tree(tmp_dir: &Path) -> (PathBuf, PathBuf, PathBuf, sycli::FakeClient) {
+        let source = tmp_dir.join("src/show");
+        let target = tmp_dir.join("dst");
+        let links = tmp_dir.join("links");
+        for dir in [&source, &target, &links] {
+            std::fs::create_dir_all(dir).expect("failed to create test dir");
+        }
+        for file in ["ep1.mkv", "ep2.mkv"] {
+            std::fs::write(source.join(file), "video").expect("failed to create test file");
+        }
+        std::os::unix::fs::symlink(source.join("ep1.mkv"), links.join("ep1.mkv"))
+            .expect("failed to create symlink");
+        let client = sycli::FakeClient::new(vec![
+            sycli::Torrent::for_test(
+                "show",
+                tmp_dir.join("src"),
+                &[("show/ep1.mkv", 5), ("show/ep2.mkv", 5)],
+            ),
+            sycli::Torrent::for_test("linked", &links, &[("ep1.mkv", 5)]),
+        ]);
+        (source, target, links, client)
+    }
+
+    #[test]
+    fn run_moves_files_and_torrents() {
+        for strategy in [Strategy::CopyAndUnlink, Strategy::Rename] {
+            let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+            let (source, target, links, client) = make_tree(tmp_dir.path());
+            let args = MoveArgs {
+                symlink_dir: vec![links.clone()],
+                ..make_args(&source, &target, strategy)
+            };
+
+            args.run(&client).unwrap();
+            assert_eq!(
+                *client.calls.borrow(),
+                [
+                    "pause show",
+                    "pause linked",
+                    "move show",
+                    "resume show",
+                    "resume linked"
+                ]
+            );
+            assert!(!source.exists());
+            for file in ["ep1.mkv", "ep2.mkv"] {
+                assert_eq!(
+                    std::fs::read_to_string(target.join("show").join(file)).unwrap(),
+                    "video"
+                );
+            }
+            assert_eq!(client.base_path("show"), target);
+            assert_eq!(client.base_path("linked"), links);
+            assert_eq!(
+                std::fs::read_link(links.join("ep1.mkv")).unwrap(),
+                target.join("show/ep1.mkv")
+            );
+        }
+    }
+
+    #[test]
+    fn run_no_resume_leaves_torrents_paused() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let (source, target, _links, client) = make_tree(tmp_dir.path());
+        let args = MoveArgs {
+            no_resume: true,
+            ..make_args(&source, &target, Strategy::Rename)
+        };
+
+        args.run(&client).unwrap();
+        assert_eq!(*client.calls.borrow(), ["pause show", "move show"]);
+        assert_eq!(client.base_path("show"), target);
+    }
+
+    #[test]
+    fn run_dry_run_changes_nothing() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let (source, target, links, client) = make_tree(tmp_dir.path());
+        let args = MoveArgs {
+            symlink_dir: vec![links.clone()],
+            dry_run: true,
+            ..make_args(&source, &target, Strategy::CopyAndUnlink)
+        };
+
+        args.run(&client).unwrap();
+        assert!(client.calls.borrow().is_empty());
+        assert!(source.join("ep1.mkv").exists());
+        assert!(!target.join("show").exists());
+        assert_eq!(client.base_path("show"), tmp_dir.path().join("src"));
+        assert_eq!(
+            std::fs::read_link(links.join("ep1.mkv")).unwrap(),
+            source.join("ep1.mkv")
+        );
+    }
+
+    #[test]
+    fn run_refuses_incomplete_torrents() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let (source, target, _links, client) = make_tree(tmp_dir.path());
+        client.torrents.borrow_mut()[0].progress = 0.5;
+
+        let err = make_args(&source, &target, Strategy::Rename)
+            .run(&client)
+            .unwrap_err();
+        assert!(err.to_string().contains("incomplete"), "{err}");
+        assert!(client.calls.borrow().is_empty());
+        assert!(source.join("ep1.mkv").exists());
+    }
+
+    #[test]
+    fn run_min_progress_with_mixed_progress_source() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let (source, target, _links, client) = make_tree(tmp_dir.path());
+        client.torrents.borrow_mut().push(sycli::Torrent {
+            progress: 0.5,
+            ..sycli::Torrent::for_test(
+                "partial",
+                tmp_dir.path().join("src"),
+                &[("show/ep2.mkv", 5)],
+            )
+        });
+
+        let args = MoveArgs {
+            min_progress: Some(0.9),
+            ..make_args(&source, &target, Strategy::Rename)
+        };
+        let err = args.run(&client).unwrap_err();
+        assert!(
+            err.to_string().starts_with("partial is incomplete"),
+            "{err}"
+        );
+        assert!(client.calls.borrow().is_empty());
+        assert!(source.join("ep2.mkv").exists());
+
+        let args = MoveArgs {
+            min_progress: Some(0.5),
+            ..make_args(&source, &target, Strategy::Rename)
+        };
+        args.run(&client).unwrap();
+        assert!(target.join("show/ep2.mkv").exists());
+        assert_eq!(client.base_path("show"), target);
+        assert_eq!(client.base_path("partial"), target);
+    }
+
+    #[test]
+    fn run_refuses_torrents_from_other_trackers() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let (source, target, _links, client) = make_tree(tmp_dir.path());
+        client.torrents.borrow_mut()[0].tracker_urls = vec!["https://example.com/announce".into()];
+        client.torrents.borrow_mut().push(sycli::Torrent {
+            tracker_urls: vec!["https://other.org/announce".into()],
+            ..sycli::Torrent::for_test("other", tmp_dir.path().join("src"), &[("show/ep2.mkv", 5)])
+        });
+
+        for args in [
+            MoveArgs {
+                tracker: vec!["example".into()],
+                ..make_args(&source, &target, Strategy::Rename)
+            },
+            MoveArgs {
+                exclude_tracker: vec!["other".into()],
+                ..make_args(&source, &target, Strategy::Rename)
+            },
+        ] {
+            let err = args.run(&client).unwrap_err();
+            assert!(
+                err.to_string().starts_with("other is from a tracker"),
+                "{err}"
+            );
+            assert!(client.calls.borrow().is_empty());
+            assert!(source.join("ep2.mkv").exists());
+        }
+    }
 }
@@ -0,0 +1,210 @@
+use clap::Args;
+use console::style;
+use globset::GlobSet;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use crate::fs;
+use crate::subcommands::import;
+use crate::sycli;
+use crate::util;
+
+#[derive(Args)]
+pub struct RelinkArgs {
+    /// A directory with symlinks to repair. May be specified multiple times.
+    #[arg(long, required(true))]
+    symlink_dir: Vec<PathBuf>,
+
+    /// Directories to search for replacement targets. May be specified more than once.
+    #[arg(long, required(true))]
+    source: Vec<PathBuf>,
+
+    /// If true, only prints out the changes that would have been made.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl RelinkArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        self.run(&*sycli::new_instance(self.dry_run))
+    }
+
+    fn run(&self, client: &dyn sycli::Client) -> anyhow::Result<()> {
+        let mut dangling = BTreeMap::new();
+        for symlink_dir in &self.symlink_dir {
+            dangling.extend(
+                fs::collect_symlinks(&std::path::absolute(symlink_dir)?, true)?
+                    .into_iter()
+                    .filter(|(link, _)| fs::is_dangling(link)),
+            );
+        }
+        if dangling.is_empty() {
+            println!("no dangling symlinks found");
+            return Ok(());
+        }
+
+        // A dangling symlink's target can't be measured, but if a torrent seeds from the link or
+        // its old target, the client knows what size the file should be. Candidates are matched
+        // by file name, and by size where it is known. Ties are broken the same way as for
+        // imports: by the longest common suffix with the original target path.
+        let sizes = client
+            .get_torrents()?
+            .into_iter()
+            .flat_map(|torrent| {
+                torrent
+                    .files
+                    .into_iter()
+                    .map(move |(path, info)| (torrent.base_path.join(path), info.size as u64))
+            })
+            .collect::<HashMap<_, _>>();
+        let sources = self
+            .source
+            .iter()
+            .map(std::path::absolute)
+            .collect::<Result<Vec<_>, _>>()?;
+        let (entries, errors) =
+            import::enumerate_files_with_sizes(&sources, &GlobSet::empty(), false, None);
+        for err in &errors {
+            util::warning!("skipping {err:#}");
+        }
+        let mut candidates = HashMap::<_, Vec<_>>::new();
+        for (size, paths) in entries {
+            for path in paths {
+                if let Some(file_name) = path.file_name() {
+                    candidates
+                        .entry(file_name.to_os_string())
+                        .or_default()
+                        .push((path, size));
+                }
+            }
+        }
+
+        let mut unresolved = vec![];
+        for (link, original_target) in &dangling {
+            let size = sizes
+                .get(link)
+                .or_else(|| sizes.get(original_target))
+                .copied();
+            let Some(new_target) = find_replacement(original_target, size, &candidates) else {
+                unresolved.push(link);
+                continue;
+            };
+            util::info!(
+                "updating symlink {} from {} to {}",
+                link.display(),
+                original_target.display(),
+                new_target.display()
+            );
+            if !self.dry_run {
+                fs::create_or_update_symlink(link, new_target)?;
+            }
+        }
+
+        if !unresolved.is_empty() {
+            println!(
+                "{}",
+                style(format!(
+                    "unable to find replacement targets for {} symlink(s):",
+                    unresolved.len()
+                ))
+                .red()
+            );
+            for link in unresolved {
+                println!("  {} -> {}", link.display(), dangling[link].display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Picks the best replacement for `original_target` among `candidates`, which are grouped by file
+/// name and paired with their sizes. If `size` is known, candidates of any other size are ignored.
+fn find_replacement<'a>(
+    original_target: &Path,
+    size: Option<u64>,
+    candidates: &'a HashMap<OsString, Vec<(PathBuf, u64)>>,
+) -> Option<&'a Path> {
+    let candidates = candidates
+        .get(original_target.file_name()?)?
+        .iter()
+        .filter(|(_, candidate_size)| size.is_none_or(|size| size == *candidate_size))
+        .map(|(path, _)| path.as_path())
+        .collect::<Vec<_>>();
+    import::get_best_candidate(original_target, &candidates, None::<&Path>)
+        .map(|(_, new_target)| new_target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_replacement_matches_name_and_size() {
+        let candidates = HashMap::from([(
+            OsString::from("ep1.mkv"),
+            vec![
+                (PathBuf::from("/new/show/ep1.mkv"), 10),
+                (PathBuf::from("/new/other/ep1.mkv"), 20),
+            ],
+        )]);
+        let original_target = Path::new("/old/show/ep1.mkv");
+        assert_eq!(
+            find_replacement(original_target, Some(20), &candidates),
+            Some(Path::new("/new/other/ep1.mkv"))
+        );
+        // Without a size, the longest common suffix wins.
+        assert_eq!(
+            find_replacement(original_target, None, &candidates),
+            Some(Path::new("/new/show/ep1.mkv"))
+        );
+        assert_eq!(
+            find_replacement(original_target, Some(30), &candidates),
+            None
+        );
+        assert_eq!(
+            find_replacement(Path::new("/old/show/ep2.mkv"), None, &candidates),
+            None
+        );
+    }
+
+    #[test]
+    fn run_repoints_dangling_symlinks() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let links = tmp_dir.path().join("links");
+        let source = tmp_dir.path().join("source");
+        for dir in [&links, &source.join("show"), &source.join("sample")] {
+            std::fs::create_dir_all(dir).expect("failed to create test dir");
+        }
+        std::fs::write(source.join("show/ep1.mkv"), "video").expect("failed to create test file");
+        std::fs::write(source.join("sample/ep1.mkv"), "v").expect("failed to create test file");
+        let old = tmp_dir.path().join("old");
+        for name in ["ep1.mkv", "ep2.mkv"] {
+            std::os::unix::fs::symlink(old.join("sample").join(name), links.join(name))
+                .expect("failed to create symlink");
+        }
+        // The torrent seeding from the link says it should be the five byte file, even though
+        // the sample's path is a closer match.
+        let client = sycli::FakeClient::new(vec![sycli::Torrent::for_test(
+            "linked",
+            &links,
+            &[("ep1.mkv", 5)],
+        )]);
+        let args = RelinkArgs {
+            symlink_dir: vec![links.clone()],
+            source: vec![source.clone()],
+            dry_run: false,
+        };
+
+        args.run(&client).unwrap();
+        assert_eq!(
+            std::fs::read_link(links.join("ep1.mkv")).unwrap(),
+            source.join("show/ep1.mkv")
+        );
+        assert_eq!(
+            std::fs::read_link(links.join("ep2.mkv")).unwrap(),
+            old.join("sample/ep2.mkv")
+        );
+    }
+}
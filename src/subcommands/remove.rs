@@ -0,0 +1,180 @@
+use anyhow::{anyhow, bail};
+use clap::Args;
+use dialoguer::Confirm;
+use std::collections::{BTreeSet, HashSet};
+use std::os::unix::fs::MetadataExt;
+
+use crate::sycli;
+use crate::util;
+
+#[derive(Args)]
+pub struct RemoveArgs {
+    /// Infohashes of the torrents to remove.
+    #[arg(required(true))]
+    torrents: Vec<String>,
+
+    /// Also delete the torrent data from disk.
+    #[arg(long)]
+    with_data: bool,
+
+    /// If true, only prints out the changes that would have been made.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl RemoveArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        let client = sycli::new_instance(self.dry_run);
+        let all_torrents = client.get_torrents()?;
+        let torrents = self
+            .torrents
+            .iter()
+            .map(|id| {
+                all_torrents
+                    .iter()
+                    .find(|torrent| torrent.id == *id)
+                    .ok_or_else(|| anyhow!("no torrent with infohash {id}"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        if self.with_data {
+            let removed_ids = torrents
+                .iter()
+                .map(|torrent| torrent.id.as_str())
+                .collect::<HashSet<_>>();
+            let remaining = all_torrents
+                .iter()
+                .filter(|torrent| !removed_ids.contains(torrent.id.as_str()))
+                .collect::<Vec<_>>();
+            for torrent in &torrents {
+                if let Some(other) = find_sharing_torrent(torrent, &remaining) {
+                    bail!(
+                        "{} shares files with {}; refusing to delete its data",
+                        torrent.id,
+                        other.id
+                    );
+                }
+            }
+
+            println!("The following files will be deleted:");
+            for torrent in &torrents {
+                for path in torrent.files.keys() {
+                    println!("  {}", torrent.base_path.join(path).display());
+                }
+            }
+            if !self.dry_run
+                && !Confirm::new()
+                    .with_prompt("Continue?")
+                    .default(false)
+                    .interact()?
+            {
+                return Ok(());
+            }
+        }
+
+        for torrent in &torrents {
+            util::info!("removing {}", torrent.id);
+            client.remove_torrent(&torrent.id)?;
+            if self.with_data {
+                delete_data(self.dry_run, torrent)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns a torrent in `others` that references the same on-disk data as any file in `torrent`,
+/// whether by path, by hardlink, or by symlink.
+fn find_sharing_torrent<'a>(
+    torrent: &sycli::Torrent,
+    others: &[&'a sycli::Torrent],
+) -> Option<&'a sycli::Torrent> {
+    let paths = torrent
+        .files
+        .keys()
+        .map(|path| torrent.base_path.join(path))
+        .collect::<HashSet<_>>();
+    let inodes = paths
+        .iter()
+        .filter_map(|path| std::fs::metadata(path).ok())
+        .map(|metadata| (metadata.dev(), metadata.ino()))
+        .collect::<HashSet<_>>();
+    others.iter().copied().find(|other| {
+        other.files.keys().any(|path| {
+            let path = other.base_path.join(path);
+            // `metadata()` follows symlinks, so this also catches symlinks to `torrent`'s files.
+            paths.contains(&path)
+                || std::fs::metadata(&path)
+                    .is_ok_and(|metadata| inodes.contains(&(metadata.dev(), metadata.ino())))
+        })
+    })
+}
+
+fn delete_data(dry_run: bool, torrent: &sycli::Torrent) -> anyhow::Result<()> {
+    let mut dirs = BTreeSet::new();
+    for path in torrent.files.keys() {
+        let full_path = torrent.base_path.join(path);
+        util::info!("deleting {}", full_path.display());
+        if !dry_run {
+            match std::fs::remove_file(&full_path) {
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                result => result?,
+            }
+        }
+        dirs.extend(
+            path.ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .map(|dir| torrent.base_path.join(dir)),
+        );
+    }
+    // Clean up directories created for the torrent, deepest first. Directories that still contain
+    // other files are left alone.
+    for dir in dirs.iter().rev() {
+        util::info!("removing directory {}", dir.display());
+        if !dry_run {
+            let _ = std::fs::remove_dir(dir);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_sharing_torrent_detects_hardlinks_and_symlinks() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        std::fs::write(tmp_dir.path().join("original"), "contents")
+            .expect("failed to create test file");
+        std::fs::write(tmp_dir.path().join("unrelated"), "contents")
+            .expect("failed to create test file");
+        std::fs::hard_link(
+            tmp_dir.path().join("original"),
+            tmp_dir.path().join("hardlink"),
+        )
+        .expect("failed to create hardlink");
+        std::os::unix::fs::symlink(
+            tmp_dir.path().join("original"),
+            tmp_dir.path().join("symlink"),
+        )
+        .expect("failed to create symlink");
+
+        let torrent = sycli::Torrent::for_test("original", tmp_dir.path(), &[("original", 8)]);
+        let unrelated = sycli::Torrent::for_test("unrelated", tmp_dir.path(), &[("unrelated", 8)]);
+        let hardlink = sycli::Torrent::for_test("hardlink", tmp_dir.path(), &[("hardlink", 8)]);
+        let symlink = sycli::Torrent::for_test("symlink", tmp_dir.path(), &[("symlink", 8)]);
+
+        assert_eq!(find_sharing_torrent(&torrent, &[&unrelated]), None);
+        assert_eq!(
+            find_sharing_torrent(&torrent, &[&unrelated, &hardlink]),
+            Some(&hardlink)
+        );
+        assert_eq!(
+            find_sharing_torrent(&torrent, &[&unrelated, &symlink]),
+            Some(&symlink)
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+use crate::sycli;
+use crate::util;
+
+#[derive(Args)]
+pub struct StatsArgs {
+    /// Only count torrents with a tracker whose host contains this string, ignoring case. May be
+    /// specified more than once to match any of several trackers.
+    #[arg(long)]
+    tracker: Vec<String>,
+
+    /// Leave out torrents with a tracker whose host contains this string, ignoring case, even if
+    /// they match --tracker. May be specified more than once.
+    #[arg(long)]
+    exclude_tracker: Vec<String>,
+
+    /// Output format.
+    #[arg(default_value = "table", long, value_enum)]
+    format: Format,
+}
+
+#[derive(Copy, Clone, Default, ValueEnum)]
+enum Format {
+    /// A human-readable summary with one row per tracker.
+    #[default]
+    Table,
+    /// A JSON object with the totals and a map of trackers.
+    Json,
+    /// Gauges in the Prometheus text exposition format, for scraping.
+    Prometheus,
+}
+
+#[derive(Default, Serialize)]
+struct TrackerStats {
+    torrents: usize,
+    size: usize,
+    errors: usize,
+}
+
+#[derive(Default, Serialize)]
+struct Stats {
+    complete: usize,
+    incomplete: usize,
+    size: usize,
+    files: usize,
+    tracker_errors: usize,
+    trackers: BTreeMap<String, TrackerStats>,
+}
+
+impl Stats {
+    fn new(torrents: &[sycli::Torrent]) -> Self {
+        let mut stats = Stats::default();
+        for torrent in torrents {
+            if torrent.is_complete() {
+                stats.complete += 1;
+            } else {
+                stats.incomplete += 1;
+            }
+            stats.size += torrent.size;
+            stats.files += torrent.files.len();
+            if torrent.tracker_errors().next().is_some() {
+                stats.tracker_errors += 1;
+            }
+            // A torrent may list several announce URLs for the same host; only count it once.
+            let hosts = torrent
+                .tracker_urls
+                .iter()
+                .map(|url| util::url_host(url).unwrap_or_else(|_| url.clone()))
+                .collect::<BTreeSet<_>>();
+            let error_hosts = torrent
+                .tracker_errors()
+                .map(|tracker| util::url_host(&tracker.url).unwrap_or_else(|_| tracker.url.clone()))
+                .collect::<BTreeSet<_>>();
+            for host in hosts {
+                let tracker = stats.trackers.entry(host).or_default();
+                tracker.torrents += 1;
+                tracker.size += torrent.size;
+            }
+            for host in error_hosts {
+                stats.trackers.entry(host).or_default().errors += 1;
+            }
+        }
+        stats
+    }
+}
+
+impl StatsArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        let mut torrents = sycli::new_instance(false).get_torrents()?;
+        torrents.retain(|torrent| {
+            torrent.matches_tracker_filters(&self.tracker, &self.exclude_tracker)
+        });
+        let stats = Stats::new(&torrents);
+
+        match self.format {
+            Format::Table => print_table(&stats),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+            Format::Prometheus => {
+                let mut output = std::io::stdout().lock();
+                write_prometheus(&mut output, &stats)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn print_table(stats: &Stats) {
+    println!(
+        "{} torrent(s): {} complete, {} incomplete",
+        stats.complete + stats.incomplete,
+        stats.complete,
+        stats.incomplete
+    );
+    println!(
+        "{} file(s) totalling {}",
+        stats.files,
+        util::format_bytes(stats.size as u64)
+    );
+    if stats.tracker_errors > 0 {
+        println!("{} torrent(s) with tracker errors", stats.tracker_errors);
+    }
+    let width = stats.trackers.keys().map(String::len).max().unwrap_or(0);
+    for (host, tracker) in &stats.trackers {
+        println!(
+            "  {host:width$}  {:>6}  {:>12}  {:>6} error(s)",
+            tracker.torrents,
+            util::format_bytes(tracker.size as u64),
+            tracker.errors
+        );
+    }
+}
+
+fn write_prometheus(output: &mut dyn Write, stats: &Stats) -> std::io::Result<()> {
+    let gauges = [
+        (
+            "toru_torrents_total",
+            "Number of torrents.",
+            stats.complete + stats.incomplete,
+        ),
+        (
+            "toru_torrents_complete",
+            "Number of torrents with all wanted files downloaded.",
+            stats.complete,
+        ),
+        (
+            "toru_torrents_incomplete",
+            "Number of torrents still downloading.",
+            stats.incomplete,
+        ),
+        (
+            "toru_files_total",
+            "Number of files in all torrents.",
+            stats.files,
+        ),
+        (
+            "toru_bytes_total",
+            "Total size of all torrents in bytes.",
+            stats.size,
+        ),
+        (
+            "toru_torrents_with_tracker_errors",
+            "Number of torrents with at least one tracker error.",
+            stats.tracker_errors,
+        ),
+    ];
+    for (name, help, value) in gauges {
+        writeln!(output, "# HELP {name} {help}")?;
+        writeln!(output, "# TYPE {name} gauge")?;
+        writeln!(output, "{name} {value}")?;
+    }
+
+    let per_tracker = |value: fn(&TrackerStats) -> usize| {
+        stats
+            .trackers
+            .iter()
+            .map(|(host, tracker)| (host, value(tracker)))
+            .collect::<Vec<_>>()
+    };
+    let tracker_gauges = [
+        (
+            "toru_torrents",
+            "Number of torrents per tracker host.",
+            per_tracker(|tracker| tracker.torrents),
+        ),
+        (
+            "toru_bytes",
+            "Size of the torrents per tracker host in bytes.",
+            per_tracker(|tracker| tracker.size),
+        ),
+        (
+            "toru_tracker_errors",
+            "Number of torrents with an error from each tracker host.",
+            per_tracker(|tracker| tracker.errors),
+        ),
+    ];
+    for (name, help, samples) in tracker_gauges {
+        writeln!(output, "# HELP {name} {help}")?;
+        writeln!(output, "# TYPE {name} gauge")?;
+        for (host, value) in samples {
+            writeln!(
+                output,
+                "{name}{{tracker=\"{}\"}} {value}",
+                escape_label_value(host)
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Escapes `value` for use as a label value in the Prometheus text format, which only treats
+/// backslashes, double quotes, and line feeds specially.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_counts_each_tracker_host_once_per_torrent() {
+        let torrent = sycli::Torrent {
+            tracker_urls: vec![
+                "https://example.com:9999/announce".into(),
+                "udp://example.com:1337".into(),
+                "https://example.org/announce".into(),
+            ],
+            ..sycli::Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test.txt", 123)],
+            )
+        };
+        let torrent2 = sycli::Torrent {
+            progress: 0.5,
+            tracker_urls: vec!["https://example.com:9999/announce".into()],
+            ..sycli::Torrent::for_test(
+                "1234567890123456789012345678901234567890",
+                "/tmp",
+                &[("test2.txt", 100)],
+            )
+        };
+        let stats = Stats::new(&[torrent, torrent2]);
+        assert_eq!(stats.complete, 1);
+        assert_eq!(stats.incomplete, 1);
+        assert_eq!(stats.size, 223);
+        assert_eq!(stats.files, 2);
+        assert_eq!(stats.trackers.len(), 2);
+        assert_eq!(stats.trackers["example.com"].torrents, 2);
+        assert_eq!(stats.trackers["example.com"].size, 223);
+        assert_eq!(stats.trackers["example.org"].torrents, 1);
+        assert_eq!(stats.tracker_errors, 0);
+    }
+
+    #[test]
+    fn write_prometheus_emits_gauges() {
+        let stats = Stats {
+            complete: 2,
+            incomplete: 1,
+            size: 300,
+            files: 4,
+            tracker_errors: 1,
+            trackers: BTreeMap::from([(
+                "example.com".to_string(),
+                TrackerStats {
+                    torrents: 3,
+                    size: 300,
+                    errors: 1,
+                },
+            )]),
+        };
+        let mut output = vec![];
+        write_prometheus(&mut output, &stats).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let samples = output
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            samples,
+            [
+                "toru_torrents_total 3",
+                "toru_torrents_complete 2",
+                "toru_torrents_incomplete 1",
+                "toru_files_total 4",
+                "toru_bytes_total 300",
+                "toru_torrents_with_tracker_errors 1",
+                "toru_torrents{tracker=\"example.com\"} 3",
+                "toru_bytes{tracker=\"example.com\"} 300",
+                "toru_tracker_errors{tracker=\"example.com\"} 1",
+            ]
+        );
+        assert!(output.contains("# TYPE toru_torrents gauge\n"));
+    }
+
+    #[test]
+    fn escape_label_value_escapes_special_characters() {
+        assert_eq!(escape_label_value("example.com"), "example.com");
+        assert_eq!(escape_label_value("a\\b\"c\nd"), "a\\\\b\\\"c\\nd");
+    }
+}
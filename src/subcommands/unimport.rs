@@ -0,0 +1,276 @@
+use anyhow::{Context, bail};
+use clap::Args;
+use console::style;
+use std::collections::{BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+
+use super::import::ManifestRecord;
+use crate::sycli;
+use crate::util;
+
+#[derive(Args)]
+pub struct UnimportArgs {
+    /// Manifest written by `import --manifest`. Its imports are undone from the most recent one
+    /// back.
+    #[arg(long, value_name = "PATH")]
+    manifest: PathBuf,
+
+    /// Also remove the torrents that the manifest says were added to the client.
+    #[arg(long)]
+    remove_torrents: bool,
+
+    /// If true, only prints out the changes that would have been made.
+    #[arg(long)]
+    dry_run: bool,
+}
+
+impl UnimportArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        self.run(&*sycli::new_instance(self.dry_run))
+    }
+
+    fn run(&self, client: &dyn sycli::Client) -> anyhow::Result<()> {
+        let records = read_manifest(&self.manifest)?;
+        let mut client_ids = if self.remove_torrents {
+            client
+                .get_torrents()?
+                .into_iter()
+                .map(|torrent| torrent.id.to_ascii_lowercase())
+                .collect()
+        } else {
+            HashSet::new()
+        };
+        let mut failed = 0;
+        for record in records.iter().rev() {
+            if let Err(err) = self.undo(client, record, &mut client_ids) {
+                eprintln!("{} {:?}", style("error:").red(), style(err).red());
+                failed += 1;
+            }
+        }
+        if failed > 0 {
+            bail!("failed to undo {failed} of {} imports", records.len());
+        }
+        Ok(())
+    }
+
+    fn undo(
+        &self,
+        client: &dyn sycli::Client,
+        record: &ManifestRecord,
+        client_ids: &mut HashSet<String>,
+    ) -> anyhow::Result<()> {
+        util::info!("undoing import of {}", record.torrent.display());
+        // Remove the torrent first, so the client never sees its files disappear.
+        if self.remove_torrents && record.added {
+            let id = record.info_hash.to_ascii_lowercase();
+            if client_ids.remove(&id) {
+                util::info!("removing {id}");
+                client.remove_torrent(&record.info_hash)?;
+            } else {
+                util::info!("{id} is no longer in the client");
+            }
+        }
+        remove_links(self.dry_run, record)
+    }
+}
+
+fn read_manifest(path: &Path) -> anyhow::Result<Vec<ManifestRecord>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("unable to read manifest {}", path.display()))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).with_context(|| {
+                format!("invalid record on line {} of {}", index + 1, path.display())
+            })
+        })
+        .collect()
+}
+
+/// Removes the symlinks created by the import in `record`, then any directories under its seed
+/// directory that are left empty. Links that are gone already are skipped, and links that no
+/// longer point at their recorded target are left alone, since they have been reused since.
+fn remove_links(dry_run: bool, record: &ManifestRecord) -> anyhow::Result<()> {
+    let mut dirs = BTreeSet::new();
+    for link in &record.links {
+        match std::fs::read_link(&link.link) {
+            Ok(target) if target == link.target => {}
+            Ok(target) => {
+                util::warning!(
+                    "skipping {}: it now points to {} instead of {}",
+                    link.link.display(),
+                    target.display(),
+                    link.target.display()
+                );
+                continue;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                util::info!("{} is already gone", link.link.display());
+                continue;
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::InvalidInput => {
+                util::warning!(
+                    "skipping {}: it is no longer a symlink",
+                    link.link.display()
+                );
+                continue;
+            }
+            Err(err) => {
+                return Err(err).context(format!("unable to read {}", link.link.display()));
+            }
+        }
+        util::info!("removing {}", link.link.display());
+        if !dry_run {
+            std::fs::remove_file(&link.link)?;
+        }
+        // The seed directory itself may be shared with other imports, e.g. per tracker.
+        dirs.extend(
+            link.link
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| *dir != record.seed_dir && dir.starts_with(&record.seed_dir))
+                .map(Path::to_path_buf),
+        );
+    }
+    // Deepest first; directories that still contain other files are left alone.
+    for dir in dirs.iter().rev() {
+        if !dry_run && std::fs::remove_dir(dir).is_ok() {
+            util::info!("removed directory {}", dir.display());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::subcommands::import::{Action, Link};
+
+    fn make_record(seed_dir: &Path, links: Vec<Link>) -> ManifestRecord {
+        ManifestRecord {
+            torrent: "/torrents/t.torrent".into(),
+            info_hash: "0123456789012345678901234567890123456789".into(),
+            action: Action::Symlink,
+            seed_dir: seed_dir.to_path_buf(),
+            client_seed_dir: None,
+            links,
+            added: true,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn remove_links_only_removes_unchanged_links() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let data = tmp_dir.path().join("data");
+        std::fs::create_dir(&data).expect("failed to create test dir");
+        for name in ["a", "b", "c"] {
+            std::fs::write(data.join(name), name).expect("failed to create test file");
+        }
+        let seed_dir = tmp_dir.path().join("links");
+        let show_dir = seed_dir.join("show");
+        let other_dir = seed_dir.join("other");
+        std::fs::create_dir_all(&show_dir).expect("failed to create test dir");
+        std::fs::create_dir_all(&other_dir).expect("failed to create test dir");
+        std::os::unix::fs::symlink(data.join("a"), show_dir.join("a"))
+            .expect("failed to create symlink");
+        // Repointed since the import.
+        std::os::unix::fs::symlink(data.join("c"), other_dir.join("b"))
+            .expect("failed to create symlink");
+        let record = make_record(
+            &seed_dir,
+            vec![
+                Link {
+                    link: show_dir.join("a"),
+                    target: data.join("a"),
+                },
+                Link {
+                    link: other_dir.join("b"),
+                    target: data.join("b"),
+                },
+                // Already removed.
+                Link {
+                    link: show_dir.join("c"),
+                    target: data.join("c"),
+                },
+            ],
+        );
+
+        remove_links(true, &record).unwrap();
+        assert!(show_dir.join("a").is_symlink());
+
+        remove_links(false, &record).unwrap();
+        assert!(!show_dir.exists());
+        assert!(other_dir.join("b").is_symlink());
+        assert!(seed_dir.is_dir());
+        for name in ["a", "b", "c"] {
+            assert!(data.join(name).is_file());
+        }
+    }
+
+    #[test]
+    fn run_removes_added_torrents_still_in_client() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let data = tmp_dir.path().join("data");
+        let seed_dir = tmp_dir.path().join("links");
+        std::fs::create_dir(&data).expect("failed to create test dir");
+        std::fs::create_dir(&seed_dir).expect("failed to create test dir");
+        std::fs::write(data.join("a"), "a").expect("failed to create test file");
+        std::os::unix::fs::symlink(data.join("a"), seed_dir.join("a"))
+            .expect("failed to create symlink");
+        let record = make_record(
+            &seed_dir,
+            vec![Link {
+                link: seed_dir.join("a"),
+                target: data.join("a"),
+            }],
+        );
+        let removed_already = ManifestRecord {
+            info_hash: "9876543210987654321098765432109876543210".into(),
+            ..make_record(&seed_dir, vec![])
+        };
+        let manifest = tmp_dir.path().join("manifest.jsonl");
+        std::fs::write(
+            &manifest,
+            format!(
+                "{}\n{}\n",
+                serde_json::to_string(&record).unwrap(),
+                serde_json::to_string(&removed_already).unwrap()
+            ),
+        )
+        .expect("failed to write");
+        let client = sycli::FakeClient::new(vec![sycli::Torrent::for_test(
+            &record.info_hash.to_ascii_uppercase(),
+            &seed_dir,
+            &[("a", 1)],
+        )]);
+        let args = UnimportArgs {
+            manifest,
+            remove_torrents: true,
+            dry_run: false,
+        };
+
+        args.run(&client).unwrap();
+        assert_eq!(
+            *client.calls.borrow(),
+            [format!("remove {}", record.info_hash)]
+        );
+        assert!(!seed_dir.join("a").exists());
+        assert!(data.join("a").is_file());
+    }
+
+    #[test]
+    fn read_manifest_reports_bad_lines() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tmp_dir.path().join("manifest.jsonl");
+        let record = serde_json::to_string(&make_record(Path::new("/links"), vec![])).unwrap();
+        std::fs::write(&path, format!("{record}\n\n{record}\n")).expect("failed to write");
+        assert_eq!(read_manifest(&path).unwrap().len(), 2);
+
+        std::fs::write(&path, format!("{record}\nnot json\n")).expect("failed to write");
+        let err = read_manifest(&path).unwrap_err();
+        assert!(err.to_string().starts_with("invalid record on line 2 of"));
+    }
+}
@@ -1,8 +1,10 @@
+use anyhow::bail;
 use clap::Args;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::fs;
 use crate::sycli;
+use crate::util;
 
 #[derive(Args)]
 pub struct UpdatePathsArgs {
@@ -21,6 +23,7 @@ impl UpdatePathsArgs {
     pub fn exec(self) -> anyhow::Result<()> {
         let source = std::path::absolute(self.source)?;
         let target = std::path::absolute(self.target)?;
+        validate_paths(&source, &target)?;
 
         // TODO: Consider changing this logic to handle paths in a similar way to the move
         // subcommand, since it could be used to help pick up the pieces if move fails in the
@@ -28,7 +31,7 @@ impl UpdatePathsArgs {
         for torrent in sycli::get_torrents()? {
             if let Ok(remainder) = torrent.base_path.strip_prefix(&source) {
                 let new_base_path = target.join(remainder);
-                eprintln!(
+                util::info!(
                     "Updating {} from {} to {}",
                     torrent.id,
                     source.display(),
@@ -39,16 +42,23 @@ impl UpdatePathsArgs {
         }
 
         for symlink_dir in self.symlink_dir {
-            for (symlink, original_target_path) in fs::collect_symlinks(&symlink_dir)? {
+            for (symlink, original_target_path) in fs::collect_symlinks(&symlink_dir, true)? {
                 if let Ok(remainder) = original_target_path.strip_prefix(&source) {
                     let new_target_path = target.join(remainder);
-                    eprintln!(
+                    util::info!(
                         "Updating symlink {} from {} to {}",
                         symlink.display(),
                         original_target_path.display(),
                         new_target_path.display()
                     );
                     fs::create_or_update_symlink(&symlink, &new_target_path)?;
+                    if fs::is_dangling(&symlink) {
+                        util::warning!(
+                            "updated symlink {} points to missing target {}",
+                            symlink.display(),
+                            new_target_path.display()
+                        );
+                    }
                 }
             }
         }
@@ -56,3 +66,43 @@ impl UpdatePathsArgs {
         Ok(())
     }
 }
+
+/// Rejects a `source` and `target` that contain one another. Rewriting paths under `source` to
+/// paths under a nested `target` (or the reverse) mixes moved and unmoved paths, and running the
+/// command again would keep rewriting them.
+fn validate_paths(source: &Path, target: &Path) -> anyhow::Result<()> {
+    if target.starts_with(source) {
+        bail!(
+            "target {} is inside source {}; did you swap them?",
+            target.display(),
+            source.display()
+        );
+    }
+    if source.starts_with(target) {
+        bail!(
+            "source {} is inside target {}; did you swap them?",
+            source.display(),
+            target.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_paths_unrelated() {
+        assert!(validate_paths(Path::new("/data/old"), Path::new("/data/new")).is_ok());
+        // Only whole components count, so a shared string prefix is fine.
+        assert!(validate_paths(Path::new("/data/a"), Path::new("/data/ab")).is_ok());
+    }
+
+    #[test]
+    fn validate_paths_rejects_nesting() {
+        assert!(validate_paths(Path::new("/data"), Path::new("/data")).is_err());
+        assert!(validate_paths(Path::new("/data"), Path::new("/data/new")).is_err());
+        assert!(validate_paths(Path::new("/data/old"), Path::new("/data")).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use anyhow::bail;
+use clap::Args;
+use console::style;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::torrent;
+use crate::util;
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    /// Torrent file to verify, or `-` to read it from stdin.
+    torrent: PathBuf,
+
+    /// Directory containing the torrent data, i.e. the directory a client would seed it from.
+    #[arg(long)]
+    source: PathBuf,
+}
+
+impl VerifyArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        let torrent: torrent::Torrent =
+            serde_bencode::from_bytes(&util::read_input(&self.torrent)?)?;
+        if let Some(creation_date) = torrent.creation_date {
+            util::info!("created on {}", util::format_timestamp(creation_date));
+        }
+        if let Some(created_by) = &torrent.created_by {
+            util::info!("created by {created_by}");
+        }
+        if let Some(comment) = &torrent.comment {
+            util::info!("comment: {comment}");
+        }
+
+        let disk_paths = torrent
+            .info
+            .files
+            .iter()
+            .map(|file| self.source.join(&file.path))
+            .collect::<Vec<_>>();
+
+        // Files that are missing or have the wrong size cannot possibly match, so don't bother
+        // hashing pieces that include them.
+        let mut missing = BTreeSet::new();
+        let mut mapping = HashMap::new();
+        for (file, disk_path) in torrent.info.files.iter().zip(&disk_paths) {
+            match std::fs::metadata(disk_path) {
+                Ok(metadata) if metadata.is_file() && metadata.len() == file.length => {
+                    mapping.insert(file.path.as_path(), disk_path.as_path());
+                }
+                _ => {
+                    missing.insert(file.path.as_path());
+                }
+            }
+        }
+        let (pieces, skipped_pieces): (Vec<_>, Vec<_>) =
+            torrent.info.pieces.iter().cloned().partition(|piece| {
+                piece
+                    .file_slices
+                    .iter()
+                    .all(|slice| mapping.contains_key::<Path>(slice.path.as_ref()))
+            });
+
+        let bar = util::new_progress_bar().with_message("hashing...");
+        bar.set_length(pieces.iter().map(torrent::Piece::length).sum());
+        let corrupt = torrent::find_failed_paths(&pieces, &mapping, None, &bar)?
+            .into_iter()
+            .collect::<BTreeSet<_>>();
+        bar.finish_using_style();
+
+        // Files that share a piece with a missing file can only be partially verified.
+        let unverified = skipped_pieces
+            .iter()
+            .flat_map(|piece| piece.file_slices.iter().map(|slice| slice.path.as_path()))
+            .filter(|path| !missing.contains(path) && !corrupt.contains(path))
+            .collect::<BTreeSet<_>>();
+
+        let total = torrent.info.files.len();
+        let failed = missing.len() + corrupt.len() + unverified.len();
+        println!(
+            "verified {} against {}: {} of {} file(s) ok",
+            self.torrent.display(),
+            self.source.display(),
+            total - failed,
+            total
+        );
+        for path in &missing {
+            println!("  {} {}", style("missing:").red(), path.display());
+        }
+        for path in &corrupt {
+            println!("  {} {}", style("corrupt:").red(), path.display());
+        }
+        for path in &unverified {
+            println!("  {} {}", style("unverified:").yellow(), path.display());
+        }
+
+        if failed > 0 {
+            bail!("{failed} file(s) failed verification");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a single-file torrent for a file named "a" with `contents` to `dir`, and returns
+    /// its path.
+    fn write_torrent(dir: &Path, contents: &[u8]) -> PathBuf {
+        let mut bytes = format!(
+            "d8:announce20:https://example.com/4:infod6:lengthi{}e4:name1:a12:piece lengthi16384e\
+             6:pieces20:",
+            contents.len()
+        )
+        .into_bytes();
+        bytes.extend(sha1_smol::Sha1::from(contents).digest().bytes());
+        bytes.extend(b"ee");
+        let path = dir.join("a.torrent");
+        std::fs::write(&path, bytes).expect("failed to create test torrent");
+        path
+    }
+
+    #[test]
+    fn verify_passes_with_matching_data() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        std::fs::write(tmp_dir.path().join("a"), "hello").expect("failed to create test file");
+        let args = VerifyArgs {
+            torrent: write_torrent(tmp_dir.path(), b"hello"),
+            source: tmp_dir.path().to_path_buf(),
+        };
+        args.exec().unwrap();
+    }
+
+    #[test]
+    fn verify_fails_with_corrupt_or_missing_data() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let torrent = write_torrent(tmp_dir.path(), b"hello");
+        std::fs::write(tmp_dir.path().join("a"), "jello").expect("failed to create test file");
+        let args = VerifyArgs {
+            torrent: torrent.clone(),
+            source: tmp_dir.path().to_path_buf(),
+        };
+        let err = args.exec().unwrap_err();
+        assert_eq!(err.to_string(), "1 file(s) failed verification");
+
+        std::fs::remove_file(tmp_dir.path().join("a")).expect("failed to remove test file");
+        let args = VerifyArgs {
+            torrent,
+            source: tmp_dir.path().to_path_buf(),
+        };
+        let err = args.exec().unwrap_err();
+        assert_eq!(err.to_string(), "1 file(s) failed verification");
+    }
+}
@@ -0,0 +1,81 @@
+use anyhow::bail;
+use clap::Args;
+use std::collections::BTreeMap;
+
+use crate::sycli;
+use crate::util;
+
+#[derive(Args)]
+pub struct WhereArgs {
+    /// Infohash of the torrent, or any unambiguous prefix of it.
+    infohash: String,
+}
+
+impl WhereArgs {
+    pub fn exec(self) -> anyhow::Result<()> {
+        let torrents = sycli::new_instance(false).get_torrents()?;
+        let torrent = find_by_id_prefix(&torrents, &self.infohash)?;
+
+        println!("{} {}", torrent.id, torrent.name);
+        println!("{}", torrent.base_path.display());
+        for (path, file) in torrent.files.iter().collect::<BTreeMap<_, _>>() {
+            println!(
+                "  {} ({}){}",
+                torrent.base_path.join(path).display(),
+                util::format_bytes(file.size as u64),
+                if file.wanted { "" } else { " (not wanted)" }
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Returns the only torrent whose id starts with `prefix`, ignoring case.
+fn find_by_id_prefix<'a>(
+    torrents: &'a [sycli::Torrent],
+    prefix: &str,
+) -> anyhow::Result<&'a sycli::Torrent> {
+    let prefix = prefix.to_lowercase();
+    let matches = torrents
+        .iter()
+        .filter(|torrent| torrent.id.to_lowercase().starts_with(&prefix))
+        .collect::<Vec<_>>();
+    match matches[..] {
+        [] => bail!("no torrent matches {prefix}"),
+        [torrent] => Ok(torrent),
+        _ => bail!(
+            "{prefix} is ambiguous; it matches {}",
+            matches
+                .iter()
+                .map(|torrent| torrent.id.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_by_id_prefix_matches() {
+        let torrents = [
+            sycli::Torrent::for_test("abcdef", "/data", &[]),
+            sycli::Torrent::for_test("abd123", "/data", &[]),
+        ];
+        assert_eq!(find_by_id_prefix(&torrents, "abc").unwrap().id, "abcdef");
+        assert_eq!(find_by_id_prefix(&torrents, "ABD").unwrap().id, "abd123");
+        assert_eq!(find_by_id_prefix(&torrents, "abcdef").unwrap().id, "abcdef");
+    }
+
+    #[test]
+    fn find_by_id_prefix_errors() {
+        let torrents = [
+            sycli::Torrent::for_test("abcdef", "/data", &[]),
+            sycli::Torrent::for_test("abd123", "/data", &[]),
+        ];
+        assert!(find_by_id_prefix(&torrents, "ab").is_err());
+        assert!(find_by_id_prefix(&torrents, "f").is_err());
+    }
+}
@@ -1,28 +1,102 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
 use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Output};
+use std::time::Duration;
 
-use anyhow::{anyhow, bail, Result};
+use anyhow::{Result, anyhow, bail};
 use console::style;
 use serde::Deserialize;
 use thiserror::Error;
+use tracing::{debug, trace};
 
+use crate::util;
+
+/// Returns the sycli binary to run: `TORU_SYCLI` if set, then `sycli.binary` from the config, and
+/// otherwise `sycli` from `PATH`.
+fn sycli_binary() -> PathBuf {
+    std::env::var_os("TORU_SYCLI")
+        .map(PathBuf::from)
+        .or_else(|| crate::config::config().sycli.binary.clone())
+        .unwrap_or_else(|| "sycli".into())
+}
+
+/// Runs sycli with `args`. If sycli fails to connect to the Synapse daemon, e.g. because it was
+/// just restarted, the command is retried with exponential backoff.
+fn run_sycli(args: &[&OsStr]) -> Result<Output> {
+    let binary = sycli_binary();
+    let retries = crate::config::config().sycli.retries;
+    let mut delay = Duration::from_millis(200);
+    debug!(binary = %binary.display(), ?args, "running sycli");
+    for _ in 0..retries {
+        let output = Command::new(&binary).args(args).output()?;
+        if output.status.success() || !is_transient_error(&String::from_utf8_lossy(&output.stderr))
+        {
+            trace!(status = %output.status, "sycli finished");
+            return Ok(output);
+        }
+        debug!(?delay, "could not connect to sycli; retrying");
+        std::thread::sleep(delay);
+        delay *= 2;
+    }
+    Ok(Command::new(&binary).args(args).output()?)
+}
+
+/// Whether sycli's `stderr` reports an I/O error of a kind that is worth retrying. sycli only
+/// reports errors as text, so this looks for the descriptions of those kinds, as the OS words
+/// them, e.g. "Connection refused (os error 111)" for `ConnectionRefused`.
+fn is_transient_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    util::TRANSIENT_IO_ERRORS
+        .iter()
+        .any(|kind| stderr.contains(&kind.to_string()))
+}
+
+fn check_status(output: Output) -> Result<Output> {
+    if !output.status.success() {
+        bail!(
+            "sycli finished with non-zero status: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    Ok(output)
+}
+
+/// A torrent client. Subcommands that orchestrate several operations take a `&dyn Client` so they
+/// can be tested against a fake client.
 pub trait Client {
-    fn add_torrent(&self, torrent_path: &Path, seed_path: &Path) -> Result<()>;
+    fn get_torrents(&self) -> Result<Vec<Torrent>>;
+    /// Adds the torrent at `torrent_path` to the client, seeding from the existing data in
+    /// `seed_path`. `seed_path` is passed through as an `OsStr`, so it need not be valid UTF-8. If
+    /// `paused` is set, the torrent is added without starting it.
+    fn add_torrent(&self, torrent_path: &Path, seed_path: &Path, paused: bool) -> Result<()>;
+    fn pause_torrent(&self, torrent_id: &str) -> Result<()>;
+    fn resume_torrent(&self, torrent_id: &str) -> Result<()>;
+    /// Points the torrent at `dir_path` without moving any of its files.
+    fn move_torrent(&self, torrent_id: &str, dir_path: &Path) -> Result<()>;
+    /// Removes the torrent from the client, leaving its data on disk.
+    fn remove_torrent(&self, torrent_id: &str) -> Result<()>;
 }
 
 struct Synapse;
 
 impl Client for Synapse {
-    fn add_torrent(&self, torrent_path: &Path, seed_path: &Path) -> Result<()> {
-        let output = Command::new("sycli")
-            .arg("add")
-            .arg("--import")
-            .arg("--directory")
-            .arg(seed_path)
-            .arg(torrent_path)
-            .output()?;
+    fn get_torrents(&self) -> Result<Vec<Torrent>> {
+        get_torrents()
+    }
+
+    fn add_torrent(&self, torrent_path: &Path, seed_path: &Path, paused: bool) -> Result<()> {
+        let mut args: Vec<&OsStr> = vec!["add".as_ref(), "--import".as_ref()];
+        if paused {
+            args.push("--pause".as_ref());
+        }
+        args.extend([
+            "--directory".as_ref(),
+            seed_path.as_os_str(),
+            torrent_path.as_os_str(),
+        ]);
+        let output = run_sycli(&args)?;
 
         let result = match output.status.code() {
             Some(0) => Ok(()),
@@ -40,23 +114,143 @@ impl Client for Synapse {
         }
         result
     }
+
+    fn pause_torrent(&self, torrent_id: &str) -> Result<()> {
+        pause_torrent(torrent_id)
+    }
+
+    fn resume_torrent(&self, torrent_id: &str) -> Result<()> {
+        resume_torrent(torrent_id)
+    }
+
+    fn move_torrent(&self, torrent_id: &str, dir_path: &Path) -> Result<()> {
+        move_torrent(torrent_id, dir_path)
+    }
+
+    fn remove_torrent(&self, torrent_id: &str) -> Result<()> {
+        remove_torrent(torrent_id)
+    }
 }
 
+/// Reads from the real client, but only prints the changes it would have made.
 struct DryRun;
 
 impl Client for DryRun {
-    fn add_torrent(&self, torrent_path: &Path, seed_path: &Path) -> Result<()> {
+    fn get_torrents(&self) -> Result<Vec<Torrent>> {
+        get_torrents()
+    }
+
+    fn add_torrent(&self, torrent_path: &Path, seed_path: &Path, paused: bool) -> Result<()> {
         println!(
-            "{} {} {} {}",
+            "{} {} {} {}{}",
             style("seeding").green(),
             style(torrent_path.display()).cyan(),
             style("from").green(),
-            style(seed_path.display()).cyan()
+            style(seed_path.display()).cyan(),
+            if paused {
+                style(" (paused)").yellow()
+            } else {
+                style("")
+            }
         );
         Ok(())
     }
+
+    // Subcommands report the changes they make to existing torrents themselves, so these only
+    // need to skip them.
+
+    fn pause_torrent(&self, torrent_id: &str) -> Result<()> {
+        debug!(torrent_id, "dry run: not pausing");
+        Ok(())
+    }
+
+    fn resume_torrent(&self, torrent_id: &str) -> Result<()> {
+        debug!(torrent_id, "dry run: not resuming");
+        Ok(())
+    }
+
+    fn move_torrent(&self, torrent_id: &str, dir_path: &Path) -> Result<()> {
+        debug!(torrent_id, dir_path = %dir_path.display(), "dry run: not moving");
+        Ok(())
+    }
+
+    fn remove_torrent(&self, torrent_id: &str) -> Result<()> {
+        debug!(torrent_id, "dry run: not removing");
+        Ok(())
+    }
+}
+
+/// An in-memory client for tests. It records each change it is asked to make in `calls`.
+#[cfg(test)]
+pub struct FakeClient {
+    pub torrents: std::cell::RefCell<Vec<Torrent>>,
+    pub calls: std::cell::RefCell<Vec<String>>,
+}
+
+#[cfg(test)]
+impl FakeClient {
+    pub fn new(torrents: Vec<Torrent>) -> Self {
+        Self {
+            torrents: torrents.into(),
+            calls: vec![].into(),
+        }
+    }
+
+    pub fn base_path(&self, torrent_id: &str) -> PathBuf {
+        self.torrents
+            .borrow()
+            .iter()
+            .find(|torrent| torrent.id == torrent_id)
+            .unwrap()
+            .base_path
+            .clone()
+    }
+}
+
+#[cfg(test)]
+impl Client for FakeClient {
+    fn get_torrents(&self) -> Result<Vec<Torrent>> {
+        Ok(self.torrents.borrow().clone())
+    }
+
+    fn add_torrent(&self, torrent_path: &Path, _seed_path: &Path, _paused: bool) -> Result<()> {
+        self.calls
+            .borrow_mut()
+            .push(format!("add {}", torrent_path.display()));
+        Ok(())
+    }
+
+    fn pause_torrent(&self, torrent_id: &str) -> Result<()> {
+        self.calls.borrow_mut().push(format!("pause {torrent_id}"));
+        Ok(())
+    }
+
+    fn resume_torrent(&self, torrent_id: &str) -> Result<()> {
+        self.calls.borrow_mut().push(format!("resume {torrent_id}"));
+        Ok(())
+    }
+
+    fn move_torrent(&self, torrent_id: &str, dir_path: &Path) -> Result<()> {
+        self.calls.borrow_mut().push(format!("move {torrent_id}"));
+        for torrent in self.torrents.borrow_mut().iter_mut() {
+            if torrent.id == torrent_id {
+                torrent.base_path = dir_path.to_path_buf();
+            }
+        }
+        Ok(())
+    }
+
+    fn remove_torrent(&self, torrent_id: &str) -> Result<()> {
+        self.calls.borrow_mut().push(format!("remove {torrent_id}"));
+        self.torrents
+            .borrow_mut()
+            .retain(|torrent| torrent.id != torrent_id);
+        Ok(())
+    }
 }
 
+/// Returns a client that shells out to sycli, or if `dry_run` is set, one that only prints what
+/// would have been changed.
 // TODO: Support more clients.
 pub fn new_instance(dry_run: bool) -> Box<dyn Client> {
     if dry_run {
@@ -75,14 +269,41 @@ struct RawTorrent {
     tracker_urls: Vec<String>,
     size: usize,
     files: usize,
+    // Synapse has no native concept of labels, so this is currently always absent. It is
+    // deserialized anyway so that labels work if sycli ever exposes them.
+    label: Option<String>,
+    /// When the torrent was added to the client, as an RFC 3339 timestamp.
+    #[serde(default)]
+    created: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct RawFile {
-    id: String,
     torrent_id: String,
     path: PathBuf,
     size: usize,
+    /// Download priority; 0 means the file was deselected and will not be downloaded.
+    #[serde(default)]
+    priority: Option<u8>,
+    #[serde(default)]
+    progress: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawTracker {
+    torrent_id: String,
+    url: String,
+    last_report: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrackerStatus {
+    pub url: String,
+    /// When the tracker was last successfully announced to, if ever.
+    pub last_report: Option<String>,
+    /// The error from the last announce, if it failed.
+    pub error: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -94,43 +315,164 @@ pub struct Torrent {
     pub progress: f64,
     pub tracker_urls: Vec<String>,
     pub size: usize,
-    pub files: HashMap<PathBuf, usize>,
+    pub label: Option<String>,
+    /// When the torrent was added to the client, as a Unix timestamp, if known.
+    pub added: Option<i64>,
+    pub trackers: Vec<TrackerStatus>,
+    pub files: HashMap<PathBuf, FileInfo>,
 }
 
-fn get_raw_torrents() -> Result<Vec<RawTorrent>> {
-    let output = Command::new("sycli")
-        .args(["list", "-k", "torrent", "-o", "json"])
-        .output()?;
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileInfo {
+    pub size: usize,
+    /// False if the file was deselected in the client, so it is never downloaded. A partial
+    /// torrent may be missing such files on disk entirely.
+    pub wanted: bool,
+    /// Download progress of just this file, from 0.0 to 1.0, if the client reports it.
+    pub progress: Option<f64>,
+}
 
-    if !output.status.success() {
-        bail!(
-            "sycli finished with non-zero status: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+/// A wanted file of the given size, with unknown progress.
+impl From<usize> for FileInfo {
+    fn from(size: usize) -> Self {
+        FileInfo {
+            size,
+            wanted: true,
+            progress: None,
+        }
     }
+}
 
-    Ok(serde_json::from_str(&String::from_utf8(output.stdout)?)?)
+#[cfg(test)]
+impl Torrent {
+    /// Returns a complete torrent in `base_path` with the given files and sizes, named after the
+    /// first file's top-level path component. Tests override other fields with struct update
+    /// syntax.
+    pub fn for_test(id: &str, base_path: impl Into<PathBuf>, files: &[(&str, usize)]) -> Self {
+        let name = files
+            .first()
+            .and_then(|(path, _)| Path::new(path).iter().next())
+            .map_or_else(|| id.into(), |name| name.to_string_lossy().into_owned());
+        Torrent {
+            id: id.into(),
+            name,
+            base_path: base_path.into(),
+            progress: 1.0,
+            tracker_urls: vec![],
+            size: files.iter().map(|(_, size)| size).sum(),
+            label: None,
+            added: None,
+            trackers: vec![],
+            files: files
+                .iter()
+                .map(|(path, size)| (path.into(), (*size).into()))
+                .collect(),
+        }
+    }
 }
 
-fn get_raw_files() -> Result<Vec<RawFile>> {
-    let output = Command::new("sycli")
-        .args(["list", "-k", "file", "-o", "json"])
-        .output()?;
+impl Torrent {
+    /// Returns whether every wanted file has been downloaded. For a torrent with deselected files,
+    /// the overall progress never reaches 1.0, so this falls back to per-file progress.
+    pub fn is_complete(&self) -> bool {
+        self.progress == 1.0
+            || (self.files.values().any(|file| !file.wanted)
+                && self
+                    .files
+                    .values()
+                    .filter(|file| file.wanted)
+                    .all(|file| file.progress == Some(1.0)))
+    }
 
-    if !output.status.success() {
-        bail!(
-            "sycli finished with non-zero status: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
+    /// Returns the trackers that reported an error on the last announce.
+    pub fn tracker_errors(&self) -> impl Iterator<Item = &TrackerStatus> {
+        self.trackers
+            .iter()
+            .filter(|tracker| tracker.error.is_some())
     }
 
-    Ok(serde_json::from_str(&String::from_utf8(output.stdout)?)?)
+    /// Returns whether the host of any of the torrent's tracker URLs contains one of `hosts`,
+    /// ignoring case. Always true if `hosts` is empty.
+    pub fn matches_tracker(&self, hosts: &[String]) -> bool {
+        hosts.is_empty() || self.has_tracker_host(hosts)
+    }
+
+    /// Like `matches_tracker` with `include`, but additionally false if the host of any of the
+    /// torrent's tracker URLs contains one of `exclude`, even if another one matches `include`.
+    pub fn matches_tracker_filters(&self, include: &[String], exclude: &[String]) -> bool {
+        self.matches_tracker(include) && !self.has_tracker_host(exclude)
+    }
+
+    fn has_tracker_host(&self, hosts: &[String]) -> bool {
+        self.tracker_urls
+            .iter()
+            .filter_map(|url| util::url_host(url).ok())
+            .any(|host| {
+                let host = host.to_lowercase();
+                hosts
+                    .iter()
+                    .any(|pattern| host.contains(&pattern.to_lowercase()))
+            })
+    }
+}
+
+fn get_raw_torrents() -> Result<Vec<RawTorrent>> {
+    let output = check_status(run_sycli(
+        &["list", "-k", "torrent", "-o", "json"].map(OsStr::new),
+    )?)?;
+
+    // JSON cannot represent non-UTF8 paths, so there is no point in handling them here.
+    Ok(serde_json::from_slice(&output.stdout)?)
 }
 
+fn get_raw_files() -> Result<Vec<RawFile>> {
+    let output = check_status(run_sycli(
+        &["list", "-k", "file", "-o", "json"].map(OsStr::new),
+    )?)?;
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+fn get_raw_trackers() -> Result<Vec<RawTracker>> {
+    let output = check_status(run_sycli(
+        &["list", "-k", "tracker", "-o", "json"].map(OsStr::new),
+    )?)?;
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+/// Torrents and files are listed with separate sycli invocations, so torrents may be added or
+/// removed in between. This is reported as its own error so the pair of queries can be retried.
+#[derive(Debug, Error)]
+#[error("{0}; the client's torrent list was probably modified concurrently, try re-running")]
+struct ConcurrentModificationError(String);
+
 pub fn get_torrents() -> Result<Vec<Torrent>> {
-    let raw_torrents = get_raw_torrents()?;
-    let raw_files = get_raw_files()?;
+    const ATTEMPTS: usize = 3;
 
+    let mut attempt = 1;
+    loop {
+        let raw_torrents = get_raw_torrents()?;
+        let raw_files = get_raw_files()?;
+        // Tracker status is only informational, so a client that can't list it is not an error.
+        let raw_trackers = get_raw_trackers().unwrap_or_else(|err| {
+            debug!(err = %format!("{err:#}"), "unable to list tracker status");
+            vec![]
+        });
+        match merge_torrents_and_files(raw_torrents, raw_files, raw_trackers) {
+            Err(err) if attempt < ATTEMPTS && err.is::<ConcurrentModificationError>() => {
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn merge_torrents_and_files(
+    raw_torrents: Vec<RawTorrent>,
+    raw_files: Vec<RawFile>,
+    raw_trackers: Vec<RawTracker>,
+) -> Result<Vec<Torrent>> {
     let mut torrents = raw_torrents
         .into_iter()
         .map(|t| {
@@ -144,6 +486,9 @@ pub fn get_torrents() -> Result<Vec<Torrent>> {
                         progress: t.progress,
                         tracker_urls: t.tracker_urls,
                         size: t.size,
+                        label: t.label,
+                        added: t.created.as_deref().and_then(util::parse_timestamp),
+                        trackers: vec![],
                         files: HashMap::new(),
                     },
                     t.files,
@@ -155,10 +500,15 @@ pub fn get_torrents() -> Result<Vec<Torrent>> {
     // TODO: Consider implementing detection of single-file torrents here by checking if the
     // torrent name and the file path are equal.
     for f in raw_files {
-        let (torrent, _files_count) = torrents
-            .get_mut(&f.torrent_id)
-            .ok_or_else(|| anyhow!("{:?} has no matching torrent", f))?;
-        if let Some(_old_value) = torrent.files.insert(f.path.clone(), f.size) {
+        let (torrent, _files_count) = torrents.get_mut(&f.torrent_id).ok_or_else(|| {
+            ConcurrentModificationError(format!("{:?} has no matching torrent", f))
+        })?;
+        let file = FileInfo {
+            size: f.size,
+            wanted: f.priority != Some(0),
+            progress: f.progress,
+        };
+        if let Some(_old_value) = torrent.files.insert(f.path.clone(), file) {
             bail!(
                 "{:?} has multiple entries for the same path: {}",
                 torrent,
@@ -167,18 +517,31 @@ pub fn get_torrents() -> Result<Vec<Torrent>> {
         }
     }
 
+    // Tracker status is purely informational, so trackers for unknown torrents are ignored rather
+    // than treated as a concurrent modification.
+    for t in raw_trackers {
+        if let Some((torrent, _files_count)) = torrents.get_mut(&t.torrent_id) {
+            torrent.trackers.push(TrackerStatus {
+                url: t.url,
+                last_report: t.last_report,
+                error: t.error,
+            });
+        }
+    }
+
     torrents
         .into_iter()
         .map(|(_, (torrent, files_count))| {
             if torrent.files.len() != files_count {
-                bail!(
+                return Err(ConcurrentModificationError(format!(
                     "torrent {:?}: got {} files but expected {}",
                     torrent,
                     torrent.files.len(),
                     files_count
-                );
+                ))
+                .into());
             }
-            let file_sizes: usize = torrent.files.values().sum();
+            let file_sizes: usize = torrent.files.values().map(|file| file.size).sum();
             if file_sizes != torrent.size {
                 bail!(
                     "torrent {:?}: files total {} bytes but expected {}",
@@ -193,53 +556,28 @@ pub fn get_torrents() -> Result<Vec<Torrent>> {
 }
 
 pub fn pause_torrent(torrent_id: &str) -> Result<()> {
-    let output = Command::new("sycli").args(["pause", torrent_id]).output()?;
-
-    if !output.status.success() {
-        bail!(
-            "sycli finished with non-zero status: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-    }
-
+    check_status(run_sycli(&["pause", torrent_id].map(OsStr::new))?)?;
     Ok(())
 }
 
 pub fn resume_torrent(torrent_id: &str) -> Result<()> {
-    let output = Command::new("sycli")
-        .args(["resume", torrent_id])
-        .output()?;
-
-    if !output.status.success() {
-        bail!(
-            "sycli finished with non-zero status: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-    }
+    check_status(run_sycli(&["resume", torrent_id].map(OsStr::new))?)?;
+    Ok(())
+}
 
+pub fn remove_torrent(torrent_id: &str) -> Result<()> {
+    check_status(run_sycli(&["remove", torrent_id].map(OsStr::new))?)?;
     Ok(())
 }
 
 pub fn move_torrent(torrent_id: &str, dir_path: &Path) -> Result<()> {
-    let output = Command::new("sycli")
-        .args([
-            "torrent",
-            torrent_id,
-            "move",
-            "--skip-files",
-            dir_path
-                .to_str()
-                .ok_or_else(|| anyhow!("move_torrent cannot handle non-UTF8 paths"))?,
-        ])
-        .output()?;
-
-    if !output.status.success() {
-        bail!(
-            "sycli finished with non-zero status: {}",
-            String::from_utf8_lossy(&output.stderr)
-        );
-    }
-
+    check_status(run_sycli(&[
+        "torrent".as_ref(),
+        torrent_id.as_ref(),
+        "move".as_ref(),
+        "--skip-files".as_ref(),
+        dir_path.as_ref(),
+    ])?)?;
     Ok(())
 }
 
@@ -262,38 +600,64 @@ pub fn filter_torrents(
     torrents: &[Torrent],
     source_files: &HashMap<PathBuf, u64>,
 ) -> Result<Vec<Torrent>, FilterTorrentsError> {
+    Ok(filter_torrents_with_matches(torrents, source_files)?
+        .into_iter()
+        .map(|matched| matched.torrent)
+        .collect())
+}
+
+/// A torrent returned by `filter_torrents_with_matches`, along with the source files it covers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchedTorrent {
+    pub torrent: Torrent,
+    pub matched_paths: BTreeSet<PathBuf>,
+}
+
+/// Like `filter_torrents`, but also returns the paths in `source_files` covered by each torrent.
+pub fn filter_torrents_with_matches(
+    torrents: &[Torrent],
+    source_files: &HashMap<PathBuf, u64>,
+) -> Result<Vec<MatchedTorrent>, FilterTorrentsError> {
     type Error = FilterTorrentsError;
 
     let mut filtered_torrents = vec![];
     let mut included_paths = HashSet::new();
     for torrent in torrents {
-        let (included, missing) =
-            torrent
-                .files
-                .iter()
-                .fold((0, vec![]), |(included, mut missing), (path, size)| {
-                    let path = torrent.base_path.join(path);
-                    if source_files.contains_key(&path) {
-                        included_paths.insert(path);
-                        (included + 1, missing)
-                    } else {
-                        missing.push((path.clone(), *size));
-                        (included, missing)
-                    }
-                });
+        let (matched_paths, missing) = torrent.files.iter().fold(
+            (BTreeSet::new(), vec![]),
+            |(mut matched_paths, mut missing), (path, file)| {
+                let path = torrent.base_path.join(path);
+                if source_files.contains_key(&path) {
+                    matched_paths.insert(path);
+                } else {
+                    missing.push((path, *file));
+                }
+                (matched_paths, missing)
+            },
+        );
         if missing.len() == torrent.files.len() {
             // Torrent has no files specified in source files, so it is not interesting.
             continue;
         }
         // Some torrent clients do not create actual files on disk for 0-byte files. This is
-        // probably a bug, but for now, this is non-fatal.
-        if included > 0 && !missing.is_empty() && missing.iter().any(|(_, size)| *size != 0) {
+        // probably a bug, but for now, this is non-fatal. Deselected files are never downloaded,
+        // so they are not expected to be on disk either.
+        if !matched_paths.is_empty()
+            && !missing.is_empty()
+            && missing
+                .iter()
+                .any(|(_, file)| file.size != 0 && file.wanted)
+        {
             return Err(Error::TorrentIncludesSourceAndNonSourceFiles(
                 torrent.id.clone(),
                 missing.into_iter().map(|(path, _)| path).collect(),
             ));
         }
-        filtered_torrents.push(torrent.clone());
+        included_paths.extend(matched_paths.iter().cloned());
+        filtered_torrents.push(MatchedTorrent {
+            torrent: torrent.clone(),
+            matched_paths,
+        });
     }
 
     match (included_paths.len(), source_files.len()) {
@@ -306,6 +670,22 @@ pub fn filter_torrents(
 mod tests {
     use super::*;
 
+    #[test]
+    fn is_transient_error_matches_retryable_io_errors() {
+        assert!(is_transient_error(
+            "Failed to connect to synapse: Connection refused (os error 111)"
+        ));
+        assert!(is_transient_error(
+            "Connection reset by peer (os error 104)"
+        ));
+        assert!(is_transient_error("Connection timed out (os error 110)"));
+        assert!(!is_transient_error(
+            "Failed to connect to synapse, ensure your URI and password are correct"
+        ));
+        assert!(!is_transient_error("client disconnected: unknown torrent"));
+        assert!(!is_transient_error(""));
+    }
+
     #[test]
     fn torrent_deserialize() {
         let json = r#"
@@ -331,6 +711,45 @@ mod tests {
         assert_eq!(t.tracker_urls, &["example.com"]);
         assert_eq!(t.size, 88888888);
         assert_eq!(t.files, 1);
+        assert_eq!(t.label, None);
+    }
+
+    #[test]
+    fn matches_tracker_by_host() {
+        let torrent = Torrent {
+            name: "test".into(),
+            tracker_urls: vec![
+                "https://Tracker.Example.org:443/announce?passkey=other.net".into(),
+                "not a url".into(),
+            ],
+            ..Torrent::for_test("0123456789012345678901234567890123456789", "/tmp", &[])
+        };
+        assert!(torrent.matches_tracker(&[]));
+        assert!(torrent.matches_tracker(&["example.org".into()]));
+        assert!(torrent.matches_tracker(&["EXAMPLE".into(), "nope".into()]));
+        // Only the host is matched, not the rest of the URL.
+        assert!(!torrent.matches_tracker(&["other.net".into()]));
+        assert!(!torrent.matches_tracker(&["announce".into()]));
+    }
+
+    #[test]
+    fn matches_tracker_filters_excludes_after_including() {
+        let torrent = Torrent {
+            name: "test".into(),
+            tracker_urls: vec![
+                "https://public.example.org/announce".into(),
+                "https://private.example.net/announce".into(),
+            ],
+            ..Torrent::for_test("0123456789012345678901234567890123456789", "/tmp", &[])
+        };
+        assert!(torrent.matches_tracker_filters(&[], &[]));
+        assert!(torrent.matches_tracker_filters(&["public".into()], &[]));
+        assert!(torrent.matches_tracker_filters(&[], &["other".into()]));
+        // One tracker matching the exclusion is enough to drop the torrent, even if another
+        // tracker matches the inclusion.
+        assert!(!torrent.matches_tracker_filters(&["public".into()], &["private".into()]));
+        assert!(!torrent.matches_tracker_filters(&[], &["PRIVATE".into()]));
+        assert!(!torrent.matches_tracker_filters(&["other".into()], &["nope".into()]));
     }
 
     #[test]
@@ -346,22 +765,138 @@ mod tests {
           }
         "#;
         let f: RawFile = serde_json::from_str(json).unwrap();
-        assert_eq!(f.id, "0123456789012345678901234567890123456789");
         assert_eq!(f.torrent_id, "1234567890123456789012345678901234567890");
         assert_eq!(f.path, Path::new("data.txt"));
         assert_eq!(f.size, 88888888);
+        assert_eq!(f.priority, None);
+    }
+
+    #[test]
+    fn merge_torrents_and_files_marks_deselected_files() {
+        let raw_torrent: RawTorrent = serde_json::from_str(
+            r#"{"id": "t", "name": "t", "path": "/tmp", "progress": 0.5, "tracker_urls": [],
+                "size": 3, "files": 2}"#,
+        )
+        .unwrap();
+        let raw_files: Vec<RawFile> = serde_json::from_str(
+            r#"[{"id": "a", "torrent_id": "t", "path": "a", "size": 1, "priority": 3,
+                 "progress": 1.0},
+                {"id": "b", "torrent_id": "t", "path": "b", "size": 2, "priority": 0,
+                 "progress": 0.0}]"#,
+        )
+        .unwrap();
+        let torrents = merge_torrents_and_files(vec![raw_torrent], raw_files, vec![]).unwrap();
+        assert_eq!(
+            torrents[0].files,
+            HashMap::from([
+                (
+                    "a".into(),
+                    FileInfo {
+                        size: 1,
+                        wanted: true,
+                        progress: Some(1.0)
+                    }
+                ),
+                (
+                    "b".into(),
+                    FileInfo {
+                        size: 2,
+                        wanted: false,
+                        progress: Some(0.0)
+                    }
+                ),
+            ])
+        );
+        assert!(torrents[0].is_complete());
+    }
+
+    #[test]
+    fn is_complete_considers_only_wanted_files() {
+        let file = |wanted, progress| FileInfo {
+            size: 1,
+            wanted,
+            progress: Some(progress),
+        };
+        let mut torrent = Torrent {
+            name: "test".into(),
+            progress: 0.5,
+            size: 2,
+            files: HashMap::from([("a".into(), file(true, 1.0)), ("b".into(), file(true, 0.0))]),
+            ..Torrent::for_test("0123456789012345678901234567890123456789", "/tmp", &[])
+        };
+        assert!(!torrent.is_complete());
+        torrent.files.insert("b".into(), file(false, 0.0));
+        assert!(torrent.is_complete());
+        torrent.files.insert("a".into(), file(true, 0.5));
+        assert!(!torrent.is_complete());
+        torrent.progress = 1.0;
+        assert!(torrent.is_complete());
+    }
+
+    #[test]
+    fn filter_torrents_ignores_missing_deselected_files() {
+        let torrent = Torrent {
+            name: "Test".into(),
+            progress: 0.5,
+            size: 246,
+            files: HashMap::from([
+                ("Test/wanted.txt".into(), 123.into()),
+                (
+                    "Test/skipped.txt".into(),
+                    FileInfo {
+                        size: 123,
+                        wanted: false,
+                        progress: Some(0.0),
+                    },
+                ),
+            ]),
+            ..Torrent::for_test("0123456789012345678901234567890123456789", "/tmp", &[])
+        };
+        let source_files = HashMap::from([("/tmp/Test/wanted.txt".into(), 123)]);
+        assert_eq!(
+            filter_torrents(std::slice::from_ref(&torrent), &source_files),
+            Ok(vec![torrent.clone()])
+        );
+
+        let mut torrent = torrent;
+        torrent.files.insert("Test/skipped.txt".into(), 123.into());
+        assert!(matches!(
+            filter_torrents(&[torrent], &source_files),
+            Err(FilterTorrentsError::TorrentIncludesSourceAndNonSourceFiles(
+                ..
+            ))
+        ));
+    }
+
+    #[test]
+    fn tracker_deserialize() {
+        let json = r#"
+          {
+            "type": "tracker",
+            "id": "0123456789012345678901234567890123456789",
+            "torrent_id": "1234567890123456789012345678901234567890",
+            "url": "https://example.com/announce",
+            "last_report": "2024-01-01T00:00:00Z",
+            "error": "unregistered torrent",
+            "unknown_field": "is_ignored"
+          }
+        "#;
+        let t: RawTracker = serde_json::from_str(json).unwrap();
+        assert_eq!(t.torrent_id, "1234567890123456789012345678901234567890");
+        assert_eq!(t.url, "https://example.com/announce");
+        assert_eq!(t.last_report.as_deref(), Some("2024-01-01T00:00:00Z"));
+        assert_eq!(t.error.as_deref(), Some("unregistered torrent"));
     }
 
     #[test]
     fn filter_torrents_with_no_source_files() {
         let torrent = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test.txt".into(), 123)]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test.txt", 123)],
+            )
         };
         assert_eq!(filter_torrents(&[torrent], &HashMap::new()), Ok(vec![]));
     }
@@ -386,22 +921,20 @@ mod tests {
     #[test]
     fn filter_torrents_normal() {
         let torrent = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test.txt".into(), 123)]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test.txt", 123)],
+            )
         };
         let torrent2 = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test2.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test2.txt".into(), 123)]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test2.txt", 123)],
+            )
         };
         let source_files = HashMap::from([("/tmp/test.txt".into(), 123)]);
         assert_eq!(
@@ -413,17 +946,17 @@ mod tests {
     #[test]
     fn filter_torrents_torrent_with_included_and_non_included_files() {
         let torrent = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
             size: 123,
-            files: HashMap::from([("test.txt".into(), 123), ("test2.txt".into(), 123)]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test.txt", 123), ("test2.txt", 123)],
+            )
         };
         let source_files = HashMap::from([("/tmp/test.txt".into(), 123)]);
         assert_eq!(
-            filter_torrents(&[torrent.clone()], &source_files),
+            filter_torrents(std::slice::from_ref(&torrent), &source_files),
             Err(FilterTorrentsError::TorrentIncludesSourceAndNonSourceFiles(
                 torrent.id.clone(),
                 vec![PathBuf::from("/tmp/test2.txt")],
@@ -434,13 +967,12 @@ mod tests {
     #[test]
     fn filter_torrent_not_all_source_files_matched() {
         let torrent = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test.txt".into(), 123)]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test.txt", 123)],
+            )
         };
         let source_files = HashMap::from([
             ("/tmp/test.txt".into(), 123),
@@ -458,13 +990,12 @@ mod tests {
     #[test]
     fn filter_torrent_no_source_files() {
         let torrent = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test.txt".into(), 123)]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test.txt", 123)],
+            )
         };
         assert_eq!(filter_torrents(&[torrent], &HashMap::new()), Ok(vec![]),);
     }
@@ -472,17 +1003,16 @@ mod tests {
     #[test]
     fn filter_torrent_with_only_empty_file_matched() {
         let torrent = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "empty.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 0,
-            files: HashMap::from([("empty.txt".into(), 0)]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("empty.txt", 0)],
+            )
         };
         let source_files = HashMap::from([("/tmp/empty.txt".into(), 0)]);
         assert_eq!(
-            filter_torrents(&[torrent.clone()], &source_files),
+            filter_torrents(std::slice::from_ref(&torrent), &source_files),
             Ok(vec![torrent])
         );
     }
@@ -490,23 +1020,21 @@ mod tests {
     #[test]
     fn filter_torrent_with_only_empty_file_unmatched() {
         let torrent = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "test.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([("test.txt".into(), 123)]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("test.txt", 123)],
+            )
         };
         // If no files at all are matched, a torrent should not be considered matching.
         let torrent2 = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "empty.txt".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 0,
-            files: HashMap::from([("empty.txt".into(), 0)]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("empty.txt", 0)],
+            )
         };
         let source_files = HashMap::from([("/tmp/test.txt".into(), 0)]);
         assert_eq!(
@@ -518,22 +1046,18 @@ mod tests {
     #[test]
     fn filter_torrent_with_matched_non_empty_file_and_unmatched_empty_file() {
         let torrent = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "Test".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([
-                ("Test/nonempty.txt".into(), 123),
-                ("Test/empty.txt".into(), 0),
-            ]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("Test/nonempty.txt", 123), ("Test/empty.txt", 0)],
+            )
         };
         // Even though `empty.txt` is not specified in source paths, treat that as a match: some
         // clients (buggily?) do not create files for 0-byte files.
         assert_eq!(
             filter_torrents(
-                &[torrent.clone()],
+                std::slice::from_ref(&torrent),
                 &HashMap::from([("/tmp/Test/nonempty.txt".into(), 123)])
             ),
             Ok(vec![torrent])
@@ -543,20 +1067,16 @@ mod tests {
     #[test]
     fn filter_torrent_with_matched_non_empty_file_and_matched_empty_file() {
         let torrent = Torrent {
-            id: "0123456789012345678901234567890123456789".into(),
-            name: "Test".into(),
-            base_path: "/tmp".into(),
-            progress: 1.0,
             tracker_urls: vec!["https://example.com:9999".into()],
-            size: 123,
-            files: HashMap::from([
-                ("Test/nonempty.txt".into(), 123),
-                ("Test/empty.txt".into(), 0),
-            ]),
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("Test/nonempty.txt", 123), ("Test/empty.txt", 0)],
+            )
         };
         assert_eq!(
             filter_torrents(
-                &[torrent.clone()],
+                std::slice::from_ref(&torrent),
                 &HashMap::from([
                     ("/tmp/Test/nonempty.txt".into(), 123),
                     ("/tmp/Test/empty.txt".into(), 0)
@@ -565,4 +1085,42 @@ mod tests {
             Ok(vec![torrent])
         );
     }
+
+    #[test]
+    fn filter_torrents_with_matches_reports_matched_paths() {
+        let torrent = Torrent {
+            tracker_urls: vec!["https://example.com:9999".into()],
+            ..Torrent::for_test(
+                "0123456789012345678901234567890123456789",
+                "/tmp",
+                &[("Test/nonempty.txt", 123), ("Test/empty.txt", 0)],
+            )
+        };
+        assert_eq!(
+            filter_torrents_with_matches(
+                std::slice::from_ref(&torrent),
+                &HashMap::from([("/tmp/Test/nonempty.txt".into(), 123)])
+            ),
+            Ok(vec![MatchedTorrent {
+                torrent,
+                matched_paths: BTreeSet::from(["/tmp/Test/nonempty.txt".into()]),
+            }])
+        );
+    }
+
+    #[test]
+    fn merge_torrents_and_files_with_unknown_torrent() {
+        let raw_file = RawFile {
+            torrent_id: "1234567890123456789012345678901234567890".into(),
+            path: "data.txt".into(),
+            size: 123,
+            priority: None,
+            progress: None,
+        };
+        assert!(
+            merge_torrents_and_files(vec![], vec![raw_file], vec![])
+                .unwrap_err()
+                .is::<ConcurrentModificationError>()
+        );
+    }
 }
@@ -0,0 +1,332 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha1_smol::Sha1;
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use tracing::debug;
+
+use super::{Digest, File, FileSlice, Info, Piece, Torrent};
+
+/// Bumped whenever the layout of a cache entry changes, so stale entries are ignored.
+const CACHE_VERSION: u32 = 1;
+
+/// On-disk cache of parsed torrent files, including their precomputed pieces, so that importing
+/// the same torrent files again skips parsing them. Entries are keyed by the absolute path of the
+/// torrent file and are ignored once its modification time changes. The cache is purely an
+/// optimization, so any error reading or writing an entry is treated like a miss.
+pub struct TorrentCache {
+    dir: PathBuf,
+}
+
+impl TorrentCache {
+    pub fn new(dir: PathBuf) -> Self {
+        TorrentCache { dir }
+    }
+
+    /// Returns a cache in `$XDG_CACHE_HOME/toru/torrents`, falling back to `~/.cache` if
+    /// `XDG_CACHE_HOME` is unset, or `None` if neither can be determined.
+    pub fn open() -> Option<Self> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(Self::new(cache_home.join("toru").join("torrents")))
+    }
+
+    /// Returns the cached torrent and infohash for the torrent file at `path`, if there is an
+    /// entry and the file has not been modified since.
+    pub fn get(&self, path: &Path) -> Option<(Torrent, Digest)> {
+        match self.try_get(path) {
+            Ok(hit) => hit,
+            Err(err) => {
+                debug!(path = %path.display(), %err, "unable to read torrent cache entry");
+                None
+            }
+        }
+    }
+
+    /// Stores `torrent` and its infohash as the entry for the torrent file at `path`.
+    pub fn put(&self, path: &Path, torrent: &Torrent, info_hash: &Digest) {
+        if let Err(err) = self.try_put(path, torrent, info_hash) {
+            debug!(path = %path.display(), %err, "unable to write torrent cache entry");
+        }
+    }
+
+    fn try_get(&self, path: &Path) -> Result<Option<(Torrent, Digest)>> {
+        let (key, entry_path) = self.entry_path(path)?;
+        let bytes = match std::fs::read(&entry_path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        let entry: Entry = serde_bencode::from_bytes(&bytes)?;
+        if entry.version != CACHE_VERSION
+            || entry.path.as_slice() != key.as_os_str().as_bytes()
+            || entry.mtime_nanos != mtime_nanos(path)?
+        {
+            debug!(path = %path.display(), "torrent cache entry is stale");
+            return Ok(None);
+        }
+        let info_hash = Digest(
+            entry
+                .info_hash
+                .as_slice()
+                .try_into()
+                .map_err(|_| anyhow!("cached infohash has the wrong length"))?,
+        );
+        Ok(Some((entry.torrent.try_into()?, info_hash)))
+    }
+
+    fn try_put(&self, path: &Path, torrent: &Torrent, info_hash: &Digest) -> Result<()> {
+        let (key, entry_path) = self.entry_path(path)?;
+        let entry = Entry {
+            version: CACHE_VERSION,
+            path: ByteBuf::from(key.as_os_str().as_bytes()),
+            mtime_nanos: mtime_nanos(path)?,
+            info_hash: ByteBuf::from(info_hash.bytes()),
+            torrent: torrent.into(),
+        };
+        std::fs::create_dir_all(&self.dir)?;
+        // Write to a temporary file first, so a concurrent run never sees a partial entry.
+        let temp_path = entry_path.with_extension(format!("{:016x}", rand::random::<u64>()));
+        std::fs::write(&temp_path, serde_bencode::to_bytes(&entry)?)?;
+        std::fs::rename(&temp_path, &entry_path).inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp_path);
+        })?;
+        Ok(())
+    }
+
+    /// Returns the absolute path used as the key for `path`, and the file its entry is stored in.
+    fn entry_path(&self, path: &Path) -> Result<(PathBuf, PathBuf)> {
+        let key = std::path::absolute(path)?;
+        let name = Sha1::from(key.as_os_str().as_bytes()).digest().to_string();
+        let entry_path = self.dir.join(name);
+        Ok((key, entry_path))
+    }
+}
+
+fn mtime_nanos(path: &Path) -> Result<u64> {
+    let mtime = std::fs::metadata(path)?.modified()?;
+    Ok(mtime
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .try_into()?)
+}
+
+// Bencode has no booleans or non-UTF-8 strings, so the cached form stores flags as integers and
+// paths as byte strings.
+
+#[derive(Deserialize, Serialize)]
+struct Entry {
+    version: u32,
+    path: ByteBuf,
+    mtime_nanos: u64,
+    info_hash: ByteBuf,
+    torrent: CachedTorrent,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedTorrent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    announce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    creation_date: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    created_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    comment: Option<String>,
+    url_list: Vec<String>,
+    files: Vec<(u64, ByteBuf)>,
+    is_single_file: u8,
+    name: String,
+    piece_length: u64,
+    pieces: Vec<CachedPiece>,
+    private: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct CachedPiece {
+    hash: ByteBuf,
+    /// Each slice as (path, offset, length).
+    file_slices: Vec<(ByteBuf, u64, u64)>,
+}
+
+fn path_bytes(path: &Path) -> ByteBuf {
+    ByteBuf::from(path.as_os_str().as_bytes())
+}
+
+fn bytes_path(bytes: &ByteBuf) -> PathBuf {
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+impl From<&Torrent> for CachedTorrent {
+    fn from(torrent: &Torrent) -> Self {
+        CachedTorrent {
+            announce: torrent.announce.clone(),
+            creation_date: torrent.creation_date,
+            created_by: torrent.created_by.clone(),
+            comment: torrent.comment.clone(),
+            url_list: torrent.url_list.clone(),
+            files: torrent
+                .info
+                .files
+                .iter()
+                .map(|file| (file.length, path_bytes(&file.path)))
+                .collect(),
+            is_single_file: torrent.info.is_single_file.into(),
+            name: torrent.info.name.clone(),
+            piece_length: torrent.info.piece_length,
+            pieces: torrent
+                .info
+                .pieces
+                .iter()
+                .map(|piece| CachedPiece {
+                    hash: ByteBuf::from(piece.hash.bytes()),
+                    file_slices: piece
+                        .file_slices
+                        .iter()
+                        .map(|slice| (path_bytes(&slice.path), slice.offset, slice.length))
+                        .collect(),
+                })
+                .collect(),
+            private: torrent.info.private.into(),
+            source: torrent.info.source.clone(),
+        }
+    }
+}
+
+impl TryFrom<CachedTorrent> for Torrent {
+    type Error = anyhow::Error;
+
+    fn try_from(cached: CachedTorrent) -> Result<Self> {
+        let pieces = cached
+            .pieces
+            .into_iter()
+            .map(|piece| {
+                Ok(Piece {
+                    hash: Digest(
+                        piece
+                            .hash
+                            .as_slice()
+                            .try_into()
+                            .map_err(|_| anyhow!("cached piece hash has the wrong length"))?,
+                    ),
+                    file_slices: piece
+                        .file_slices
+                        .iter()
+                        .map(|(path, offset, length)| FileSlice {
+                            path: bytes_path(path),
+                            offset: *offset,
+                            length: *length,
+                        })
+                        .collect(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Torrent {
+            announce: cached.announce,
+            creation_date: cached.creation_date,
+            created_by: cached.created_by,
+            comment: cached.comment,
+            url_list: cached.url_list,
+            info: Info {
+                files: cached
+                    .files
+                    .iter()
+                    .map(|(length, path)| File {
+                        length: *length,
+                        path: bytes_path(path),
+                    })
+                    .collect(),
+                is_single_file: cached.is_single_file != 0,
+                name: cached.name,
+                piece_length: cached.piece_length,
+                pieces,
+                private: cached.private != 0,
+                source: cached.source,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    fn set_mtime(path: &Path, mtime: SystemTime) {
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .and_then(|file| file.set_modified(mtime))
+            .expect("failed to set mtime");
+    }
+
+    fn multi_file_torrent() -> Vec<u8> {
+        let mut bytes = b"d8:announce20:https://example.com/7:comment4:test4:infod5:filesl\
+            d6:lengthi3e4:pathl1:aee\
+            d6:lengthi5e4:pathl3:sub1:bee\
+            e4:name4:show12:piece lengthi16384e6:pieces20:"
+            .to_vec();
+        bytes.extend([7; sha1_smol::DIGEST_LENGTH]);
+        bytes.extend(b"7:privatei1eee");
+        bytes
+    }
+
+    #[test]
+    fn round_trips_torrent() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let torrent_path = tmp_dir.path().join("show.torrent");
+        let bytes = multi_file_torrent();
+        std::fs::write(&torrent_path, &bytes).expect("failed to create test file");
+        let torrent: Torrent = serde_bencode::from_bytes(&bytes).unwrap();
+        let info_hash = super::super::info_hash(&bytes).unwrap();
+
+        let cache = TorrentCache::new(tmp_dir.path().join("cache"));
+        assert!(cache.get(&torrent_path).is_none());
+        cache.put(&torrent_path, &torrent, &info_hash);
+        let (cached, cached_info_hash) = cache.get(&torrent_path).unwrap();
+        assert_eq!(cached_info_hash, info_hash);
+        assert_eq!(cached.announce, torrent.announce);
+        assert_eq!(cached.comment.as_deref(), Some("test"));
+        assert_eq!(cached.info.name, "show");
+        assert!(cached.info.private);
+        assert!(!cached.info.is_single_file);
+        assert_eq!(
+            cached
+                .info
+                .files
+                .iter()
+                .map(|file| (file.length, file.path.clone()))
+                .collect::<Vec<_>>(),
+            [
+                (3, PathBuf::from("show/a")),
+                (5, PathBuf::from("show/sub/b"))
+            ]
+        );
+        assert_eq!(cached.info.pieces, torrent.info.pieces);
+    }
+
+    #[test]
+    fn ignores_modified_files() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let torrent_path = tmp_dir.path().join("show.torrent");
+        let bytes = multi_file_torrent();
+        std::fs::write(&torrent_path, &bytes).expect("failed to create test file");
+        let torrent: Torrent = serde_bencode::from_bytes(&bytes).unwrap();
+
+        let cache = TorrentCache::new(tmp_dir.path().join("cache"));
+        cache.put(&torrent_path, &torrent, &Digest::default());
+        assert!(cache.get(&torrent_path).is_some());
+        set_mtime(&torrent_path, SystemTime::now() + Duration::from_secs(60));
+        assert!(cache.get(&torrent_path).is_none());
+        // Other paths never share an entry.
+        assert!(cache.get(&tmp_dir.path().join("other.torrent")).is_none());
+    }
+}
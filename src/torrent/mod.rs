@@ -1,8 +1,21 @@
+use anyhow::{Context, Result, anyhow, bail};
+use indicatif::ProgressBar;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use serde::Deserialize;
 use serde_bytes::ByteBuf;
-use std::path::PathBuf;
+use sha1_smol::Sha1;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::fs::File as FsFile;
+use std::num::NonZeroU64;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, trace};
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub mod cache;
+
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
 pub struct Digest([u8; sha1_smol::DIGEST_LENGTH]);
 
 impl Digest {
@@ -11,19 +24,52 @@ impl Digest {
     }
 }
 
+impl std::fmt::Display for Digest {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
 #[derive(Debug, Deserialize)]
+#[serde(from = "RawFile")]
 pub struct File {
     pub length: u64,
-    #[serde(deserialize_with = "deserialize_path_vec")]
     pub path: PathBuf,
 }
 
+#[derive(Deserialize)]
+struct RawFile {
+    length: u64,
+    #[serde(deserialize_with = "deserialize_path_vec")]
+    path: PathBuf,
+    // Some older torrents encode `path` in a legacy local encoding, and include the UTF-8 path
+    // separately.
+    #[serde(rename = "path.utf-8", default)]
+    path_utf8: Option<Vec<String>>,
+}
+
+impl From<RawFile> for File {
+    fn from(raw_file: RawFile) -> Self {
+        File {
+            length: raw_file.length,
+            path: raw_file
+                .path_utf8
+                .map_or(raw_file.path, |path_pieces| path_pieces.iter().collect()),
+        }
+    }
+}
+
+/// Deserializes a list of path components. Components are kept as raw bytes, since they are not
+/// guaranteed to be UTF-8.
 fn deserialize_path_vec<'de, D>(deserializer: D) -> Result<PathBuf, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let path_pieces = <Vec<String>>::deserialize(deserializer)?;
-    Ok(path_pieces.iter().collect())
+    let path_pieces = <Vec<ByteBuf>>::deserialize(deserializer)?;
+    Ok(path_pieces
+        .iter()
+        .map(|piece| OsStr::from_bytes(piece))
+        .collect())
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
@@ -39,6 +85,206 @@ pub struct Piece {
     pub file_slices: Vec<FileSlice>,
 }
 
+impl Piece {
+    /// Returns whether the on-disk data for this piece matches the expected hash. `mapping` maps
+    /// the path of each file in the torrent to the path of the file on disk. Fails if a file on
+    /// disk is too short to contain its slice.
+    pub fn check(&self, mapping: &HashMap<&Path, &Path>) -> Result<bool> {
+        match hash_slices(&self.file_slices, mapping)? {
+            Some(digest) => Ok(digest == self.hash),
+            None => bail!(
+                "on-disk data for piece {} is shorter than expected",
+                self.hash
+            ),
+        }
+    }
+
+    /// Returns the total number of bytes covered by this piece.
+    pub fn length(&self) -> u64 {
+        self.file_slices.iter().map(|slice| slice.length).sum()
+    }
+}
+
+/// Hashes the on-disk data for `file_slices`, where `mapping` maps the path of each file in the
+/// torrent to the path of the file on disk. Returns `None` if a file on disk is too short to
+/// contain its slice.
+pub fn hash_slices(
+    file_slices: &[FileSlice],
+    mapping: &HashMap<&Path, &Path>,
+) -> Result<Option<Digest>> {
+    // Pieces can be many MiB, so hash through a small fixed-size buffer rather than reading each
+    // slice in one go; this keeps memory use bounded per worker thread.
+    const BUFFER_SIZE: usize = 64 * 1024;
+
+    let mut sha1 = Sha1::new();
+    let mut buffer = vec![0; BUFFER_SIZE];
+    for slice in file_slices {
+        let path = map_path(&slice.path, mapping)?;
+        let file =
+            FsFile::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        // Pieces are read front to back, so let the kernel read ahead. This is only a hint, so
+        // failures don't matter.
+        let _ = rustix::fs::fadvise(
+            &file,
+            slice.offset,
+            NonZeroU64::new(slice.length),
+            rustix::fs::Advice::Sequential,
+        );
+        let mut offset = slice.offset;
+        let end = slice.offset + slice.length;
+        while offset < end {
+            let len = std::cmp::min(BUFFER_SIZE as u64, end - offset) as usize;
+            let bytes_read = rustix::io::pread(&file, &mut buffer[..len], offset)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            if bytes_read == 0 {
+                return Ok(None);
+            }
+            sha1.update(&buffer[..bytes_read]);
+            offset += bytes_read as u64;
+        }
+    }
+    Ok(Some(Digest(sha1.digest().bytes())))
+}
+
+fn map_path<'a>(path: &Path, mapping: &HashMap<&Path, &'a Path>) -> Result<&'a Path> {
+    mapping
+        .get(path)
+        .copied()
+        .ok_or_else(|| anyhow!("no mapping for {}", path.display()))
+}
+
+/// Remembers the hashes of on-disk piece data, so that a piece shared by several torrents, e.g.
+/// cross-seeds of the same release, is only read once. Entries are keyed by the on-disk paths
+/// and byte ranges, and the oldest entries are evicted once `capacity` is reached.
+pub struct PieceCache {
+    capacity: usize,
+    entries: Mutex<PieceCacheEntries>,
+}
+
+#[derive(Default)]
+struct PieceCacheEntries {
+    hashes: HashMap<Vec<FileSlice>, Option<Digest>>,
+    order: VecDeque<Vec<FileSlice>>,
+}
+
+impl PieceCache {
+    pub fn new(capacity: usize) -> Self {
+        PieceCache {
+            capacity,
+            entries: Mutex::default(),
+        }
+    }
+
+    /// Like `hash_slices`, but returns the cached result if the same on-disk data was already
+    /// hashed.
+    pub fn hash_slices(
+        &self,
+        file_slices: &[FileSlice],
+        mapping: &HashMap<&Path, &Path>,
+    ) -> Result<Option<Digest>> {
+        let key = file_slices
+            .iter()
+            .map(|slice| {
+                Ok(FileSlice {
+                    path: map_path(&slice.path, mapping)?.to_path_buf(),
+                    ..slice.clone()
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(digest) = self.entries.lock().unwrap().hashes.get(&key) {
+            trace!(slices = ?key, "piece cache hit");
+            return Ok(digest.clone());
+        }
+        // Don't hold the lock while hashing, so that other pieces can be checked in parallel.
+        // Two threads may occasionally hash the same data; that is harmless.
+        let identity = key
+            .iter()
+            .map(|slice| (slice.path.as_path(), slice.path.as_path()))
+            .collect();
+        let digest = hash_slices(&key, &identity)?;
+        let mut entries = self.entries.lock().unwrap();
+        if self.capacity > 0 && !entries.hashes.contains_key(&key) {
+            if entries.order.len() >= self.capacity
+                && let Some(oldest) = entries.order.pop_front()
+            {
+                entries.hashes.remove(&oldest);
+            }
+            entries.order.push_back(key.clone());
+            entries.hashes.insert(key, digest.clone());
+        }
+        Ok(digest)
+    }
+}
+
+/// Splits the concatenated contents of `files` into pieces of `piece_length` bytes, returning the
+/// slices of each file that make up each piece. Zero-length files do not appear in any piece.
+pub fn compute_file_slices(files: &[File], piece_length: u64) -> Vec<Vec<FileSlice>> {
+    assert!(piece_length > 0, "piece length must be positive");
+    let mut pieces = vec![];
+    let mut file_slices = vec![];
+    let mut piece_remaining = piece_length;
+    for file in files {
+        let mut offset = 0;
+        while offset < file.length {
+            let length = std::cmp::min(file.length - offset, piece_remaining);
+            file_slices.push(FileSlice {
+                path: file.path.clone(),
+                offset,
+                length,
+            });
+            offset += length;
+            piece_remaining -= length;
+            if piece_remaining == 0 {
+                trace!(?file_slices, "computed piece slices");
+                pieces.push(std::mem::take(&mut file_slices));
+                piece_remaining = piece_length;
+            }
+        }
+    }
+    if !file_slices.is_empty() {
+        trace!(?file_slices, "computed piece slices");
+        pieces.push(file_slices);
+    }
+    pieces
+}
+
+/// Checks `pieces` in parallel and returns the paths of all files that have data in a piece that
+/// failed the hash check. `bar` is advanced by the length of each piece as it is checked, and its
+/// message names the file the piece starts in. If
+/// `cache` is set, it is used to avoid rehashing data that was already checked. Fails if any file
+/// could not be read, e.g. due to a permissions or disk error.
+pub fn find_failed_paths<'a>(
+    pieces: &'a [Piece],
+    mapping: &HashMap<&Path, &Path>,
+    cache: Option<&PieceCache>,
+    bar: &ProgressBar,
+) -> Result<HashSet<&'a Path>> {
+    let failed_pieces = pieces
+        .par_iter()
+        .map(|piece| {
+            if let Some(slice) = piece.file_slices.first() {
+                bar.set_message(format!("hashing {}", slice.path.display()));
+            }
+            let ok = match cache {
+                Some(cache) => {
+                    cache.hash_slices(&piece.file_slices, mapping)? == Some(piece.hash.clone())
+                }
+                None => piece.check(mapping)?,
+            };
+            if !ok {
+                debug!(slices = ?piece.file_slices, "piece failed hash check");
+            }
+            bar.inc(piece.length());
+            Ok((!ok).then_some(piece))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(failed_pieces
+        .into_iter()
+        .flatten()
+        .flat_map(|piece| piece.file_slices.iter().map(|slice| slice.path.as_path()))
+        .collect())
+}
+
 #[derive(Debug)]
 pub struct Info {
     pub files: Vec<File>,
@@ -46,28 +292,176 @@ pub struct Info {
     pub name: String,
     pub piece_length: u64,
     pub pieces: Vec<Piece>,
+    /// Whether the torrent is marked private, i.e. peers should only come from its trackers.
+    pub private: bool,
+    /// Tag set by some trackers to make the infohash unique to that tracker.
+    pub source: Option<String>,
+}
+
+/// Returns the length of the bencoded value at the start of `bytes`. Lists and dictionaries are
+/// walked with a depth counter instead of recursion, so deeply nested input cannot overflow the
+/// stack.
+fn bencode_value_len(bytes: &[u8]) -> Result<usize> {
+    let mut pos = 0;
+    let mut depth = 0usize;
+    loop {
+        let rest = &bytes[pos..];
+        match rest.first() {
+            Some(b'i') => {
+                pos += rest
+                    .iter()
+                    .position(|&byte| byte == b'e')
+                    .ok_or_else(|| anyhow!("unterminated integer"))?
+                    + 1;
+            }
+            Some(b'l' | b'd') => {
+                depth += 1;
+                pos += 1;
+            }
+            Some(b'e') if depth > 0 => {
+                depth -= 1;
+                pos += 1;
+            }
+            Some(b'0'..=b'9') => {
+                let colon = rest
+                    .iter()
+                    .position(|&byte| byte == b':')
+                    .ok_or_else(|| anyhow!("unterminated string length"))?;
+                let len = std::str::from_utf8(&rest[..colon])?.parse::<usize>()?;
+                pos += colon
+                    .checked_add(1)
+                    .and_then(|n| n.checked_add(len))
+                    .filter(|&end| end <= rest.len())
+                    .ok_or_else(|| anyhow!("string length {len} exceeds input"))?;
+            }
+            None if depth > 0 => return Err(anyhow!("unterminated list or dictionary")),
+            _ => return Err(anyhow!("invalid bencoded value")),
+        }
+        if depth == 0 {
+            return Ok(pos);
+        }
+    }
+}
+
+/// Computes the infohash of the bencoded torrent file in `bytes`, i.e. the SHA-1 of the raw info
+/// dictionary. This hashes the bytes as they appear in the file, since re-encoding a parsed
+/// `Info` is not guaranteed to reproduce them.
+pub fn info_hash(bytes: &[u8]) -> Result<Digest> {
+    if bytes.first() != Some(&b'd') {
+        return Err(anyhow!("torrent is not a bencoded dictionary"));
+    }
+    let mut pos = 1;
+    while bytes.get(pos) != Some(&b'e') {
+        let key_len = bencode_value_len(&bytes[pos..])?;
+        let key = &bytes[pos..pos + key_len];
+        pos += key_len;
+        let value_len = bencode_value_len(&bytes[pos..])?;
+        if key == b"4:info" {
+            return Ok(Digest(
+                Sha1::from(&bytes[pos..pos + value_len]).digest().bytes(),
+            ));
+        }
+        pos += value_len;
+    }
+    Err(anyhow!("torrent has no info dictionary"))
+}
+
+/// Returns a copy of the bencoded torrent file in `bytes` that announces only to `announce`. Any
+/// `announce-list` is dropped, since clients prefer it over `announce`. Everything else, notably the
+/// info dictionary, is copied through byte for byte so the infohash does not change.
+pub fn replace_announce(bytes: &[u8], announce: &str) -> Result<Vec<u8>> {
+    if bytes.first() != Some(&b'd') {
+        return Err(anyhow!("torrent is not a bencoded dictionary"));
+    }
+    let encoded_announce = format!("8:announce{}:{announce}", announce.len()).into_bytes();
+    let mut result = vec![b'd'];
+    let mut wrote_announce = false;
+    let mut pos = 1;
+    while bytes.get(pos) != Some(&b'e') {
+        let key_len = bencode_value_len(&bytes[pos..])?;
+        let key = &bytes[pos..pos + key_len];
+        let value_len = bencode_value_len(&bytes[pos + key_len..])?;
+        let entry = &bytes[pos..pos + key_len + value_len];
+        pos += key_len + value_len;
+        // Keys are sorted, so the announce URL goes before the first key that sorts after it.
+        let colon = key
+            .iter()
+            .position(|&byte| byte == b':')
+            .ok_or_else(|| anyhow!("dictionary key is not a string"))?;
+        let key = &key[colon + 1..];
+        if !wrote_announce && key >= b"announce".as_slice() {
+            result.extend(&encoded_announce);
+            wrote_announce = true;
+        }
+        if key != b"announce" && key != b"announce-list" {
+            result.extend(entry);
+        }
+    }
+    if !wrote_announce {
+        result.extend(&encoded_announce);
+    }
+    result.push(b'e');
+    Ok(result)
 }
 
 #[derive(Deserialize)]
 pub struct Torrent {
-    pub announce: String,
+    /// Absent or empty for trackerless torrents.
+    #[serde(default)]
+    pub announce: Option<String>,
+    #[serde(rename = "creation date")]
+    pub creation_date: Option<i64>,
+    #[serde(rename = "created by")]
+    pub created_by: Option<String>,
+    pub comment: Option<String>,
+    /// Web seed URLs (BEP 19).
+    #[serde(
+        rename = "url-list",
+        default,
+        deserialize_with = "deserialize_url_list"
+    )]
+    pub url_list: Vec<String>,
     #[serde(deserialize_with = "deserialize_info")]
     pub info: Info,
 }
 
+/// Deserializes `url-list`, which may be either a single URL or a list of URLs. Some clients write
+/// an empty string when there are no web seeds.
+fn deserialize_url_list<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum UrlList {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match UrlList::deserialize(deserializer)? {
+        UrlList::One(url) if url.is_empty() => vec![],
+        UrlList::One(url) => vec![url],
+        UrlList::Many(urls) => urls,
+    })
+}
+
 fn deserialize_info<'de, D>(deserializer: D) -> Result<Info, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
     #[derive(Deserialize)]
     struct RawInfo {
-        name: String,
+        name: ByteBuf,
+        #[serde(rename = "name.utf-8")]
+        name_utf8: Option<String>,
         files: Option<Vec<File>>,
         length: Option<u64>,
         #[serde(rename = "piece length")]
         piece_length: u64,
         #[serde(rename = "pieces", deserialize_with = "deserialize_pieces")]
         hashes: Vec<Digest>,
+        private: Option<i64>,
+        source: Option<String>,
     }
 
     fn deserialize_pieces<'de, D>(deserializer: D) -> Result<Vec<Digest>, D::Error>
@@ -103,26 +497,30 @@ where
     }
 
     let raw_info = RawInfo::deserialize(deserializer)?;
+    let name_as_path = match &raw_info.name_utf8 {
+        Some(name) => PathBuf::from(name),
+        None => PathBuf::from(OsStr::from_bytes(&raw_info.name)),
+    };
+    let name = raw_info
+        .name_utf8
+        .unwrap_or_else(|| String::from_utf8_lossy(&raw_info.name).into_owned());
 
     let (is_single_file, files) = match (raw_info.files, raw_info.length) {
-        (Some(files), None) => {
-            let name_as_path = PathBuf::from(raw_info.name.clone());
-            Ok((
-                false,
-                files
-                    .into_iter()
-                    .map(|file| File {
-                        length: file.length,
-                        path: name_as_path.join(file.path),
-                    })
-                    .collect(),
-            ))
-        }
+        (Some(files), None) => Ok((
+            false,
+            files
+                .into_iter()
+                .map(|file| File {
+                    length: file.length,
+                    path: name_as_path.join(file.path),
+                })
+                .collect(),
+        )),
         (None, Some(length)) => Ok((
             true,
             vec![File {
                 length,
-                path: raw_info.name.clone().into(),
+                path: name_as_path,
             }],
         )),
         _ => Err(serde::de::Error::custom(
@@ -130,51 +528,376 @@ where
         )),
     }?;
 
-    let mut file_iter = files.iter().peekable();
-    let mut remaining = files.iter().map(|f| f.length).sum();
-    let mut file_remaining = file_iter
-        .peek()
-        .ok_or_else(|| serde::de::Error::custom("torrent with empty files in info"))?
-        .length;
+    debug!(
+        name = %name,
+        files = files.len(),
+        pieces = raw_info.hashes.len(),
+        piece_length = raw_info.piece_length,
+        "parsed torrent info"
+    );
+    if files.is_empty() {
+        return Err(serde::de::Error::custom("torrent with empty files in info"));
+    }
+    if raw_info.piece_length == 0 {
+        return Err(serde::de::Error::custom("piece length must be positive"));
+    }
+    let file_slices = compute_file_slices(&files, raw_info.piece_length);
+    if raw_info.hashes.len() > file_slices.len() {
+        return Err(serde::de::Error::custom(
+            "remaining hashes but all bytes consumed",
+        ));
+    }
     let pieces = raw_info
         .hashes
         .into_iter()
-        .map(|hash| {
-            if remaining == 0 {
-                return Err(serde::de::Error::custom(
-                    "remaining hashes but all bytes consumed",
-                ));
-            }
-            let mut piece_remaining = std::cmp::min(remaining, raw_info.piece_length);
-            let mut file_slices = vec![];
-            while piece_remaining > 0 {
-                let current_file = file_iter.peek().ok_or_else(|| {
-                    serde::de::Error::custom("remaining hashes but all files consumed")
-                })?;
-                let next = std::cmp::min(file_remaining, piece_remaining);
-                file_slices.push(FileSlice {
-                    path: current_file.path.clone(),
-                    offset: current_file.length - file_remaining,
-                    length: next,
-                });
-                if next >= file_remaining {
-                    file_iter.next();
-                    file_remaining = file_iter.peek().map_or(0, |file| file.length);
-                } else {
-                    file_remaining -= next;
-                }
-                remaining -= next;
-                piece_remaining -= next;
-            }
-            Ok(Piece { hash, file_slices })
-        })
-        .collect::<Result<_, D::Error>>()?;
+        .zip(file_slices)
+        .map(|(hash, file_slices)| Piece { hash, file_slices })
+        .collect();
 
     Ok(Info {
         files,
         is_single_file,
-        name: raw_info.name,
+        name,
         piece_length: raw_info.piece_length,
         pieces,
+        private: raw_info.private == Some(1),
+        source: raw_info.source,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single_file_torrent(extra_info: &str) -> Vec<u8> {
+        let mut bytes =
+            b"d8:announce20:https://example.com/4:infod6:lengthi5e4:name1:a12:piece lengthi16384e6:pieces20:"
+                .to_vec();
+        bytes.extend([0; sha1_smol::DIGEST_LENGTH]);
+        bytes.extend(format!("{extra_info}ee").into_bytes());
+        bytes
+    }
+
+    #[test]
+    fn deserialize_without_announce() {
+        let mut bytes = b"d4:infod6:lengthi5e4:name1:a12:piece lengthi16384e6:pieces20:".to_vec();
+        bytes.extend([0; sha1_smol::DIGEST_LENGTH]);
+        bytes.extend(b"ee");
+        let torrent: Torrent = serde_bencode::from_bytes(&bytes).unwrap();
+        assert_eq!(torrent.announce, None);
+        assert_eq!(torrent.info.name, "a");
+    }
+
+    #[test]
+    fn deserialize_private() {
+        let torrent: Torrent = serde_bencode::from_bytes(&single_file_torrent("")).unwrap();
+        assert!(!torrent.info.private);
+
+        let torrent: Torrent =
+            serde_bencode::from_bytes(&single_file_torrent("7:privatei1e")).unwrap();
+        assert!(torrent.info.private);
+
+        let torrent: Torrent =
+            serde_bencode::from_bytes(&single_file_torrent("7:privatei0e")).unwrap();
+        assert!(!torrent.info.private);
+    }
+
+    #[test]
+    fn deserialize_zero_length_files() {
+        let mut bytes = b"d8:announce20:https://example.com/4:infod5:filesl\
+            d6:lengthi3e4:pathl1:aee\
+            d6:lengthi0e4:pathl5:emptyee\
+            d6:lengthi5e4:pathl1:bee\
+            d6:lengthi0e4:pathl8:trailingee\
+            e4:name3:dir12:piece lengthi4e6:pieces40:"
+            .to_vec();
+        bytes.extend([0; 2 * sha1_smol::DIGEST_LENGTH]);
+        bytes.extend(b"ee");
+        let torrent: Torrent = serde_bencode::from_bytes(&bytes).unwrap();
+
+        assert_eq!(torrent.info.files.len(), 4);
+        let slices = torrent
+            .info
+            .pieces
+            .iter()
+            .map(|piece| piece.file_slices.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            slices,
+            vec![
+                vec![
+                    FileSlice {
+                        path: "dir/a".into(),
+                        offset: 0,
+                        length: 3,
+                    },
+                    FileSlice {
+                        path: "dir/b".into(),
+                        offset: 0,
+                        length: 1,
+                    },
+                ],
+                vec![FileSlice {
+                    path: "dir/b".into(),
+                    offset: 1,
+                    length: 4,
+                }],
+            ]
+        );
+    }
+
+    #[test]
+    fn deserialize_prefers_utf8_names() {
+        let mut bytes = b"d8:announce20:https://example.com/4:infod5:filesl\
+            d6:lengthi5e4:pathl2:\xe9\xe9e10:path.utf-8l4:\xc3\xa9\xc3\xa9ee\
+            d6:lengthi5e4:pathl1:\xe9ee\
+            e4:name3:\xe9\xe9\xe910:name.utf-83:dir12:piece lengthi16e6:pieces20:"
+            .to_vec();
+        bytes.extend([0; sha1_smol::DIGEST_LENGTH]);
+        bytes.extend(b"ee");
+        let torrent: Torrent = serde_bencode::from_bytes(&bytes).unwrap();
+
+        assert_eq!(torrent.info.name, "dir");
+        assert_eq!(torrent.info.files[0].path, Path::new("dir/éé"));
+        // Without a UTF-8 variant, the raw bytes are used as is.
+        assert_eq!(
+            torrent.info.files[1].path,
+            Path::new("dir").join(OsStr::from_bytes(b"\xe9"))
+        );
+    }
+
+    #[test]
+    fn info_hash_of_info_dictionary() {
+        let bytes = single_file_torrent("");
+        let info_start = bytes.windows(6).position(|w| w == b"4:info").unwrap() + 6;
+        let expected = Sha1::from(&bytes[info_start..bytes.len() - 1]).digest();
+        assert_eq!(info_hash(&bytes).unwrap().to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn info_hash_rejects_truncated_input() {
+        let bytes = single_file_torrent("");
+        assert!(info_hash(&bytes[..bytes.len() - 2]).is_err());
+        assert!(info_hash(b"le").is_err());
+    }
+
+    #[test]
+    fn bencode_value_len_rejects_overlong_string() {
+        assert!(bencode_value_len(format!("{}:x", usize::MAX).as_bytes()).is_err());
+        assert!(bencode_value_len(b"l3:abe").is_err());
+    }
+
+    #[test]
+    fn bencode_value_len_handles_deep_nesting() {
+        let depth = 1_000_000;
+        let bytes = [vec![b'l'; depth], vec![b'e'; depth]].concat();
+        assert_eq!(bencode_value_len(&bytes).unwrap(), 2 * depth);
+        assert!(bencode_value_len(&bytes[..2 * depth - 1]).is_err());
+    }
+
+    #[test]
+    fn replace_announce_keeps_info_hash() {
+        let bytes = single_file_torrent("");
+        let replaced = replace_announce(&bytes, "https://other.example.com/a").unwrap();
+        let torrent: Torrent = serde_bencode::from_bytes(&replaced).unwrap();
+        assert_eq!(
+            torrent.announce.as_deref(),
+            Some("https://other.example.com/a")
+        );
+        assert_eq!(
+            info_hash(&replaced).unwrap().to_string(),
+            info_hash(&bytes).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn deserialize_url_list() {
+        let torrent: Torrent = serde_bencode::from_bytes(&single_file_torrent("")).unwrap();
+        assert!(torrent.url_list.is_empty());
+
+        let with_url_list = |url_list: &str| {
+            let mut bytes = b"d8:announce20:https://example.com/".to_vec();
+            bytes.extend(format!("8:url-list{url_list}").into_bytes());
+            bytes.extend(&single_file_torrent("")[34..]);
+            bytes
+        };
+        let torrent: Torrent =
+            serde_bencode::from_bytes(&with_url_list("18:https://a.example/")).unwrap();
+        assert_eq!(torrent.url_list, vec!["https://a.example/"]);
+
+        let torrent: Torrent = serde_bencode::from_bytes(&with_url_list(
+            "l18:https://a.example/18:https://b.example/e",
+        ))
+        .unwrap();
+        assert_eq!(
+            torrent.url_list,
+            vec!["https://a.example/", "https://b.example/"]
+        );
+
+        let torrent: Torrent = serde_bencode::from_bytes(&with_url_list("0:")).unwrap();
+        assert!(torrent.url_list.is_empty());
+
+        // Rewriting the announce URL keeps the web seeds.
+        let replaced = replace_announce(
+            &with_url_list("l18:https://a.example/e"),
+            "https://c.example/",
+        )
+        .unwrap();
+        let torrent: Torrent = serde_bencode::from_bytes(&replaced).unwrap();
+        assert_eq!(torrent.url_list, vec!["https://a.example/"]);
+    }
+
+    #[test]
+    fn replace_announce_drops_announce_list() {
+        let mut bytes =
+            b"d8:announce20:https://example.com/13:announce-listll20:https://example.com/ee"
+                .to_vec();
+        bytes.extend(&single_file_torrent("")[34..]);
+        let replaced = replace_announce(&bytes, "https://b.example/").unwrap();
+        let mut expected = b"d8:announce18:https://b.example/".to_vec();
+        expected.extend(&single_file_torrent("")[34..]);
+        assert_eq!(replaced, expected);
+    }
+
+    #[test]
+    fn replace_announce_inserts_missing_announce() {
+        let bytes = b"d7:comment2:hi4:infodee";
+        assert_eq!(
+            replace_announce(bytes, "https://b.example/").unwrap(),
+            b"d8:announce18:https://b.example/7:comment2:hi4:infodee"
+        );
+    }
+
+    #[test]
+    fn replace_announce_rejects_non_string_key() {
+        let err = replace_announce(b"di1e2:hie", "https://b.example/").unwrap_err();
+        assert_eq!(err.to_string(), "dictionary key is not a string");
+    }
+
+    #[test]
+    fn deserialize_metadata() {
+        let torrent: Torrent = serde_bencode::from_bytes(&single_file_torrent("")).unwrap();
+        assert_eq!(torrent.creation_date, None);
+        assert_eq!(torrent.created_by, None);
+        assert_eq!(torrent.comment, None);
+
+        let mut bytes =
+            b"d7:comment5:hello10:created by4:toru13:creation datei1700000000e".to_vec();
+        bytes.extend(&single_file_torrent("")[1..]);
+        let torrent: Torrent = serde_bencode::from_bytes(&bytes).unwrap();
+        assert_eq!(torrent.creation_date, Some(1700000000));
+        assert_eq!(torrent.created_by.as_deref(), Some("toru"));
+        assert_eq!(torrent.comment.as_deref(), Some("hello"));
+    }
+
+    #[test]
+    fn deserialize_source() {
+        let torrent: Torrent = serde_bencode::from_bytes(&single_file_torrent("")).unwrap();
+        assert_eq!(torrent.info.source, None);
+
+        let torrent: Torrent =
+            serde_bencode::from_bytes(&single_file_torrent("6:source3:ABC")).unwrap();
+        assert_eq!(torrent.info.source.as_deref(), Some("ABC"));
+    }
+
+    #[test]
+    fn piece_check_spans_files_larger_than_buffer() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let first = (0..100_000).map(|i| i as u8).collect::<Vec<_>>();
+        let second = (0..200_000).map(|i| (i / 7) as u8).collect::<Vec<_>>();
+        let first_path = tmp_dir.path().join("first");
+        let second_path = tmp_dir.path().join("second");
+        std::fs::write(&first_path, &first).expect("failed to create test file");
+        std::fs::write(&second_path, &second).expect("failed to create test file");
+
+        let mut sha1 = Sha1::new();
+        sha1.update(&first[1000..]);
+        sha1.update(&second[..150_000]);
+        let mut piece = Piece {
+            hash: Digest(sha1.digest().bytes()),
+            file_slices: vec![
+                FileSlice {
+                    path: "a".into(),
+                    offset: 1000,
+                    length: 99_000,
+                },
+                FileSlice {
+                    path: "b".into(),
+                    offset: 0,
+                    length: 150_000,
+                },
+            ],
+        };
+        let mapping = HashMap::from([
+            (Path::new("a"), first_path.as_path()),
+            (Path::new("b"), second_path.as_path()),
+        ]);
+        assert!(piece.check(&mapping).unwrap());
+
+        piece.file_slices[1].offset = 1;
+        assert!(!piece.check(&mapping).unwrap());
+
+        piece.file_slices[1].offset = 100_000;
+        assert!(piece.check(&mapping).is_err());
+    }
+
+    #[test]
+    fn find_failed_paths_reports_io_errors() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let missing = tmp_dir.path().join("missing");
+        let pieces = [Piece {
+            hash: Digest::default(),
+            file_slices: vec![FileSlice {
+                path: "a".into(),
+                offset: 0,
+                length: 5,
+            }],
+        }];
+        let mapping = HashMap::from([(Path::new("a"), missing.as_path())]);
+        let err = find_failed_paths(&pieces, &mapping, None, &ProgressBar::hidden()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("failed to open {}", missing.display())
+        );
+    }
+
+    #[test]
+    fn piece_cache_reuses_hashes() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tmp_dir.path().join("a");
+        std::fs::write(&path, b"hello").expect("failed to create test file");
+        let slices = [FileSlice {
+            path: "a".into(),
+            offset: 0,
+            length: 5,
+        }];
+        let mapping = HashMap::from([(Path::new("a"), path.as_path())]);
+        let cache = PieceCache::new(1);
+        let digest = cache.hash_slices(&slices, &mapping).unwrap();
+        assert_eq!(digest, hash_slices(&slices, &mapping).unwrap());
+
+        // The same on-disk data, even under a different torrent path, is not read again.
+        std::fs::write(&path, b"world").expect("failed to update test file");
+        let other_mapping = HashMap::from([(Path::new("b"), path.as_path())]);
+        let other_slices = [FileSlice {
+            path: "b".into(),
+            ..slices[0].clone()
+        }];
+        assert_eq!(
+            cache.hash_slices(&other_slices, &other_mapping).unwrap(),
+            digest
+        );
+
+        // Once evicted, the data is hashed again.
+        cache
+            .hash_slices(
+                &[FileSlice {
+                    length: 4,
+                    ..slices[0].clone()
+                }],
+                &mapping,
+            )
+            .unwrap();
+        assert_ne!(cache.hash_slices(&slices, &mapping).unwrap(), digest);
+    }
+}
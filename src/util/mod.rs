@@ -1,7 +1,85 @@
-use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use clap::ValueEnum;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+use std::io::Read;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppresses informational output and progress bars for the rest of the process.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+#[derive(Copy, Clone, Default, ValueEnum)]
+pub enum ColorChoice {
+    /// Use colors if the output is a terminal and `NO_COLOR` is not set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Configures whether styled output on stdout and stderr is colored.
+pub fn set_color_choice(choice: ColorChoice) {
+    let enabled = match choice {
+        // `console` already disables colors when the output is not a terminal.
+        ColorChoice::Auto => {
+            if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+                Some(false)
+            } else {
+                None
+            }
+        }
+        ColorChoice::Always => Some(true),
+        ColorChoice::Never => Some(false),
+    };
+    if let Some(enabled) = enabled {
+        console::set_colors_enabled(enabled);
+        console::set_colors_enabled_stderr(enabled);
+    }
+}
+
+/// Prints informational status to stderr, unless `--quiet` was specified.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if !$crate::util::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+pub(crate) use info;
+
+/// Prints a warning to stderr. Warnings are printed even if `--quiet` was specified.
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        eprintln!(
+            "{} {}",
+            console::style("warning:").yellow(),
+            format_args!($($arg)*)
+        );
+    };
+}
+pub(crate) use warning;
+
+/// Progress bars are drawn on stderr; animating them only makes sense if someone is watching.
+fn hide_if_unattended(bar: ProgressBar) -> ProgressBar {
+    if is_quiet() || !console::Term::stderr().features().is_attended() {
+        bar.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    bar
+}
+
+/// Returns a spinner for work of unknown length. It ticks on its own, so it keeps moving while
+/// the caller is blocked.
 pub fn new_progress_spinner() -> ProgressBar {
-    ProgressBar::new_spinner()
+    let bar = hide_if_unattended(ProgressBar::new_spinner())
         .with_style(
             ProgressStyle::with_template("{spinner} {pos} {elapsed_precise} {wide_msg}")
                 .unwrap()
@@ -10,11 +88,13 @@ pub fn new_progress_spinner() -> ProgressBar {
                     "🌑", "🌒", "🌓", "🌔", "🌕", "🌖", "🌗", "🌘",
                 ]),
         )
-        .with_finish(ProgressFinish::AndLeave)
+        .with_finish(ProgressFinish::AndLeave);
+    bar.enable_steady_tick(std::time::Duration::from_millis(125));
+    bar
 }
 
 pub fn new_progress_bar() -> ProgressBar {
-    ProgressBar::no_length()
+    hide_if_unattended(ProgressBar::no_length())
         .with_style(
             ProgressStyle::with_template(
                 "{bytes} {elapsed_precise} [ {bytes_per_sec} ] [{wide_bar:.cyan/blue}] {percent}% ETA {eta_precise}",
@@ -24,3 +104,418 @@ pub fn new_progress_bar() -> ProgressBar {
         )
         .with_finish(ProgressFinish::AndLeave)
 }
+
+/// Like `new_progress_bar`, but with the message on its own line above the bar, for showing
+/// long values such as the path currently being read.
+pub fn new_two_line_progress_bar() -> ProgressBar {
+    hide_if_unattended(ProgressBar::no_length())
+        .with_style(
+            ProgressStyle::with_template(
+                "{wide_msg}\n{bytes} {elapsed_precise} [ {bytes_per_sec} ] [{wide_bar:.cyan/blue}] {percent}% ETA {eta_precise}",
+            )
+            .unwrap()
+            .progress_chars("=> "),
+        )
+        .with_finish(ProgressFinish::AndLeave)
+}
+
+/// Parses a progress fraction for use as a clap value parser, requiring it to be in [0.0, 1.0].
+pub fn parse_progress(value: &str) -> Result<f64, String> {
+    let progress = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if (0.0..=1.0).contains(&progress) {
+        Ok(progress)
+    } else {
+        Err(format!("{progress} is not between 0.0 and 1.0"))
+    }
+}
+
+/// Parses a `--since` value for use as a clap value parser, returning the Unix timestamp it refers
+/// to. Accepts a date such as "2024-01-31", taken as midnight UTC, a full RFC 3339 timestamp, or a
+/// duration before now such as "90m", "24h", "7d", or "2w".
+pub fn parse_since(value: &str) -> Result<i64, String> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|err| err.to_string())?
+        .as_secs();
+    parse_since_at(value, now as i64)
+}
+
+fn parse_since_at(value: &str, now: i64) -> Result<i64, String> {
+    if let Some(timestamp) = parse_timestamp(value) {
+        return Ok(timestamp);
+    }
+    let invalid = || format!("{value} is not a date, timestamp, or duration like 7d");
+    let unit = match value.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 60 * 60,
+        Some('d') => 24 * 60 * 60,
+        Some('w') => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let count = value[..value.len() - 1]
+        .parse::<u32>()
+        .map_err(|_| invalid())?;
+    Ok(now - i64::from(count) * unit)
+}
+
+/// Parses a date ("2024-01-31", taken as midnight UTC) or an RFC 3339 timestamp
+/// ("2024-01-31T12:00:00Z", optionally with fractional seconds or a UTC offset) into a Unix
+/// timestamp.
+pub fn parse_timestamp(value: &str) -> Option<i64> {
+    fn number(digits: &str) -> Option<i64> {
+        digits
+            .bytes()
+            .all(|byte| byte.is_ascii_digit())
+            .then(|| digits.parse().ok())
+            .flatten()
+    }
+
+    let date = value.get(..10)?;
+    let (year, month, day) = (
+        number(date.get(..4)?)?,
+        number(date.get(5..7)?)?,
+        number(date.get(8..10)?)?,
+    );
+    if date.as_bytes()[4] != b'-'
+        || date.as_bytes()[7] != b'-'
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+    {
+        return None;
+    }
+    let days = date::days_from_civil(year, month, day);
+    let rest = &value[10..];
+    if rest.is_empty() {
+        return Some(days * 86400);
+    }
+    let rest = rest.strip_prefix(['T', 't', ' '])?;
+    let (hour, minute, second) = (
+        number(rest.get(..2)?)?,
+        number(rest.get(3..5)?)?,
+        number(rest.get(6..8)?)?,
+    );
+    if rest.as_bytes()[2] != b':' || rest.as_bytes()[5] != b':' {
+        return None;
+    }
+    let mut offset = &rest[8..];
+    if let Some(fraction) = offset.strip_prefix('.') {
+        offset = fraction.trim_start_matches(|c: char| c.is_ascii_digit());
+    }
+    let offset = match offset {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match offset.get(..1)? {
+                "+" => 1,
+                "-" => -1,
+                _ => return None,
+            };
+            if offset.len() != 6 || offset.as_bytes()[3] != b':' {
+                return None;
+            }
+            sign * (number(&offset[1..3])? * 3600 + number(&offset[4..6])? * 60)
+        }
+    };
+    Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+/// Sends `tracing` output to stderr. By default only warnings are logged; each level of
+/// `verbosity` enables more detail.
+pub fn init_logging(verbosity: u8) {
+    let level = match verbosity {
+        0 => tracing::Level::WARN,
+        1 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(std::io::stderr)
+        .with_ansi(console::colors_enabled_stderr())
+        .without_time()
+        .init();
+}
+
+/// Formats `bytes` as a human-readable string using binary units, e.g. "1.50 GiB".
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{value:.2} {unit}")
+}
+
+/// Conversions between proleptic Gregorian dates and days since the Unix epoch; see
+/// https://howardhinnant.github.io/date_algorithms.html
+mod date {
+    pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+        let year = if month <= 2 { year - 1 } else { year };
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let doy = (153 * mp + 2) / 5 + day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    /// Returns the year, month and day.
+    pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        (yoe + era * 400 + i64::from(month <= 2), month, day)
+    }
+}
+
+/// Formats a Unix timestamp as a UTC date and time, e.g. "2024-01-31 12:00:00 UTC".
+pub fn format_timestamp(timestamp: i64) -> String {
+    let (year, month, day) = date::civil_from_days(timestamp.div_euclid(86400));
+    let seconds = timestamp.rem_euclid(86400);
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Turns a title into a dot-separated file name component, e.g. "Star Wars: Episode IV" becomes
+/// "Star.Wars.Episode.IV". Characters that are not allowed in file names on common filesystems are
+/// dropped, path separators become dots, repeated dots are collapsed, and leading or trailing dots
+/// are trimmed.
+pub fn sanitize_title(title: &str) -> String {
+    let mut sanitized = String::with_capacity(title.len());
+    for c in title.chars() {
+        let c = match c {
+            '/' | '\\' => '.',
+            c if c.is_whitespace() => '.',
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => continue,
+            c if c.is_control() => continue,
+            c => c,
+        };
+        if c == '.' && (sanitized.is_empty() || sanitized.ends_with('.')) {
+            continue;
+        }
+        sanitized.push(c);
+    }
+    while sanitized.ends_with('.') {
+        sanitized.pop();
+    }
+    sanitized
+}
+
+/// Returns true if `path` is `-`, which torrent-consuming subcommands treat as stdin.
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Reads the contents of `path`, or all of stdin if `path` is `-`.
+pub fn read_input(path: &Path) -> std::io::Result<Vec<u8>> {
+    if is_stdin(path) {
+        let mut bytes = vec![];
+        std::io::stdin().lock().read_to_end(&mut bytes)?;
+        Ok(bytes)
+    } else {
+        std::fs::read(path)
+    }
+}
+
+/// Returns the agent to use for all outbound HTTP requests, with the timeouts from config.toml.
+pub fn http_agent() -> &'static ureq::Agent {
+    static AGENT: OnceLock<ureq::Agent> = OnceLock::new();
+
+    AGENT.get_or_init(|| {
+        let http = &crate::config::config().http;
+        ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(http.timeout_secs)))
+            .timeout_connect(Some(Duration::from_secs(http.connect_timeout_secs)))
+            .build()
+            .new_agent()
+    })
+}
+
+/// Kinds of I/O errors that may go away if the operation is retried, e.g. because a server is
+/// still starting up.
+pub const TRANSIENT_IO_ERRORS: [std::io::ErrorKind; 5] = [
+    std::io::ErrorKind::ConnectionRefused,
+    std::io::ErrorKind::ConnectionReset,
+    std::io::ErrorKind::ConnectionAborted,
+    std::io::ErrorKind::TimedOut,
+    std::io::ErrorKind::Interrupted,
+];
+
+/// Returns the host component of `url`, e.g. "tracker.example.com" for
+/// "https://tracker.example.com:443/announce".
+pub fn url_host(url: &str) -> anyhow::Result<String> {
+    Ok(url::Url::parse(url)?
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("URL {url} has no hostname"))?
+        .to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_title_spaces() {
+        assert_eq!(sanitize_title("Cowboy Bebop"), "Cowboy.Bebop");
+        assert_eq!(sanitize_title("  Cowboy   Bebop "), "Cowboy.Bebop");
+    }
+
+    #[test]
+    fn sanitize_title_illegal_characters() {
+        assert_eq!(
+            sanitize_title("Star Wars: Episode IV - A New Hope"),
+            "Star.Wars.Episode.IV.-.A.New.Hope"
+        );
+        assert_eq!(sanitize_title("Face/Off"), "Face.Off");
+        assert_eq!(sanitize_title("What If...?"), "What.If");
+        assert_eq!(
+            sanitize_title("Who Framed Roger Rabbit?"),
+            "Who.Framed.Roger.Rabbit"
+        );
+        assert_eq!(
+            sanitize_title(r#"A "Quoted" <Title> | *"#),
+            "A.Quoted.Title"
+        );
+    }
+
+    #[test]
+    fn sanitize_title_trailing_dots() {
+        assert_eq!(sanitize_title("Mr. Robot"), "Mr.Robot");
+        assert_eq!(sanitize_title("Dr. Stone."), "Dr.Stone");
+        assert_eq!(
+            sanitize_title("...And Justice for All"),
+            "And.Justice.for.All"
+        );
+    }
+
+    #[test]
+    fn read_input_from_file() {
+        let tmp_dir = tempfile::tempdir().expect("failed to create temp dir");
+        let path = tmp_dir.path().join("test.torrent");
+        std::fs::write(&path, b"d4:infoe").expect("failed to create test file");
+        assert!(!is_stdin(&path));
+        assert_eq!(read_input(&path).unwrap(), b"d4:infoe");
+    }
+
+    #[test]
+    fn is_stdin_only_matches_dash() {
+        assert!(is_stdin(Path::new("-")));
+        assert!(!is_stdin(Path::new("./-")));
+        assert!(!is_stdin(Path::new("--")));
+    }
+
+    #[test]
+    fn format_bytes_zero() {
+        assert_eq!(format_bytes(0), "0 B");
+    }
+
+    #[test]
+    fn format_bytes_sub_kib() {
+        assert_eq!(format_bytes(1), "1 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+    }
+
+    #[test]
+    fn format_bytes_exact_powers() {
+        assert_eq!(format_bytes(1 << 10), "1.00 KiB");
+        assert_eq!(format_bytes(1 << 20), "1.00 MiB");
+        assert_eq!(format_bytes(1 << 30), "1.00 GiB");
+        assert_eq!(format_bytes(1 << 40), "1.00 TiB");
+    }
+
+    #[test]
+    fn format_bytes_fractional() {
+        assert_eq!(format_bytes(1536), "1.50 KiB");
+        assert_eq!(format_bytes(3 << 29), "1.50 GiB");
+    }
+
+    #[test]
+    fn format_bytes_multi_tib() {
+        assert_eq!(format_bytes(5 << 40), "5.00 TiB");
+        assert_eq!(format_bytes(1023 << 40), "1023.00 TiB");
+        assert_eq!(format_bytes(u64::MAX), "16.00 EiB");
+    }
+
+    #[test]
+    fn parse_progress_range() {
+        assert_eq!(parse_progress("0"), Ok(0.0));
+        assert_eq!(parse_progress("0.5"), Ok(0.5));
+        assert_eq!(parse_progress("1.0"), Ok(1.0));
+        assert!(parse_progress("1.5").is_err());
+        assert!(parse_progress("-0.1").is_err());
+        assert!(parse_progress("NaN").is_err());
+        assert!(parse_progress("done").is_err());
+    }
+
+    #[test]
+    fn date_conversions_round_trip() {
+        for days in [-719468, -1, 0, 19782, 2932896] {
+            let (year, month, day) = date::civil_from_days(days);
+            assert_eq!(date::days_from_civil(year, month, day), days);
+        }
+    }
+
+    #[test]
+    fn format_timestamp_epoch() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00 UTC");
+    }
+
+    #[test]
+    fn format_timestamp_leap_day() {
+        assert_eq!(format_timestamp(1709210096), "2024-02-29 12:34:56 UTC");
+    }
+
+    #[test]
+    fn format_timestamp_before_epoch() {
+        assert_eq!(format_timestamp(-1), "1969-12-31 23:59:59 UTC");
+    }
+
+    #[test]
+    fn parse_timestamp_formats() {
+        assert_eq!(parse_timestamp("1970-01-01"), Some(0));
+        assert_eq!(parse_timestamp("2024-02-29T12:34:56Z"), Some(1709210096));
+        assert_eq!(
+            parse_timestamp("2024-02-29T12:34:56.123456Z"),
+            Some(1709210096)
+        );
+        assert_eq!(
+            parse_timestamp("2024-02-29T14:34:56+02:00"),
+            Some(1709210096)
+        );
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59Z"), Some(-1));
+        assert_eq!(parse_timestamp("2024-13-01"), None);
+        assert_eq!(parse_timestamp("2024-01-01T12:00"), None);
+        assert_eq!(parse_timestamp("7d"), None);
+    }
+
+    #[test]
+    fn parse_since_durations_and_dates() {
+        let now = 1_000_000;
+        assert_eq!(parse_since_at("90s", now), Ok(now - 90));
+        assert_eq!(parse_since_at("24h", now), Ok(now - 24 * 3600));
+        assert_eq!(parse_since_at("7d", now), Ok(now - 7 * 86400));
+        assert_eq!(parse_since_at("1w", now), Ok(now - 7 * 86400));
+        assert_eq!(parse_since_at("2024-01-01", now), Ok(1704067200));
+        assert!(parse_since_at("7", now).is_err());
+        assert!(parse_since_at("d", now).is_err());
+        assert!(parse_since_at("-7d", now).is_err());
+        assert!(parse_since_at("yesterday", now).is_err());
+    }
+}